@@ -0,0 +1,365 @@
+//! curl-like command-line HTTP/2 client built on `httpbis::Client`.
+//!
+//! ```text
+//! httpbis-cli -i https://localhost:8443/
+//! httpbis-cli -X PUT -H 'content-type: application/json' -d @body.json http://localhost:8080/api
+//! httpbis-cli --unix-socket /tmp/server.sock --stats http://localhost/status
+//! ```
+//!
+//! Plain `http` URLs are requested over HTTP/2 with prior knowledge (h2c),
+//! there is no HTTP/1.1 upgrade.
+
+extern crate bytes;
+extern crate futures;
+extern crate env_logger;
+extern crate clap;
+extern crate url;
+extern crate tls_api;
+extern crate tls_api_openssl;
+extern crate httpbis;
+
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+
+use futures::Future;
+use futures::Stream;
+
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+
+use url::Url;
+
+use tls_api::Certificate;
+use tls_api::TlsConnector as tls_api_TlsConnector;
+use tls_api::TlsConnectorBuilder;
+use tls_api_openssl::TlsConnector;
+
+use httpbis::Client;
+use httpbis::ClientBuilder;
+use httpbis::ClientTlsOption;
+use httpbis::Header;
+use httpbis::Headers;
+use httpbis::HttpPartStream;
+use httpbis::Service;
+use httpbis::TextFrameDump;
+use httpbis::stream_part::HttpStreamPartContent;
+
+
+type Result<T> = ::std::result::Result<T, Box<StdError>>;
+
+/// Exit code of curl with `--fail` on HTTP error status
+const EXIT_HTTP_ERROR: i32 = 22;
+
+fn app() -> App<'static, 'static> {
+    App::new("httpbis-cli")
+        .about("HTTP/2 client")
+        .arg(Arg::with_name("url")
+            .required(true)
+            .help("http or https URL"))
+        .arg(Arg::with_name("request")
+            .short("X")
+            .long("request")
+            .takes_value(true)
+            .help("Request method, GET or POST with --data by default"))
+        .arg(Arg::with_name("header")
+            .short("H")
+            .long("header")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Request header `name: value`, pseudo-headers like `:authority` replace defaults"))
+        .arg(Arg::with_name("data")
+            .short("d")
+            .long("data")
+            .takes_value(true)
+            .help("Request body, `@file` to read from file, `@-` to read from stdin"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .takes_value(true)
+            .help("Write response body to file instead of stdout"))
+        .arg(Arg::with_name("include")
+            .short("i")
+            .long("include")
+            .help("Print response headers and trailers"))
+        .arg(Arg::with_name("fail")
+            .short("f")
+            .long("fail")
+            .help("Exit with code 22 if response status is 400 or greater"))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .help("Print every sent and received frame to stderr"))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Print request time and connection state to stderr"))
+        .arg(Arg::with_name("cacert")
+            .long("cacert")
+            .takes_value(true)
+            .help("DER-encoded CA certificate to verify the server with"))
+        .arg(Arg::with_name("unix-socket")
+            .long("unix-socket")
+            .takes_value(true)
+            .help("Connect to unix socket instead of URL host"))
+        .arg(Arg::with_name("connect-timeout")
+            .long("connect-timeout")
+            .takes_value(true)
+            .help("Connection timeout in seconds"))
+}
+
+fn tls_connector(cacert: Option<&str>) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder()?;
+    builder.set_alpn_protocols(&[b"h2"])?;
+    if let Some(cacert) = cacert {
+        let mut der = Vec::new();
+        File::open(cacert)?.read_to_end(&mut der)?;
+        builder.add_root_certificate(Certificate::from_der(der))?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(unix)]
+fn set_unix_addr(builder: &mut ClientBuilder<TlsConnector>, path: &str) -> Result<()> {
+    Ok(builder.set_unix_addr(path)?)
+}
+
+#[cfg(not(unix))]
+fn set_unix_addr(_builder: &mut ClientBuilder<TlsConnector>, _path: &str) -> Result<()> {
+    Err("unix sockets are not supported on this platform".into())
+}
+
+fn read_body(data: &str) -> Result<Bytes> {
+    let mut body = Vec::new();
+    if data == "@-" {
+        io::stdin().read_to_end(&mut body)?;
+    } else if data.starts_with('@') {
+        File::open(&data[1..])?.read_to_end(&mut body)?;
+    } else {
+        body.extend_from_slice(data.as_bytes());
+    }
+    Ok(Bytes::from(body))
+}
+
+fn parse_header(header: &str) -> Result<Header> {
+    // name of pseudo-header starts with colon
+    let colon = header.char_indices().skip(1).find(|&(_, c)| c == ':').map(|(p, _)| p)
+        .ok_or_else(|| format!("header must be `name: value`: {}", header))?;
+    let name = header[..colon].trim().to_lowercase();
+    let value = header[colon + 1..].trim().to_owned();
+    Ok(Header::new(name, value))
+}
+
+/// Add header from command line, pseudo-header replaces the default one
+fn add_header(headers: &mut Headers, header: Header) {
+    if header.name().starts_with(b":") {
+        let name = header.name().to_vec();
+        headers.0.retain(|h| h.name() != &name[..]);
+        headers.0.insert(0, header);
+    } else {
+        headers.0.push(header);
+    }
+}
+
+/// Pseudo-headers for request of URL
+fn url_headers(method: &str, url: &Url) -> Result<Headers> {
+    let host = url.host_str().ok_or("URL must have host")?;
+    // default port is not included
+    let authority = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+
+    Ok(Headers(vec![
+        Header::new(":method", method.to_owned()),
+        Header::new(":path", path),
+        Header::new(":authority", authority),
+        Header::new(":scheme", url.scheme().to_owned()),
+    ]))
+}
+
+fn write_headers(out: &mut Write, headers: &Headers) -> io::Result<()> {
+    for header in &headers.0 {
+        out.write_all(header.name())?;
+        out.write_all(b": ")?;
+        out.write_all(header.value())?;
+        out.write_all(b"\n")?;
+    }
+    out.write_all(b"\n")
+}
+
+fn new_client(m: &ArgMatches, url: &Url, host: &str) -> Result<Client> {
+    let mut builder = ClientBuilder::<TlsConnector>::new();
+
+    match m.value_of("unix-socket") {
+        Some(path) => set_unix_addr(&mut builder, path)?,
+        None => {
+            let port = url.port_or_known_default().ok_or("URL must have port")?;
+            builder.set_addr((host, port))?;
+        }
+    }
+
+    if url.scheme() == "https" {
+        let connector = tls_connector(m.value_of("cacert"))?;
+        builder.tls = ClientTlsOption::Tls(host.to_owned(), Arc::new(connector));
+    }
+
+    if let Some(timeout) = m.value_of("connect-timeout") {
+        builder.conf.connection_timeout = Some(Duration::from_secs(timeout.parse()?));
+    }
+
+    if m.is_present("verbose") {
+        builder.set_frame_tracer(Arc::new(TextFrameDump::new(io::stderr())));
+    }
+
+    Ok(builder.build()?)
+}
+
+fn run(m: &ArgMatches) -> Result<i32> {
+    let url = Url::parse(m.value_of("url").unwrap())?;
+    match url.scheme() {
+        "http" | "https" => {}
+        scheme => return Err(format!("unsupported URL scheme: {}", scheme).into()),
+    }
+    let host = url.host_str().ok_or("URL must have host")?.to_owned();
+
+    let body = match m.value_of("data") {
+        Some(data) => Some(read_body(data)?),
+        None => None,
+    };
+    let method = m.value_of("request").unwrap_or(if body.is_some() { "POST" } else { "GET" });
+
+    let mut headers = url_headers(method, &url)?;
+    for header in m.values_of("header").into_iter().flat_map(|v| v) {
+        add_header(&mut headers, parse_header(header)?);
+    }
+
+    let client = new_client(m, &url, &host)?;
+
+    let mut out: Box<Write> = match m.value_of("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    let start = Instant::now();
+
+    let req = match body {
+        Some(body) => HttpPartStream::once_bytes(body),
+        None => HttpPartStream::empty(),
+    };
+    let resp = client.start_request(headers, req);
+
+    let mut status = None;
+    for part in resp.into_stream().wait() {
+        match part? {
+            HttpStreamPartContent::Headers(headers) => {
+                // last of informational (1xx) or final headers, trailers have no status
+                if let Some(s) = headers.get_opt_parse(":status") {
+                    status = Some(s);
+                }
+                if m.is_present("include") {
+                    write_headers(&mut *out, &headers)?;
+                }
+            }
+            HttpStreamPartContent::Data(data) => out.write_all(&data)?,
+        }
+    }
+    out.flush()?;
+
+    if m.is_present("stats") {
+        let elapsed = start.elapsed();
+        let state = client.dump_state().wait()?;
+        let mut stderr = io::stderr();
+        writeln!(stderr, "time: {}.{:03}s", elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000)?;
+        writeln!(stderr, "connection: {:#?}", state)?;
+    }
+
+    let status: u32 = status.ok_or("response has no :status")?;
+    Ok(if m.is_present("fail") && status >= 400 { EXIT_HTTP_ERROR } else { 0 })
+}
+
+fn main() {
+    env_logger::init();
+
+    let matches = app().get_matches();
+
+    match run(&matches) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            writeln!(io::stderr(), "httpbis-cli: {}", e).ok();
+            process::exit(1);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name, value)
+    }
+
+    #[test]
+    fn parse_header_name_value() {
+        assert_eq!(header("content-type", "text/plain"), parse_header("Content-Type: text/plain").unwrap());
+        assert_eq!(header("x-a", "b:c"), parse_header("x-a:b:c").unwrap());
+        assert_eq!(header("x-empty", ""), parse_header("x-empty:").unwrap());
+        assert!(parse_header("no-colon").is_err());
+    }
+
+    #[test]
+    fn parse_pseudo_header() {
+        assert_eq!(header(":authority", "example.com:8443"), parse_header(":authority: example.com:8443").unwrap());
+        assert_eq!(header(":path", "/a"), parse_header(":path:/a").unwrap());
+        assert!(parse_header(":path").is_err());
+    }
+
+    fn url_headers_str(url: &str) -> Headers {
+        url_headers("GET", &Url::parse(url).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn url_to_pseudo_headers() {
+        let headers = url_headers_str("http://localhost:8080/a/b?x=1&y");
+        assert_eq!("GET", headers.get(":method"));
+        assert_eq!("/a/b?x=1&y", headers.get(":path"));
+        assert_eq!("localhost:8080", headers.get(":authority"));
+        assert_eq!("http", headers.get(":scheme"));
+
+        let headers = url_headers_str("https://example.com");
+        assert_eq!("/", headers.get(":path"));
+        assert_eq!("example.com", headers.get(":authority"));
+        assert_eq!("https", headers.get(":scheme"));
+
+        assert_eq!("example.com", url_headers_str("https://example.com:443/").get(":authority"));
+        assert_eq!("[::1]:8080", url_headers_str("http://[::1]:8080/").get(":authority"));
+    }
+
+    #[test]
+    fn pseudo_header_replaces_default() {
+        let mut headers = url_headers_str("http://localhost/a");
+        add_header(&mut headers, header(":authority", "example.com"));
+        add_header(&mut headers, header("x-a", "1"));
+        assert_eq!(vec!["example.com"], headers.0.iter()
+            .filter(|h| h.name() == b":authority")
+            .map(|h| ::std::str::from_utf8(h.value()).unwrap())
+            .collect::<Vec<_>>());
+        assert_eq!(b":authority", headers.0[0].name());
+        assert_eq!("1", headers.get("x-a"));
+    }
+}
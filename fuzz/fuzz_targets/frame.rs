@@ -0,0 +1,28 @@
+//! Parse frames from bytes, and check that valid frames survive
+//! serialization round trip.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate httpbis;
+
+use httpbis::solicit::connection::HttpFrame;
+use httpbis::solicit::frame::FrameIR;
+use httpbis::solicit::frame::RawFrame;
+use httpbis::solicit::frame::FRAME_HEADER_LEN;
+
+fuzz_target!(|data: &[u8]| {
+    let mut data = data;
+    while let Some(raw_frame) = RawFrame::parse(data) {
+        data = &data[FRAME_HEADER_LEN + raw_frame.header().length as usize..];
+
+        let frame = match HttpFrame::from_raw(&raw_frame) {
+            Ok(frame) => frame,
+            Err(_) => continue,
+        };
+
+        let serialized = frame.clone().serialize_into_vec();
+        let raw_frame = RawFrame::parse(serialized).expect("parse serialized");
+        let reparsed = HttpFrame::from_raw(&raw_frame).expect("parse serialized");
+        assert_eq!(frame, reparsed);
+    }
+});
@@ -0,0 +1,24 @@
+//! Decode HPACK header blocks.
+//!
+//! First byte is the length of the first block, the rest is the second block,
+//! so decoding of the second block uses dynamic table filled by the first one.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate httpbis;
+
+use httpbis::hpack::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    if data.is_empty() {
+        return;
+    }
+
+    let split = ::std::cmp::min(data[0] as usize, data.len() - 1) + 1;
+    let (first, second) = (&data[1..split], &data[split..]);
+
+    let mut decoder = Decoder::new();
+    if decoder.decode(first).is_ok() {
+        let _ = decoder.decode(second);
+    }
+});
@@ -0,0 +1,9 @@
+//! Feed arbitrary bytes after connection preface into a server connection.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate httpbis_fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    httpbis_fuzz::run_server(data);
+});
@@ -0,0 +1,212 @@
+//! Structure-aware server fuzzer.
+//!
+//! Input is interpreted as a sequence of commands, each producing a mostly
+//! well-formed frame on a small set of streams, so the fuzzer spends its time
+//! in the connection state machine rather than in frame parsing.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate bytes;
+extern crate httpbis;
+extern crate httpbis_fuzz;
+
+use bytes::Bytes;
+
+use httpbis::ErrorCode;
+use httpbis::hpack::Encoder;
+use httpbis::solicit::StreamId;
+use httpbis::solicit::frame::FrameIR;
+use httpbis::solicit::frame::FrameHeader;
+use httpbis::solicit::frame::pack_header;
+use httpbis::solicit::frame::DataFrame;
+use httpbis::solicit::frame::DataFlag;
+use httpbis::solicit::frame::HeadersFrame;
+use httpbis::solicit::frame::HeadersFlag;
+use httpbis::solicit::frame::ContinuationFrame;
+use httpbis::solicit::frame::continuation::ContinuationFlag;
+use httpbis::solicit::frame::PriorityFrame;
+use httpbis::solicit::frame::RstStreamFrame;
+use httpbis::solicit::frame::SettingsFrame;
+use httpbis::solicit::frame::HttpSetting;
+use httpbis::solicit::frame::PingFrame;
+use httpbis::solicit::frame::GoawayFrame;
+use httpbis::solicit::frame::WindowUpdateFrame;
+
+
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&first, rem) = self.data.split_first()?;
+        self.data = rem;
+        Some(first)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        Some(self.u8()? & 1 != 0)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut r = 0;
+        for _ in 0..4 {
+            r = (r << 8) | self.u8()? as u32;
+        }
+        Some(r)
+    }
+
+    /// Stream ids mostly from a small set to hit existing streams
+    fn stream_id(&mut self) -> Option<StreamId> {
+        let b = self.u8()?;
+        Some(match b {
+            0xff => self.u32()? & 0x7fffffff,
+            b => (b % 8) as StreamId,
+        })
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = std::cmp::min(self.u8()? as usize, self.data.len());
+        let (r, rem) = self.data.split_at(len);
+        self.data = rem;
+        Some(r)
+    }
+
+    /// Small or large window increments or sizes
+    fn size(&mut self) -> Option<u32> {
+        Some(match self.u8()? % 4 {
+            0 => 0,
+            1 => self.u8()? as u32,
+            2 => 65535,
+            _ => self.u32()?,
+        })
+    }
+}
+
+fn header_block(input: &mut Input, encoder: &mut Encoder) -> Option<Vec<u8>> {
+    let method: &[u8] = match input.u8()? % 3 {
+        0 => b"GET",
+        1 => b"POST",
+        _ => b"PUT",
+    };
+    let path = input.bytes()?;
+
+    let mut headers: Vec<(&[u8], &[u8])> = vec![
+        (&b":method"[..], method),
+        (&b":path"[..], path),
+        (&b":scheme"[..], &b"http"[..]),
+        (&b":authority"[..], &b"localhost"[..]),
+    ];
+
+    // Drop or duplicate some pseudo-headers
+    match input.u8()? % 8 {
+        0 => { headers.remove(0); }
+        1 => { headers.remove(1); }
+        2 => { let h = headers[1]; headers.push(h); }
+        3 => { headers.insert(0, (&b"x-regular"[..], &b"before-pseudo"[..])); }
+        _ => {}
+    }
+
+    Some(encoder.encode(headers))
+}
+
+fn next_frame(input: &mut Input, encoder: &mut Encoder) -> Option<Vec<u8>> {
+    Some(match input.u8()? % 12 {
+        0 | 1 => {
+            let stream_id = input.stream_id()?;
+            let fragment = header_block(input, encoder)?;
+            let mut frame = HeadersFrame::new(fragment, stream_id);
+            if input.bool()? {
+                frame.set_flag(HeadersFlag::EndStream);
+            }
+            if input.bool()? {
+                frame.set_flag(HeadersFlag::EndHeaders);
+            }
+            frame.serialize_into_vec()
+        }
+        2 => {
+            let stream_id = input.stream_id()?;
+            let fragment = if input.bool()? {
+                header_block(input, encoder)?
+            } else {
+                input.bytes()?.to_vec()
+            };
+            let mut frame = ContinuationFrame::new(fragment, stream_id);
+            if input.bool()? {
+                frame.set_flag(ContinuationFlag::EndHeaders);
+            }
+            frame.serialize_into_vec()
+        }
+        3 | 4 => {
+            let stream_id = input.stream_id()?;
+            let mut frame = DataFrame::with_data(stream_id, Bytes::from(input.bytes()?));
+            if input.bool()? {
+                frame.set_flag(DataFlag::EndStream);
+            }
+            frame.serialize_into_vec()
+        }
+        5 => {
+            let stream_id = input.stream_id()?;
+            let error_code = input.u32()?;
+            RstStreamFrame::new(stream_id, ErrorCode::from(error_code)).serialize_into_vec()
+        }
+        6 => {
+            if input.bool()? {
+                SettingsFrame::new_ack().serialize_into_vec()
+            } else {
+                let id = (input.u8()? % 8) as u16;
+                let value = input.size()?;
+                let settings = HttpSetting::from_id(id, value).into_iter().collect();
+                SettingsFrame::from_settings(settings).serialize_into_vec()
+            }
+        }
+        7 => {
+            let data = input.u32()? as u64;
+            if input.bool()? {
+                PingFrame::new_ack(data).serialize_into_vec()
+            } else {
+                PingFrame::with_data(data).serialize_into_vec()
+            }
+        }
+        8 => {
+            let last_stream_id = input.stream_id()?;
+            let error_code = input.u32()?;
+            GoawayFrame::new(last_stream_id, ErrorCode::from(error_code)).serialize_into_vec()
+        }
+        9 => {
+            let stream_id = input.stream_id()?;
+            let increment = input.size()?;
+            WindowUpdateFrame::for_stream(stream_id, increment).serialize_into_vec()
+        }
+        10 => {
+            let stream_id = input.stream_id()?;
+            let stream_dep = input.stream_id()?;
+            let exclusive = input.bool()?;
+            let weight = input.u8()?;
+            PriorityFrame::new(stream_id, stream_dep, exclusive, weight).serialize_into_vec()
+        }
+        _ => {
+            // Anything else, including PUSH_PROMISE and unknown frame types
+            let frame_type = input.u8()?;
+            let flags = input.u8()?;
+            let stream_id = input.stream_id()?;
+            let payload = input.bytes()?;
+            let header = FrameHeader::new(payload.len() as u32, frame_type, flags, stream_id);
+            let mut frame = pack_header(&header).to_vec();
+            frame.extend_from_slice(payload);
+            frame
+        }
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input { data: data };
+    let mut encoder = Encoder::new();
+
+    let mut frames = Vec::new();
+    while let Some(frame) = next_frame(&mut input, &mut encoder) {
+        frames.extend(frame);
+    }
+
+    httpbis_fuzz::run_server(&frames);
+});
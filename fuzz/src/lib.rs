@@ -0,0 +1,124 @@
+//! Shared code of fuzz targets.
+//!
+//! `run_server` feeds bytes into a server connection over an in-memory socket:
+//! reads are served from the input, writes are discarded.
+//! Server must never panic, whatever it reads.
+
+extern crate bytes;
+extern crate futures;
+extern crate httpbis;
+extern crate tls_api_stub;
+extern crate tokio_core;
+extern crate tokio_io;
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures::Async;
+use futures::Poll;
+
+use tokio_core::reactor::Core;
+
+use tokio_io::AsyncRead;
+use tokio_io::AsyncWrite;
+
+use httpbis::ConnectionInfo;
+use httpbis::CpuPoolOption;
+use httpbis::Headers;
+use httpbis::HttpPartStream;
+use httpbis::Response;
+use httpbis::ServerConf;
+use httpbis::ServerTlsOption;
+use httpbis::Service;
+use httpbis::server_conn::ServerConnection;
+use httpbis::socket::AnySocketAddr;
+use httpbis::socket::StreamItem;
+use httpbis::testing::PREFACE;
+
+
+/// Socket which reads given bytes and then EOF, and discards everything written
+#[derive(Debug)]
+struct InputSocket {
+    input: io::Cursor<Vec<u8>>,
+}
+
+impl io::Read for InputSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(&mut self.input, buf)
+    }
+}
+
+impl io::Write for InputSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for InputSocket {}
+
+impl AsyncWrite for InputSocket {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl StreamItem for InputSocket {
+    fn is_tcp(&self) -> bool {
+        false
+    }
+
+    fn set_nodelay(&self, _no_delay: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// Echo request body for POST, respond with a few data frames otherwise
+struct FuzzService;
+
+impl Service for FuzzService {
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        match headers.get_opt(":method") {
+            Some("POST") => Response::headers_and_stream(Headers::ok_200(), req),
+            _ => Response::headers_and_bytes(Headers::ok_200(), vec![b'x'; 40000]),
+        }
+    }
+}
+
+
+/// Run server connection reading given bytes after client preface
+pub fn run_server(frames: &[u8]) {
+    let mut input = PREFACE.to_vec();
+    input.extend_from_slice(frames);
+
+    let mut core = Core::new().expect("core");
+
+    let addr = AnySocketAddr::Inet(SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 0));
+    let conn_info = ConnectionInfo {
+        conn_id: 0,
+        peer_addr: addr.clone(),
+        local_addr: addr,
+        tls: false,
+        sni_host_name: None,
+        alpn_protocol: None,
+    };
+
+    let no_tls: ServerTlsOption<tls_api_stub::TlsAcceptor> = ServerTlsOption::Plain;
+    let (_conn, future) = ServerConnection::new_with_conn_info(
+        &core.handle(),
+        Box::new(InputSocket { input: io::Cursor::new(input) }),
+        conn_info,
+        no_tls,
+        CpuPoolOption::SingleThread,
+        ServerConf::new(),
+        Arc::new(FuzzService));
+
+    // Connection errors are expected, panics are not
+    let _ = core.run(future);
+}
@@ -0,0 +1,391 @@
+//! h2load-style load generator built on `httpbis::Client`.
+//!
+//! ```text
+//! httpbis-load -c 4 -m 16 -d 10 http://localhost:8080/
+//! httpbis-load --rate 1000 --body-size 4096 -d 30 https://localhost:8443/upload
+//! httpbis-load --in-process --response-size 65536 -c 2 -m 100 -d 5
+//! ```
+//!
+//! By default each of `connections * streams` workers sends the next request
+//! after the previous one completes (closed loop). With `--rate` requests
+//! are sent on schedule regardless of outstanding responses (open loop),
+//! and latency is measured from the scheduled time, so server stalls
+//! are not hidden by the generator waiting for them.
+
+extern crate bytes;
+extern crate futures;
+extern crate futures_cpupool;
+extern crate env_logger;
+extern crate clap;
+extern crate url;
+extern crate tls_api;
+extern crate tls_api_openssl;
+extern crate httpbis;
+
+mod stats;
+
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+
+use futures::future;
+use futures::future::Future;
+use futures::future::Loop;
+use futures::future::loop_fn;
+
+use futures_cpupool::CpuPool;
+
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+
+use url::Url;
+
+use tls_api::Certificate;
+use tls_api::TlsConnector as tls_api_TlsConnector;
+use tls_api::TlsConnectorBuilder;
+use tls_api_openssl::TlsConnector;
+
+use httpbis::Client;
+use httpbis::ClientBuilder;
+use httpbis::ClientTlsOption;
+use httpbis::Header;
+use httpbis::Headers;
+use httpbis::HttpPartStream;
+use httpbis::MetricsRegistry;
+use httpbis::Response;
+use httpbis::Server;
+use httpbis::ServerBuilder;
+use httpbis::Service;
+use httpbis::message::SimpleHttpMessage;
+
+use stats::Stats;
+
+
+type Result<T> = ::std::result::Result<T, Box<StdError>>;
+
+/// Name of in-process server address
+const IN_PROCESS_ADDR: &str = "httpbis-load";
+
+/// How long to wait for outstanding responses after the end of the test
+const DRAIN_TIMEOUT_SECS: u64 = 10;
+
+fn app() -> App<'static, 'static> {
+    App::new("httpbis-load")
+        .about("HTTP/2 load generator")
+        .arg(Arg::with_name("url")
+            .required_unless("in-process")
+            .help("http or https URL"))
+        .arg(Arg::with_name("in-process")
+            .long("in-process")
+            .help("Start a server in this process and connect to it without sockets"))
+        .arg(Arg::with_name("response-size")
+            .long("response-size")
+            .takes_value(true)
+            .default_value("0")
+            .help("Response body size of in-process server"))
+        .arg(Arg::with_name("connections")
+            .short("c")
+            .long("connections")
+            .takes_value(true)
+            .default_value("1")
+            .help("Number of connections"))
+        .arg(Arg::with_name("streams")
+            .short("m")
+            .long("streams")
+            .takes_value(true)
+            .default_value("1")
+            .help("Concurrent streams per connection in closed loop mode"))
+        .arg(Arg::with_name("rate")
+            .short("r")
+            .long("rate")
+            .takes_value(true)
+            .help("Requests per second over all connections, closed loop if not specified"))
+        .arg(Arg::with_name("duration")
+            .short("d")
+            .long("duration")
+            .takes_value(true)
+            .default_value("10")
+            .help("Test duration in seconds"))
+        .arg(Arg::with_name("method")
+            .short("X")
+            .long("method")
+            .takes_value(true)
+            .help("Request method, GET or POST with --body-size by default"))
+        .arg(Arg::with_name("body-size")
+            .short("b")
+            .long("body-size")
+            .takes_value(true)
+            .default_value("0")
+            .help("Request body size"))
+        .arg(Arg::with_name("header")
+            .short("H")
+            .long("header")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Request header `name: value`"))
+        .arg(Arg::with_name("cacert")
+            .long("cacert")
+            .takes_value(true)
+            .help("DER-encoded CA certificate to verify the server with"))
+}
+
+/// What to request
+struct Target {
+    headers: Headers,
+    body: Bytes,
+}
+
+impl Target {
+    fn request(&self, client: &Client) -> Box<Future<Item=SimpleHttpMessage, Error=httpbis::Error> + Send> {
+        let body = if self.body.is_empty() {
+            HttpPartStream::empty()
+        } else {
+            HttpPartStream::once_bytes(self.body.clone())
+        };
+        Box::new(client.start_request(self.headers.clone(), body).collect())
+    }
+}
+
+fn parse_header(header: &str) -> Result<Header> {
+    let colon = header.find(':').ok_or_else(|| format!("header must be `name: value`: {}", header))?;
+    Ok(Header::new(header[..colon].trim().to_lowercase(), header[colon + 1..].trim().to_owned()))
+}
+
+fn tls_connector(cacert: Option<&str>) -> Result<TlsConnector> {
+    let mut builder = TlsConnector::builder()?;
+    builder.set_alpn_protocols(&[b"h2"])?;
+    if let Some(cacert) = cacert {
+        let mut der = Vec::new();
+        File::open(cacert)?.read_to_end(&mut der)?;
+        builder.add_root_certificate(Certificate::from_der(der))?;
+    }
+    Ok(builder.build()?)
+}
+
+fn start_in_process_server(response_size: usize) -> Result<Server> {
+    let body = Bytes::from(vec![b'x'; response_size]);
+    let mut server = ServerBuilder::new_plain();
+    server.set_memory_addr(IN_PROCESS_ADDR)?;
+    server.service.set_service_fn("/", move |_headers, _req| {
+        Response::headers_and_bytes(Headers::ok_200(), body.clone())
+    });
+    Ok(server.build()?)
+}
+
+/// Create client for each connection
+fn new_clients(m: &ArgMatches, url: Option<&Url>, metrics: Arc<MetricsRegistry>) -> Result<Vec<Arc<Client>>> {
+    let connections: usize = m.value_of("connections").unwrap().parse()?;
+
+    let tls = match url {
+        Some(url) if url.scheme() == "https" => {
+            let host = url.host_str().ok_or("URL must have host")?;
+            let connector = tls_connector(m.value_of("cacert"))?;
+            ClientTlsOption::Tls(host.to_owned(), Arc::new(connector))
+        }
+        _ => ClientTlsOption::Plain,
+    };
+
+    let mut clients = Vec::new();
+    for _ in 0..connections {
+        let mut builder = ClientBuilder::<TlsConnector>::new();
+        match url {
+            Some(url) => {
+                let host = url.host_str().ok_or("URL must have host")?;
+                let port = url.port_or_known_default().ok_or("URL must have port")?;
+                builder.set_addr((host, port))?;
+            }
+            None => builder.set_memory_addr(IN_PROCESS_ADDR)?,
+        }
+        builder.tls = tls.clone();
+        builder.set_metrics(metrics.clone());
+        clients.push(Arc::new(builder.build()?));
+    }
+
+    // Do not count connection establishment as latency of first requests
+    for client in &clients {
+        client.wait_for_connect().wait()?;
+    }
+
+    Ok(clients)
+}
+
+fn closed_loop(
+    target: Arc<Target>,
+    clients: &[Arc<Client>],
+    streams: usize,
+    stats: Arc<Mutex<Stats>>,
+    deadline: Instant)
+{
+    let pool = CpuPool::new_num_cpus();
+
+    let mut workers = Vec::new();
+    for client in clients {
+        for _ in 0..streams {
+            let target = target.clone();
+            let client = client.clone();
+            let stats = stats.clone();
+            let worker = loop_fn((), move |()| -> Box<Future<Item=Loop<(), ()>, Error=()> + Send> {
+                if Instant::now() >= deadline {
+                    return Box::new(future::ok(Loop::Break(())));
+                }
+                let start = Instant::now();
+                let stats = stats.clone();
+                Box::new(target.request(&client).then(move |r| {
+                    stats.lock().unwrap().record(start, r);
+                    Ok(Loop::Continue(()))
+                }))
+            });
+            workers.push(pool.spawn(worker));
+        }
+    }
+
+    future::join_all(workers).wait().ok();
+}
+
+fn open_loop(
+    target: Arc<Target>,
+    clients: &[Arc<Client>],
+    rate: f64,
+    stats: Arc<Mutex<Stats>>,
+    deadline: Instant)
+{
+    let pool = CpuPool::new_num_cpus();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    let mut sent = 0u64;
+    loop {
+        // computed in floating point, so rates above 1e9 and tiny rates are scheduled correctly
+        let offset = sent as f64 / rate;
+        let offset = Duration::new(offset as u64, (offset.fract() * 1e9) as u32);
+        let scheduled = match start.checked_add(offset) {
+            Some(scheduled) if scheduled < deadline => scheduled,
+            _ => break,
+        };
+        let now = Instant::now();
+        if scheduled > now {
+            thread::sleep(scheduled - now);
+        }
+
+        let client = &clients[sent as usize % clients.len()];
+        let stats = stats.clone();
+        let in_flight_copy = in_flight.clone();
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        pool.spawn(target.request(client).then(move |r| {
+            stats.lock().unwrap().record(scheduled, r);
+            in_flight_copy.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, ()>(())
+        })).forget();
+
+        sent += 1;
+    }
+
+    let drain_deadline = Instant::now() + Duration::from_secs(DRAIN_TIMEOUT_SECS);
+    while in_flight.load(Ordering::SeqCst) != 0 && Instant::now() < drain_deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn run(m: &ArgMatches) -> Result<()> {
+    let _server;
+    let url = if m.is_present("in-process") {
+        let response_size = m.value_of("response-size").unwrap().parse()?;
+        _server = start_in_process_server(response_size)?;
+        None
+    } else {
+        Some(Url::parse(m.value_of("url").unwrap())?)
+    };
+
+    let (scheme, authority, path) = match url {
+        Some(ref url) => {
+            let host = url.host_str().ok_or("URL must have host")?;
+            let authority = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_owned(),
+            };
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_owned(),
+            };
+            (url.scheme().to_owned(), authority, path)
+        }
+        None => ("http".to_owned(), "localhost".to_owned(), "/".to_owned()),
+    };
+
+    let body_size: usize = m.value_of("body-size").unwrap().parse()?;
+    let method = m.value_of("method").unwrap_or(if body_size != 0 { "POST" } else { "GET" });
+
+    let mut headers = Headers(vec![
+        Header::new(":method", method.to_owned()),
+        Header::new(":path", path),
+        Header::new(":authority", authority),
+        Header::new(":scheme", scheme),
+    ]);
+    for header in m.values_of("header").into_iter().flat_map(|v| v) {
+        headers.0.push(parse_header(header)?);
+    }
+
+    let target = Arc::new(Target {
+        headers: headers,
+        body: Bytes::from(vec![b'x'; body_size]),
+    });
+
+    let rate = match m.value_of("rate") {
+        Some(rate) => {
+            let rate: f64 = rate.parse()?;
+            // negated to reject NaN too
+            if !(rate > 0.0) || rate.is_infinite() {
+                return Err("--rate must be a positive number".into());
+            }
+            Some(rate)
+        }
+        None => None,
+    };
+
+    let metrics = Arc::new(MetricsRegistry::new());
+    let clients = new_clients(m, url.as_ref(), metrics.clone())?;
+
+    let streams: usize = m.value_of("streams").unwrap().parse()?;
+    let duration = Duration::from_secs(m.value_of("duration").unwrap().parse()?);
+
+    let stats = Arc::new(Mutex::new(Stats::default()));
+
+    let start = Instant::now();
+    let deadline = start + duration;
+    match rate {
+        Some(rate) => open_loop(target, &clients, rate, stats.clone(), deadline),
+        None => closed_loop(target, &clients, streams, stats.clone(), deadline),
+    }
+    let elapsed = start.elapsed();
+
+    let window_stalls = metrics.snapshot().window_stalls;
+    let report = stats.lock().unwrap().report(elapsed, window_stalls);
+    print!("{}", report);
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let matches = app().get_matches();
+
+    if let Err(e) = run(&matches) {
+        writeln!(io::stderr(), "httpbis-load: {}", e).ok();
+        process::exit(1);
+    }
+}
@@ -0,0 +1,197 @@
+//! Results collected by load generator
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use httpbis;
+use httpbis::message::SimpleHttpMessage;
+
+
+fn micros(d: Duration) -> u64 {
+    d.as_secs() * 1_000_000 + (d.subsec_nanos() / 1000) as u64
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+fn format_micros(us: u64) -> String {
+    if us < 1000 {
+        format!("{}us", us)
+    } else if us < 1_000_000 {
+        format!("{:.2}ms", us as f64 / 1e3)
+    } else {
+        format!("{:.2}s", us as f64 / 1e6)
+    }
+}
+
+/// Latencies of successful requests
+#[derive(Default)]
+pub struct Latencies {
+    micros: Vec<u64>,
+    sorted: bool,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.micros.push(micros(latency));
+        self.sorted = false;
+    }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.micros.sort();
+            self.sorted = true;
+        }
+    }
+
+    /// Nearest-rank percentile, `p` is in `0..1`
+    pub fn percentile(&mut self, p: f64) -> u64 {
+        self.sort();
+        if self.micros.is_empty() {
+            return 0;
+        }
+        let rank = (p * self.micros.len() as f64).ceil() as usize;
+        self.micros[rank.max(1).min(self.micros.len()) - 1]
+    }
+
+    /// Counts of latencies by power of two buckets of microseconds,
+    /// bucket is upper bound
+    pub fn histogram(&mut self) -> Vec<(u64, usize)> {
+        self.sort();
+        let mut r: Vec<(u64, usize)> = Vec::new();
+        for &us in &self.micros {
+            let bucket = us.next_power_of_two();
+            if r.last().map(|&(b, _)| b) == Some(bucket) {
+                r.last_mut().unwrap().1 += 1;
+            } else {
+                r.push((bucket, 1));
+            }
+        }
+        r
+    }
+}
+
+/// Outcomes of all requests
+#[derive(Default)]
+pub struct Stats {
+    pub latencies: Latencies,
+    /// Response count by status
+    pub statuses: BTreeMap<u32, u64>,
+    /// Failed request count by `ErrorCode` of reset stream or kind of other error
+    pub errors: BTreeMap<String, u64>,
+    pub response_body_bytes: u64,
+}
+
+fn error_kind(e: &httpbis::Error) -> String {
+    match e {
+        &httpbis::Error::CodeError(error_code) => error_code.as_ref().to_owned(),
+        e => {
+            // Variant name
+            let debug = format!("{:?}", e);
+            debug.split('(').next().unwrap().to_owned()
+        }
+    }
+}
+
+impl Stats {
+    /// Record outcome of request started at `start`
+    pub fn record(&mut self, start: Instant, result: httpbis::Result<SimpleHttpMessage>) {
+        let latency = start.elapsed();
+        match result {
+            Ok(message) => {
+                *self.statuses.entry(message.headers.status()).or_insert(0) += 1;
+                self.response_body_bytes += message.body.len() as u64;
+                self.latencies.record(latency);
+            }
+            Err(e) => {
+                *self.errors.entry(error_kind(&e)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn report(&mut self, elapsed: Duration, window_stalls: u64) -> String {
+        let ok: u64 = self.statuses.values().sum();
+        let failed: u64 = self.errors.values().sum();
+        let elapsed_secs = secs(elapsed);
+
+        let mut r = String::new();
+        writeln!(r, "requests:     {} total, {} completed, {} failed", ok + failed, ok, failed).unwrap();
+        writeln!(r, "duration:     {:.2}s", elapsed_secs).unwrap();
+        writeln!(r, "throughput:   {:.1} req/s, {:.2} MiB/s response body",
+            ok as f64 / elapsed_secs,
+            self.response_body_bytes as f64 / elapsed_secs / (1024.0 * 1024.0)).unwrap();
+
+        if ok != 0 {
+            let percentiles = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999), ("max", 1.0)];
+            let mut line = format!("min {}", format_micros(self.latencies.percentile(0.0)));
+            for &(name, p) in &percentiles {
+                write!(line, ", {} {}", name, format_micros(self.latencies.percentile(p))).unwrap();
+            }
+            writeln!(r, "latency:      {}", line).unwrap();
+
+            writeln!(r, "histogram:").unwrap();
+            for (bucket, count) in self.latencies.histogram() {
+                writeln!(r, "  <= {:>8}  {:>10}  {:5.1}%",
+                    format_micros(bucket), count, count as f64 * 100.0 / ok as f64).unwrap();
+            }
+        }
+
+        writeln!(r, "status codes:").unwrap();
+        for (status, count) in &self.statuses {
+            writeln!(r, "  {}  {}", status, count).unwrap();
+        }
+
+        if !self.errors.is_empty() {
+            writeln!(r, "errors:").unwrap();
+            for (kind, count) in &self.errors {
+                writeln!(r, "  {}  {}", kind, count).unwrap();
+            }
+        }
+
+        writeln!(r, "flow control stalls: {}", window_stalls).unwrap();
+
+        r
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn latencies(micros: &[u64]) -> Latencies {
+        let mut latencies = Latencies::default();
+        for &us in micros {
+            latencies.record(Duration::new(us / 1_000_000, (us % 1_000_000) as u32 * 1000));
+        }
+        latencies
+    }
+
+    #[test]
+    fn percentile() {
+        assert_eq!(0, latencies(&[]).percentile(0.5));
+
+        let mut l = latencies(&[10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(1, l.percentile(0.0));
+        assert_eq!(1, l.percentile(0.1));
+        assert_eq!(5, l.percentile(0.5));
+        assert_eq!(6, l.percentile(0.51));
+        assert_eq!(9, l.percentile(0.9));
+        assert_eq!(10, l.percentile(0.99));
+        assert_eq!(10, l.percentile(1.0));
+
+        l.record(Duration::from_secs(2));
+        assert_eq!(2_000_000, l.percentile(1.0));
+    }
+
+    #[test]
+    fn histogram() {
+        assert_eq!(Vec::<(u64, usize)>::new(), latencies(&[]).histogram());
+        assert_eq!(
+            vec![(1, 2), (4, 2), (8, 1), (1024, 1)],
+            latencies(&[1000, 5, 4, 3, 1, 0]).histogram());
+    }
+}
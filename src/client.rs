@@ -1,5 +1,9 @@
+use std::mem;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
 
@@ -33,11 +37,16 @@ use solicit_async::*;
 
 use client_conn::*;
 use client_conf::*;
+use client_backoff::Backoff;
+use metrics::Metrics;
+use frame_trace::FrameTracer;
+use compression;
 use common::*;
 use stream_part::*;
 use service::Service;
 use socket::ToClientStream;
 use socket::AnySocketAddr;
+use socket_memory::MemoryAddr;
 
 pub use client_tls::ClientTlsOption;
 
@@ -81,6 +90,14 @@ impl<C : TlsConnector> ClientBuilder<C> {
     }
 }
 
+impl<C : TlsConnector> ClientBuilder<C> {
+    /// Connect to in-process server, see `ServerBuilder::set_memory_addr`
+    pub fn set_memory_addr(&mut self, name: &str) -> Result<()> {
+        self.addr = Some(AnySocketAddr::Memory(MemoryAddr(name.to_owned())));
+        Ok(())
+    }
+}
+
 impl<C : TlsConnector> ClientBuilder<C> {
     pub fn new() -> ClientBuilder<C> {
         ClientBuilder {
@@ -95,7 +112,7 @@ impl<C : TlsConnector> ClientBuilder<C> {
         let mut tls_connector = C::builder()?;
 
         if C::supports_alpn() {
-            // TODO: check negotiated protocol after connect
+            // negotiated protocol is checked after connect according to `ClientConf::alpn`
             tls_connector.set_alpn_protocols(&[b"h2"])?;
         }
 
@@ -106,6 +123,16 @@ impl<C : TlsConnector> ClientBuilder<C> {
         Ok(())
     }
 
+    /// Report connection events, e. g. to `MetricsRegistry`
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.conf.common.metrics = Some(metrics);
+    }
+
+    /// Trace every frame of every connection, e. g. with `TextFrameDump`
+    pub fn set_frame_tracer(&mut self, tracer: Arc<FrameTracer>) {
+        self.conf.common.frame_tracer = Some(tracer);
+    }
+
     pub fn build(self) -> Result<Client> {
         let addr = self.addr.expect("addr is not specified");
 
@@ -118,9 +145,14 @@ impl<C : TlsConnector> ClientBuilder<C> {
 
         let (done_tx, done_rx) = oneshot::channel();
 
+        let conn_state = Arc::new(Mutex::new(ClientConnectionState::Connecting));
+
+        let decompress = self.conf.decompress;
+
         let join = if let Some(remote) = self.event_loop {
             let tls = self.tls;
             let conf = self.conf;
+            let conn_state = conn_state.clone();
             let controller_tx = controller_tx.clone();
             remote.spawn(move |handle| {
                 spawn_client_event_loop(
@@ -129,6 +161,7 @@ impl<C : TlsConnector> ClientBuilder<C> {
                     addr,
                     tls,
                     conf,
+                    conn_state,
                     done_tx,
                     controller_tx,
                     controller_rx);
@@ -141,6 +174,7 @@ impl<C : TlsConnector> ClientBuilder<C> {
             let conf = self.conf;
             let thread_name = conf.thread_name.clone()
                 .unwrap_or_else(|| "http2-client-loop".to_owned()).to_string();
+            let conn_state = conn_state.clone();
             let controller_tx = controller_tx.clone();
             let join_handle = thread::Builder::new()
                 .name(thread_name)
@@ -154,6 +188,7 @@ impl<C : TlsConnector> ClientBuilder<C> {
                         addr,
                         tls,
                         conf,
+                        conn_state,
                         done_tx,
                         controller_tx,
                         controller_rx);
@@ -168,6 +203,8 @@ impl<C : TlsConnector> ClientBuilder<C> {
             join: Some(join),
             controller_tx: controller_tx,
             http_scheme: http_scheme,
+            conn_state: conn_state,
+            decompress: decompress,
             shutdown: shutdown_signal,
         })
     }
@@ -179,10 +216,24 @@ enum Completion {
     Rx(oneshot::Receiver<()>),
 }
 
+/// State of the connection of `Client`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientConnectionState {
+    /// Connection is being established, requests are queued
+    Connecting,
+    /// Connection is established
+    Ready,
+    /// Connection failed or was lost; client reconnects on next request,
+    /// but not earlier than reconnect delay
+    TransientFailure,
+}
+
 pub struct Client {
     controller_tx: UnboundedSender<ControllerCommand>,
     join: Option<Completion>,
     http_scheme: HttpScheme,
+    conn_state: Arc<Mutex<ClientConnectionState>>,
+    decompress: bool,
     // used only once to send shutdown signal
     shutdown: ShutdownSignal,
 }
@@ -220,6 +271,14 @@ impl Client {
         client.build()
     }
 
+    /// Client connected to in-process server
+    pub fn new_plain_memory(name: &str, conf: ClientConf) -> Result<Client> {
+        let mut client = ClientBuilder::new_plain();
+        client.conf = conf;
+        client.set_memory_addr(name)?;
+        client.build()
+    }
+
     pub fn new_expl<C : TlsConnector>(addr: &SocketAddr, tls: ClientTlsOption<C>, conf: ClientConf) -> Result<Client> {
         let mut client = ClientBuilder::new();
         client.addr = Some(AnySocketAddr::Inet(addr.clone()));
@@ -277,6 +336,16 @@ impl Client {
         Box::new(rx.map_err(|_| error::Error::Other("conn died")))
     }
 
+    /// `http` or `https`, used as `:scheme` of requests
+    pub fn http_scheme(&self) -> HttpScheme {
+        self.http_scheme
+    }
+
+    /// Current state of the connection
+    pub fn connection_state(&self) -> ClientConnectionState {
+        *self.conn_state.lock().expect("lock")
+    }
+
     pub fn wait_for_connect(&self) -> HttpFutureSend<()> {
         let (tx, rx) = oneshot::channel();
         // ignore error
@@ -285,19 +354,28 @@ impl Client {
     }
 }
 
-impl Service for Client {
-    // TODO: copy-paste with ClientConnection::start_request
-    fn start_request(
+impl Client {
+    /// Start request with per-request options like deadline.
+    ///
+    /// Dropping the response before server ended it resets the stream with `CANCEL`.
+    // TODO: copy-paste with ClientConnection::start_request_with_options
+    pub fn start_request_with_options(
         &self,
-        headers: Headers,
-        body: HttpPartStream)
+        mut headers: Headers,
+        body: HttpPartStream,
+        options: ClientRequestOptions)
             -> Response
     {
+        if self.decompress && headers.get_opt("accept-encoding").is_none() {
+            headers.add("accept-encoding", compression::ACCEPT_ENCODING);
+        }
+
         let (resp_tx, resp_rx) = oneshot::channel();
 
         let start = StartRequestMessage {
             headers: headers,
             body: body,
+            options: options,
             resp_tx: resp_tx,
         };
 
@@ -311,14 +389,38 @@ impl Service for Client {
 
         let resp_rx = resp_rx.flatten_stream();
 
-        Response::from_stream(resp_rx)
-    }}
+        let response = Response::from_stream(resp_rx);
+        if self.decompress {
+            compression::decompress_response(response)
+        } else {
+            response
+        }
+    }
+}
+
+impl Service for Client {
+    fn start_request(
+        &self,
+        headers: Headers,
+        body: HttpPartStream)
+            -> Response
+    {
+        self.start_request_with_options(headers, body, ClientRequestOptions::new())
+    }
+}
 
 enum ControllerCommand {
-    GoAway,
+    GoAway(u64),
     StartRequest(StartRequestMessage),
     WaitForConnect(oneshot::Sender<Result<()>>),
     DumpState(oneshot::Sender<ConnectionStateSnapshot>),
+    // Connection with given id completed handshake
+    ConnectionReady(u64),
+    // Connection with given id terminated
+    ConnectionDied(u64),
+    ReconnectDelayExpired,
+    // Deadline of some pending request expired
+    PendingDeadlineExpired,
 }
 
 struct ControllerState<T : ToClientStream, C : TlsConnector> {
@@ -328,55 +430,320 @@ struct ControllerState<T : ToClientStream, C : TlsConnector> {
     conf: ClientConf,
     // current connection
     conn: Arc<ClientConnection>,
+    // id of current connection, used to ignore events from previous connections
+    conn_id: u64,
+    conn_state: Arc<Mutex<ClientConnectionState>>,
+    backoff: Backoff,
+    // reconnect is not allowed before that time after connection failure
+    reconnect_not_before: Instant,
+    reconnect_scheduled: bool,
+    // commands waiting for connection
+    pending_requests: Vec<StartRequestMessage>,
+    pending_wait_for_connect: Vec<oneshot::Sender<Result<()>>>,
     tx: UnboundedSender<ControllerCommand>,
 }
 
+fn spawn_conn<T : ToClientStream + 'static + Clone, C : TlsConnector>(
+    handle: &reactor::Handle,
+    socket_addr: &T,
+    tls: &ClientTlsOption<C>,
+    conf: &ClientConf,
+    tx: &UnboundedSender<ControllerCommand>,
+    conn_id: u64)
+        -> ClientConnection
+{
+    let (conn, future) = ClientConnection::new(
+        handle.clone(),
+        Box::new(socket_addr.clone()),
+        tls.clone(),
+        conf.clone(),
+        CallbacksImpl {
+            tx: tx.clone(),
+            conn_id: conn_id,
+        });
+
+    let died_tx = tx.clone();
+    handle.spawn(future.then(move |r| {
+        if let Err(e) = r {
+            warn!("client error: {:?}", e);
+        }
+        // ignore error, controller may be already dead
+        drop(died_tx.unbounded_send(ControllerCommand::ConnectionDied(conn_id)));
+        Ok(())
+    }));
+
+    // Wait for connect is processed by connection after handshake
+    let (ready_tx, ready_rx) = oneshot::channel();
+    // ignore error, `ConnectionDied` is sent when connection is dead
+    drop(conn.wait_for_connect_with_resp_sender(ready_tx));
+    let ready_tx = tx.clone();
+    handle.spawn(ready_rx.then(move |r| {
+        if let Ok(Ok(())) = r {
+            drop(ready_tx.unbounded_send(ControllerCommand::ConnectionReady(conn_id)));
+        }
+        Ok(())
+    }));
+
+    conn
+}
+
 impl<T : ToClientStream + 'static + Clone, C : TlsConnector> ControllerState<T, C> {
-    fn init_conn(&mut self) {
-        let (conn, future) = ClientConnection::new(
-            self.handle.clone(),
-            Box::new(self.socket_addr.clone()),
-            self.tls.clone(),
-            self.conf.clone(),
-            CallbacksImpl {
-                tx: self.tx.clone(),
-            });
+    fn new(
+        handle: reactor::Handle,
+        socket_addr: T,
+        tls: ClientTlsOption<C>,
+        conf: ClientConf,
+        conn_state: Arc<Mutex<ClientConnectionState>>,
+        tx: UnboundedSender<ControllerCommand>)
+            -> ControllerState<T, C>
+    {
+        let conn_id = 1;
+        let conn = spawn_conn(&handle, &socket_addr, &tls, &conf, &tx, conn_id);
+        *conn_state.lock().expect("lock") = ClientConnectionState::Connecting;
+
+        let backoff = Backoff::new(conf.reconnect.clone().unwrap_or_default());
+
+        ControllerState {
+            handle: handle,
+            socket_addr: socket_addr,
+            tls: tls,
+            conf: conf,
+            conn: Arc::new(conn),
+            conn_id: conn_id,
+            conn_state: conn_state,
+            backoff: backoff,
+            reconnect_not_before: Instant::now(),
+            reconnect_scheduled: false,
+            pending_requests: Vec::new(),
+            pending_wait_for_connect: Vec::new(),
+            tx: tx,
+        }
+    }
 
-        self.handle.spawn(future.map_err(|e| { warn!("client error: {:?}", e); () }));
+    fn state(&self) -> ClientConnectionState {
+        *self.conn_state.lock().expect("lock")
+    }
+
+    fn set_state(&self, state: ClientConnectionState) {
+        debug!("client connection state: {:?}", state);
+        *self.conn_state.lock().expect("lock") = state;
+    }
+
+    fn fail_fast(&self) -> bool {
+        self.conf.reconnect.as_ref().map(|r| r.fail_fast).unwrap_or(false)
+    }
 
+    fn init_conn(&mut self) {
+        self.conn_id += 1;
+        let conn = spawn_conn(
+            &self.handle, &self.socket_addr, &self.tls, &self.conf, &self.tx, self.conn_id);
         self.conn = Arc::new(conn);
+        self.set_state(ClientConnectionState::Connecting);
     }
 
-    fn iter(mut self, cmd: ControllerCommand) -> ControllerState<T, C> {
-        match cmd {
-            ControllerCommand::GoAway => {
+    fn schedule_reconnect(&mut self) {
+        if self.reconnect_scheduled {
+            return;
+        }
+
+        let now = Instant::now();
+        if self.reconnect_not_before <= now {
+            self.init_conn();
+            return;
+        }
+
+        let tx = self.tx.clone();
+        match reactor::Timeout::new(self.reconnect_not_before - now, &self.handle) {
+            Ok(timeout) => {
+                self.reconnect_scheduled = true;
+                self.handle.spawn(timeout.then(move |_| {
+                    drop(tx.unbounded_send(ControllerCommand::ReconnectDelayExpired));
+                    Ok(())
+                }));
+            }
+            Err(e) => {
+                warn!("failed to create reconnect timer, reconnecting now: {:?}", e);
                 self.init_conn();
-            },
-            ControllerCommand::StartRequest(start) => {
+            }
+        }
+    }
+
+    fn fail_pending(&mut self) {
+        for start in self.pending_requests.drain(..) {
+            let err = error::Error::Other("client failed to connect");
+            // ignore error
+            drop(start.resp_tx.send(Response::err(err)));
+        }
+        for tx in self.pending_wait_for_connect.drain(..) {
+            // ignore error
+            drop(tx.send(Err(error::Error::Other("client failed to connect"))));
+        }
+    }
+
+    fn conn_ready(&mut self, conn_id: u64) {
+        if conn_id != self.conn_id {
+            return;
+        }
+
+        self.set_state(ClientConnectionState::Ready);
+        self.backoff.reset();
+
+        for start in mem::replace(&mut self.pending_requests, Vec::new()) {
+            self.start_request(start);
+        }
+        for tx in mem::replace(&mut self.pending_wait_for_connect, Vec::new()) {
+            self.wait_for_connect(tx);
+        }
+    }
+
+    fn conn_died(&mut self, conn_id: u64) {
+        if conn_id != self.conn_id || self.state() == ClientConnectionState::TransientFailure {
+            return;
+        }
+
+        // Reconnect immediately after loss of established connection,
+        // delay only consecutive failed attempts
+        let delay = match self.state() {
+            ClientConnectionState::Ready => Duration::from_secs(0),
+            _ => self.backoff.next_delay(),
+        };
+
+        info!("client connection died, reconnect allowed in {:?}", delay);
+
+        self.set_state(ClientConnectionState::TransientFailure);
+        self.reconnect_not_before = Instant::now() + delay;
+
+        if !self.pending_requests.is_empty() || !self.pending_wait_for_connect.is_empty() {
+            if self.fail_fast() {
+                self.fail_pending();
+            } else {
+                self.schedule_reconnect();
+            }
+        }
+    }
+
+    // Reconnect is allowed or requests are queued
+    fn can_wait_for_reconnect(&self) -> bool {
+        !self.fail_fast() || self.reconnect_not_before <= Instant::now()
+    }
+
+    fn add_pending_request(&mut self, start: StartRequestMessage) {
+        if let Some(deadline) = start.options.deadline {
+            let now = Instant::now();
+            let timeout = if deadline > now { deadline - now } else { Duration::from_secs(0) };
+            let tx = self.tx.clone();
+            match reactor::Timeout::new(timeout, &self.handle) {
+                Ok(timeout) => {
+                    self.handle.spawn(timeout.then(move |_| {
+                        drop(tx.unbounded_send(ControllerCommand::PendingDeadlineExpired));
+                        Ok(())
+                    }));
+                }
+                Err(e) => {
+                    warn!("failed to create request deadline timer: {:?}", e);
+                }
+            }
+        }
+        self.pending_requests.push(start);
+    }
+
+    fn expire_pending_requests(&mut self) {
+        let now = Instant::now();
+        let (expired, pending): (Vec<_>, Vec<_>) =
+            mem::replace(&mut self.pending_requests, Vec::new())
+                .into_iter()
+                .partition(|start| start.options.deadline.map(|d| d <= now).unwrap_or(false));
+        self.pending_requests = pending;
+        for start in expired {
+            // ignore error
+            drop(start.resp_tx.send(Response::err(error::Error::RequestTimeout)));
+        }
+    }
+
+    fn start_request(&mut self, start: StartRequestMessage) {
+        match self.state() {
+            ClientConnectionState::Ready => {
                 if let Err(start) = self.conn.start_request_with_resp_sender(start) {
-                    self.init_conn();
-                    if let Err(start) = self.conn.start_request_with_resp_sender(start) {
-                        let err = error::Error::Other("client died and reconnect failed");
-                        // ignore error
-                        if let Err(_) = start.resp_tx.send(Response::err(err)) {
-                            debug!("called likely died");
-                        }
+                    // connection is dead, but `ConnectionDied` is not yet received
+                    let conn_id = self.conn_id;
+                    self.conn_died(conn_id);
+                    self.start_request(start);
+                }
+            }
+            ClientConnectionState::Connecting => {
+                self.add_pending_request(start);
+            }
+            ClientConnectionState::TransientFailure => {
+                if self.can_wait_for_reconnect() {
+                    self.add_pending_request(start);
+                    self.schedule_reconnect();
+                } else {
+                    let err = error::Error::Other("client is waiting to reconnect");
+                    // ignore error
+                    if let Err(_) = start.resp_tx.send(Response::err(err)) {
+                        debug!("called likely died");
                     }
                 }
             }
-            ControllerCommand::WaitForConnect(tx) => {
+        }
+    }
+
+    fn wait_for_connect(&mut self, tx: oneshot::Sender<Result<()>>) {
+        match self.state() {
+            ClientConnectionState::Ready => {
                 if let Err(tx) = self.conn.wait_for_connect_with_resp_sender(tx) {
+                    let conn_id = self.conn_id;
+                    self.conn_died(conn_id);
+                    self.wait_for_connect(tx);
+                }
+            }
+            ClientConnectionState::Connecting => {
+                self.pending_wait_for_connect.push(tx);
+            }
+            ClientConnectionState::TransientFailure => {
+                if self.can_wait_for_reconnect() {
+                    self.pending_wait_for_connect.push(tx);
+                    self.schedule_reconnect();
+                } else {
+                    let err = error::Error::Other("client is waiting to reconnect");
+                    // ignore error
+                    drop(tx.send(Err(err)));
+                }
+            }
+        }
+    }
+
+    fn iter(mut self, cmd: ControllerCommand) -> ControllerState<T, C> {
+        match cmd {
+            ControllerCommand::GoAway(conn_id) => {
+                if conn_id == self.conn_id {
                     self.init_conn();
-                    if let Err(tx) = self.conn.wait_for_connect_with_resp_sender(tx) {
-                        let err = error::Error::Other("client died and reconnect failed");
-                        // ignore error
-                        drop(tx.send(Err(err)));
-                    }
                 }
+            },
+            ControllerCommand::StartRequest(start) => {
+                self.start_request(start);
+            }
+            ControllerCommand::WaitForConnect(tx) => {
+                self.wait_for_connect(tx);
             }
             ControllerCommand::DumpState(tx) => {
                 self.conn.dump_state_with_resp_sender(tx);
             }
+            ControllerCommand::ConnectionReady(conn_id) => {
+                self.conn_ready(conn_id);
+            }
+            ControllerCommand::ConnectionDied(conn_id) => {
+                self.conn_died(conn_id);
+            }
+            ControllerCommand::ReconnectDelayExpired => {
+                self.reconnect_scheduled = false;
+                if self.state() == ClientConnectionState::TransientFailure {
+                    self.init_conn();
+                }
+            }
+            ControllerCommand::PendingDeadlineExpired => {
+                self.expire_pending_requests();
+            }
         }
         self
     }
@@ -395,11 +762,12 @@ impl<T : ToClientStream + 'static + Clone, C : TlsConnector> ControllerState<T,
 
 struct CallbacksImpl {
     tx: UnboundedSender<ControllerCommand>,
+    conn_id: u64,
 }
 
 impl ClientConnectionCallbacks for CallbacksImpl {
     fn goaway(&self, _stream_id: StreamId, _error_code: u32) {
-        drop(self.tx.unbounded_send(ControllerCommand::GoAway));
+        drop(self.tx.unbounded_send(ControllerCommand::GoAway(self.conn_id)));
     }
 }
 
@@ -410,25 +778,18 @@ fn spawn_client_event_loop<T : ToClientStream + Send + Clone + 'static, C : TlsC
     socket_addr: T,
     tls: ClientTlsOption<C>,
     conf: ClientConf,
+    conn_state: Arc<Mutex<ClientConnectionState>>,
     done_tx: oneshot::Sender<()>,
     controller_tx: UnboundedSender<ControllerCommand>,
     controller_rx: UnboundedReceiver<ControllerCommand>)
 {
-    let (http_conn, conn_future) =
-        ClientConnection::new(handle.clone(), Box::new(socket_addr.clone()), tls.clone(), conf.clone(), CallbacksImpl {
-            tx: controller_tx.clone(),
-        });
-
-    handle.spawn(conn_future.map_err(|e| { warn!("client error: {:?}", e); () }));
-
-    let init = ControllerState {
-        handle: handle.clone(),
-        socket_addr: socket_addr.clone(),
-        tls: tls,
-        conf: conf,
-        conn: Arc::new(http_conn),
-        tx: controller_tx,
-    };
+    let init = ControllerState::new(
+        handle.clone(),
+        socket_addr,
+        tls,
+        conf,
+        conn_state,
+        controller_tx);
 
     let controller_future = init.run(controller_rx);
 
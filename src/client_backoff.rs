@@ -0,0 +1,110 @@
+//! Delay between client reconnect attempts
+
+use std::cmp;
+use std::time::Duration;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+
+use client_conf::ClientReconnectConf;
+
+
+fn duration_to_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+fn secs_to_duration(secs: f64) -> Duration {
+    let secs = if secs > 0.0 { secs } else { 0.0 };
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1_000_000_000.0) as u32)
+}
+
+/// Random number in `[0, 1)`
+fn random_unit() -> f64 {
+    // `RandomState` is randomly seeded, so we don't need a dependency on `rand`
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Exponential backoff with jitter
+pub struct Backoff {
+    conf: ClientReconnectConf,
+    /// Consecutive failed connection attempts
+    failed_attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(conf: ClientReconnectConf) -> Backoff {
+        Backoff {
+            conf: conf,
+            failed_attempts: 0,
+        }
+    }
+
+    /// Connection was established
+    pub fn reset(&mut self) {
+        self.failed_attempts = 0;
+    }
+
+    /// Delay without jitter after given number of failed attempts
+    fn base_delay(&self, failed_attempts: u32) -> Duration {
+        let max = duration_to_secs(self.conf.max_delay);
+        let mut delay = duration_to_secs(self.conf.initial_delay);
+        for _ in 1..failed_attempts {
+            delay *= self.conf.multiplier;
+            if delay >= max {
+                break;
+            }
+        }
+        cmp::min(secs_to_duration(delay), self.conf.max_delay)
+    }
+
+    /// Register failed connection attempt and return delay before next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+
+        let delay = duration_to_secs(self.base_delay(self.failed_attempts));
+        let jitter = delay * self.conf.jitter * (random_unit() * 2.0 - 1.0);
+        secs_to_duration(delay + jitter)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn conf_no_jitter() -> ClientReconnectConf {
+        ClientReconnectConf {
+            initial_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_millis(500),
+            jitter: 0.0,
+            fail_fast: false,
+        }
+    }
+
+    #[test]
+    fn exponential() {
+        let mut backoff = Backoff::new(conf_no_jitter());
+        assert_eq!(Duration::from_millis(100), backoff.next_delay());
+        assert_eq!(Duration::from_millis(200), backoff.next_delay());
+        assert_eq!(Duration::from_millis(400), backoff.next_delay());
+        assert_eq!(Duration::from_millis(500), backoff.next_delay());
+        assert_eq!(Duration::from_millis(500), backoff.next_delay());
+
+        backoff.reset();
+        assert_eq!(Duration::from_millis(100), backoff.next_delay());
+    }
+
+    #[test]
+    fn jitter_within_bounds() {
+        let mut conf = conf_no_jitter();
+        conf.jitter = 0.5;
+        for _ in 0..100 {
+            let delay = Backoff::new(conf.clone()).next_delay();
+            assert!(delay >= Duration::from_millis(50), "{:?}", delay);
+            assert!(delay <= Duration::from_millis(150), "{:?}", delay);
+        }
+    }
+}
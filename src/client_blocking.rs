@@ -0,0 +1,229 @@
+//! Synchronous facade for `Client`
+
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::thread;
+
+use futures::future::Future;
+use futures::sink;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use futures::stream::Wait;
+use futures::sync::mpsc;
+
+use bytes::Bytes;
+
+use client::Client;
+use error;
+use result::Result;
+use resp::Response;
+use service::Service;
+use solicit::header::Header;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use stream_part::HttpStreamPart;
+use stream_part::HttpStreamPartContent;
+
+
+/// Client which blocks current thread until response headers are received
+pub struct BlockingClient {
+    client: Client,
+    /// Size of request body chunks read from `io::Read`
+    pub chunk_size: usize,
+}
+
+impl BlockingClient {
+    pub fn new(client: Client) -> BlockingClient {
+        BlockingClient {
+            client: client,
+            chunk_size: 16 * 1024,
+        }
+    }
+
+    /// Underlying async client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn headers(&self, method: &str, path: &str, authority: &str) -> Headers {
+        Headers(vec![
+            Header::new(":method", method.to_owned()),
+            Header::new(":path", path.to_owned()),
+            Header::new(":authority", authority.to_owned()),
+            Header::new(":scheme", self.client.http_scheme().as_bytes()),
+        ])
+    }
+
+    pub fn get(&self, path: &str, authority: &str) -> Result<BlockingResponse> {
+        let headers = self.headers("GET", path, authority);
+        BlockingResponse::wait(self.client.start_request(headers, HttpPartStream::empty()))
+    }
+
+    pub fn post<R>(&self, path: &str, authority: &str, body: R) -> Result<BlockingResponse>
+        where R : Read + Send + 'static
+    {
+        let headers = self.headers("POST", path, authority);
+        self.request(headers, body)
+    }
+
+    /// Send request with body from `io::Read`, wait for response headers.
+    ///
+    /// Body is read in a separate thread, so the response can be received
+    /// while the request body is still being sent.
+    pub fn request<R>(&self, headers: Headers, body: R) -> Result<BlockingResponse>
+        where R : Read + Send + 'static
+    {
+        // capacity is per sender, so at most two chunks are buffered
+        let (tx, rx) = mpsc::channel::<Result<Bytes>>(0);
+        let rx = rx.then(|r| match r {
+            Ok(r) => r,
+            Err(()) => Err(error::Error::Other("unreachable")),
+        });
+        let response = self.client.start_request(headers, HttpPartStream::bytes(rx));
+
+        let chunk_size = self.chunk_size;
+        thread::Builder::new()
+            .name("httpbis-blocking-body".to_owned())
+            .spawn(move || send_body(body, tx.wait(), chunk_size))?;
+
+        BlockingResponse::wait(response)
+    }
+}
+
+fn send_body<R : Read>(mut body: R, mut tx: sink::Wait<mpsc::Sender<Result<Bytes>>>, chunk_size: usize) {
+    let mut buf = vec![0; chunk_size];
+    loop {
+        let r = match body.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => Ok(Bytes::from(&buf[..n])),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e.into()),
+        };
+        let failed = r.is_err();
+        // receiver is dropped when the stream is closed,
+        // the reason is reported by the response
+        if let Err(_) = tx.send(r) {
+            return;
+        }
+        if failed {
+            return;
+        }
+    }
+}
+
+
+/// Response headers and body
+pub struct BlockingResponse {
+    pub status: u32,
+    pub headers: Headers,
+    pub body: BlockingBody,
+}
+
+impl BlockingResponse {
+    fn wait(response: Response) -> Result<BlockingResponse> {
+        let (headers, body) = response.0.wait()?;
+        let status = headers.get_opt_parse(":status")
+            .ok_or_else(|| error::Error::InvalidFrame("no valid :status".to_owned()))?;
+        Ok(BlockingResponse {
+            status: status,
+            headers: headers,
+            body: BlockingBody {
+                stream: body.wait(),
+                buf: Bytes::new(),
+                trailers: None,
+                done: false,
+            },
+        })
+    }
+}
+
+
+/// Response body.
+///
+/// Frames are pulled from the connection only when `read` needs more data,
+/// so flow control window is increased as the body is consumed.
+pub struct BlockingBody {
+    stream: Wait<HttpPartStream>,
+    buf: Bytes,
+    trailers: Option<Headers>,
+    done: bool,
+}
+
+impl BlockingBody {
+    /// Trailers, available after body is read to the end
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.trailers.as_ref()
+    }
+
+    /// Read the remaining body into memory
+    pub fn read_all(&mut self) -> Result<Vec<u8>> {
+        let mut r = Vec::new();
+        self.read_to_end(&mut r)?;
+        Ok(r)
+    }
+}
+
+impl Read for BlockingBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.buf.is_empty() {
+            if self.done {
+                return Ok(0);
+            }
+
+            match self.stream.next() {
+                None => self.done = true,
+                Some(Ok(HttpStreamPart { content, last })) => {
+                    match content {
+                        HttpStreamPartContent::Data(data) => self.buf = data,
+                        HttpStreamPartContent::Headers(headers) => self.trailers = Some(headers),
+                    }
+                    self.done = last;
+                }
+                Some(Err(error::Error::IoError(e))) => return Err(e),
+                Some(Err(e)) => return Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+            }
+        }
+
+        let n = cmp::min(buf.len(), self.buf.len());
+        buf[..n].copy_from_slice(&self.buf.split_to(n));
+        Ok(n)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::stream;
+
+    fn body(parts: Vec<HttpStreamPart>) -> BlockingBody {
+        BlockingBody {
+            stream: HttpPartStream::new(stream::iter_ok(parts)).wait(),
+            buf: Bytes::new(),
+            trailers: None,
+            done: false,
+        }
+    }
+
+    #[test]
+    fn read_body() {
+        let mut trailers = Headers::new();
+        trailers.add("x-t", "1");
+
+        let mut body = body(vec![
+            HttpStreamPart::intermediate_data(Bytes::from("abc")),
+            HttpStreamPart::intermediate_data(Bytes::new()),
+            HttpStreamPart::intermediate_data(Bytes::from("def")),
+            HttpStreamPart::last_headers(trailers.clone()),
+        ]);
+
+        let mut buf = [0; 2];
+        assert_eq!(2, body.read(&mut buf).unwrap());
+        assert_eq!(b"ab", &buf);
+        assert_eq!(b"cdef".to_vec(), body.read_all().unwrap());
+        assert_eq!(Some(&trailers), body.trailers());
+        assert_eq!(0, body.read(&mut buf).unwrap());
+    }
+}
@@ -2,12 +2,81 @@ use std::time::Duration;
 
 use common::CommonConf;
 
+use error::Error;
+use result::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAlpn {
+    // Ignore negotiated ALPN
+    Ignore,
+    // Return error if negotiated protocol is not "h2", proceed if no protocol negotiated
+    RejectOther,
+    // Return error if ALPN is not "h2"
+    Require,
+}
+
+impl ClientAlpn {
+    /// Check protocol negotiated with TLS handshake
+    pub fn check(&self, negotiated: Option<&[u8]>) -> Result<()> {
+        let ok = match (self, negotiated) {
+            (&ClientAlpn::Ignore, _) => true,
+            (_, Some(b"h2")) => true,
+            (&ClientAlpn::RejectOther, None) => true,
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::AlpnMismatch(negotiated.map(|p| p.to_vec())))
+        }
+    }
+}
+
+/// Reconnect policy of `Client`.
+///
+/// Delay is applied only between consecutive failed connection attempts;
+/// client reconnects immediately after loss of a connection which was ready.
+#[derive(Debug, Clone)]
+pub struct ClientReconnectConf {
+    /// Delay after first failed connection attempt
+    pub initial_delay: Duration,
+    /// Delay is multiplied by this factor after each failed attempt
+    pub multiplier: f64,
+    /// Upper bound of the delay
+    pub max_delay: Duration,
+    /// Delay is randomly changed by this fraction, e. g. `0.2` means +-20%
+    pub jitter: f64,
+    /// Fail requests immediately while waiting for reconnect delay
+    /// instead of queuing them until connection is established
+    pub fail_fast: bool,
+}
+
+impl Default for ClientReconnectConf {
+    fn default() -> ClientReconnectConf {
+        ClientReconnectConf {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 1.6,
+            max_delay: Duration::from_secs(120),
+            jitter: 0.2,
+            fail_fast: false,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct ClientConf {
     /// TCP_NODELAY
     pub no_delay: Option<bool>,
     pub thread_name: Option<String>,
     pub connection_timeout: Option<Duration>,
+    /// How to check ALPN negotiated protocol on TLS connections,
+    /// `RejectOther` is used if not specified
+    pub alpn: Option<ClientAlpn>,
+    /// Default policy is used if not specified
+    pub reconnect: Option<ClientReconnectConf>,
+    /// Send `accept-encoding` if request has none,
+    /// and decode response body according to `content-encoding`
+    pub decompress: bool,
 
     pub common: CommonConf,
 }
@@ -17,3 +86,22 @@ impl ClientConf {
         Default::default()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alpn_check() {
+        assert!(ClientAlpn::Ignore.check(None).is_ok());
+        assert!(ClientAlpn::Ignore.check(Some(&b"http/1.1"[..])).is_ok());
+
+        assert!(ClientAlpn::RejectOther.check(None).is_ok());
+        assert!(ClientAlpn::RejectOther.check(Some(&b"h2"[..])).is_ok());
+        assert!(ClientAlpn::RejectOther.check(Some(&b"http/1.1"[..])).is_err());
+
+        assert!(ClientAlpn::Require.check(None).is_err());
+        assert!(ClientAlpn::Require.check(Some(&b"h2"[..])).is_ok());
+        assert!(ClientAlpn::Require.check(Some(&b"http/1.1"[..])).is_err());
+    }
+}
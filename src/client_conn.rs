@@ -3,9 +3,12 @@
 use std::result::Result as std_Result;
 use std::sync::Arc;
 use std::io;
+use std::time::Duration;
+use std::time::Instant;
 
 use error;
 use error::Error;
+use error::ErrorCode;
 use result;
 
 use exec::CpuPoolOption;
@@ -43,6 +46,7 @@ use client_conf::*;
 use client_tls::*;
 use socket::*;
 
+use misc::BsDebug;
 use rc_mut::*;
 
 
@@ -57,6 +61,10 @@ impl Types for ClientTypes {
     fn first_id() -> StreamId {
         1
     }
+
+    fn cancel_stream_on_drop() -> bool {
+        true
+    }
 }
 
 
@@ -117,14 +125,38 @@ pub struct ClientConnection {
 
 unsafe impl Sync for ClientConnection {}
 
+/// Options of a single request
+#[derive(Default, Debug, Clone)]
+pub struct ClientRequestOptions {
+    /// Stream is reset with `CANCEL` if it is not completed by that time,
+    /// and response fails with `Error::RequestTimeout`
+    pub deadline: Option<Instant>,
+}
+
+impl ClientRequestOptions {
+    pub fn new() -> ClientRequestOptions {
+        Default::default()
+    }
+
+    /// Options with deadline after given timeout from now
+    pub fn with_timeout(timeout: Duration) -> ClientRequestOptions {
+        ClientRequestOptions {
+            deadline: Some(Instant::now() + timeout),
+            ..Default::default()
+        }
+    }
+}
+
 pub struct StartRequestMessage {
     pub headers: Headers,
     pub body: HttpPartStream,
+    pub options: ClientRequestOptions,
     pub resp_tx: oneshot::Sender<Response>,
 }
 
 enum ClientToWriteMessage {
     Start(StartRequestMessage),
+    DeadlineExpired(StreamId),
     Common(CommonToWriteMessage),
 }
 
@@ -142,12 +174,30 @@ enum ClientCommandMessage {
 
 impl<I : AsyncWrite + Send + 'static> ClientWriteLoop<I> {
     fn process_start(self, start: StartRequestMessage) -> HttpFuture<Self> {
-        let StartRequestMessage { headers, body, resp_tx } = start;
+        let StartRequestMessage { headers, body, options, resp_tx } = start;
 
         let stream_id = self.inner.with(move |inner: &mut ClientInner| {
 
             let stream_id = inner.next_local_stream_id();
 
+            if let Some(deadline) = options.deadline {
+                let now = Instant::now();
+                let timeout = if deadline > now { deadline - now } else { Duration::from_secs(0) };
+                let to_write_tx = inner.to_write_tx.clone();
+                match reactor::Timeout::new(timeout, &inner.loop_handle) {
+                    Ok(timeout) => {
+                        inner.loop_handle.spawn(timeout.then(move |_| {
+                            // ignore error, connection may be already dead
+                            drop(to_write_tx.unbounded_send(ClientToWriteMessage::DeadlineExpired(stream_id)));
+                            Ok(())
+                        }));
+                    }
+                    Err(e) => {
+                        warn!("failed to create request deadline timer: {:?}", e);
+                    }
+                }
+            }
+
             let out_window = {
                 let (mut http_stream, resp_stream, out_window) = inner.new_stream_data(
                     stream_id,
@@ -174,6 +224,9 @@ impl<I : AsyncWrite + Send + 'static> ClientWriteLoop<I> {
     fn process_message(self, message: ClientToWriteMessage) -> HttpFuture<Self> {
         match message {
             ClientToWriteMessage::Start(start) => self.process_start(start),
+            ClientToWriteMessage::DeadlineExpired(stream_id) => {
+                self.process_stream_reset(stream_id, ErrorCode::Cancel, Error::RequestTimeout)
+            }
             ClientToWriteMessage::Common(common) => self.process_common(common),
         }
     }
@@ -309,6 +362,8 @@ impl ClientConnection {
     {
         let domain = domain.to_owned();
 
+        let alpn = conf.alpn.clone().unwrap_or(ClientAlpn::RejectOther);
+
         let connect = addr.connect(&lh)
             .map(move |c| { info!("connected to {}", addr); c })
             .map_err(|e| e.into());
@@ -321,9 +376,45 @@ impl ClientConnection {
 
         let tls_conn = tls_conn.map_err(Error::from);
 
+        let tls_conn = tls_conn.and_then(move |conn| {
+            let negotiated = conn.get_ref().get_alpn_protocol();
+            debug!("ALPN negotiated protocol: {:?}", negotiated.as_ref().map(|p| BsDebug(p)));
+            alpn.check(negotiated.as_ref().map(|p| &p[..]))?;
+            Ok(conn)
+        });
+
         ClientConnection::connected(lh, Box::new(tls_conn), conf, callbacks)
     }
 
+    pub fn start_request_with_options(
+        &self,
+        headers: Headers,
+        body: HttpPartStream,
+        options: ClientRequestOptions)
+            -> Response
+    {
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let start = StartRequestMessage {
+            headers: headers,
+            body: body,
+            options: options,
+            resp_tx: resp_tx,
+        };
+
+        if let Err(_) = self.start_request_with_resp_sender(start) {
+            return Response::err(error::Error::Other("client died"));
+        }
+
+        let resp_rx = resp_rx.map_err(|oneshot::Canceled| error::Error::Other("client likely died"));
+
+        let resp_rx = resp_rx.map(|r| r.into_stream_flag());
+
+        let resp_rx = resp_rx.flatten_stream();
+
+        Response::from_stream(resp_rx)
+    }
+
     pub fn start_request_with_resp_sender(
         &self,
         start: StartRequestMessage)
@@ -368,32 +459,13 @@ impl ClientConnection {
 }
 
 impl Service for ClientConnection {
-    // TODO: copy-paste with Client::start_request
     fn start_request(
         &self,
         headers: Headers,
         body: HttpPartStream)
             -> Response
     {
-        let (resp_tx, resp_rx) = oneshot::channel();
-
-        let start = StartRequestMessage {
-            headers: headers,
-            body: body,
-            resp_tx: resp_tx,
-        };
-
-        if let Err(_) = self.start_request_with_resp_sender(start) {
-            return Response::err(error::Error::Other("client died"));
-        }
-
-        let resp_rx = resp_rx.map_err(|oneshot::Canceled| error::Error::Other("client likely died"));
-
-        let resp_rx = resp_rx.map(|r| r.into_stream_flag());
-
-        let resp_rx = resp_rx.flatten_stream();
-
-        Response::from_stream(resp_rx)
+        self.start_request_with_options(headers, body, ClientRequestOptions::new())
     }
 }
 
@@ -1,5 +1,15 @@
-#[derive(Default, Debug, Clone)]
+use std::fmt;
+use std::sync::Arc;
+
+use metrics::Metrics;
+use frame_trace::FrameTracer;
+
+#[derive(Default, Clone)]
 pub struct CommonConf {
+    /// Receiver of connection events, e. g. `MetricsRegistry`
+    pub metrics: Option<Arc<Metrics>>,
+    /// Receiver of every sent and received frame, e. g. `TextFrameDump`
+    pub frame_tracer: Option<Arc<FrameTracer>>,
 }
 
 impl CommonConf {
@@ -7,3 +17,12 @@ impl CommonConf {
         Default::default()
     }
 }
+
+impl fmt::Debug for CommonConf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommonConf")
+            .field("metrics", &self.metrics.as_ref().map(|_| ".."))
+            .field("frame_tracer", &self.frame_tracer.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::cmp;
+use std::sync::Arc;
 
 use futures::future;
 use futures::future::Future;
@@ -18,6 +20,12 @@ use tokio_io::io as tokio_io;
 use exec::Executor;
 use exec::CpuPoolOption;
 
+use metrics;
+use metrics::Metrics;
+use metrics::NoMetrics;
+
+use frame_trace::ConnFrameTracer;
+
 use error;
 use error::ErrorCode;
 use result;
@@ -59,6 +67,7 @@ pub enum DirectlyToNetworkFrame {
     WindowUpdate(WindowUpdateFrame),
     Ping(PingFrame),
     Settings(SettingsFrame),
+    Goaway(GoawayFrame),
 }
 
 impl DirectlyToNetworkFrame {
@@ -68,6 +77,17 @@ impl DirectlyToNetworkFrame {
             DirectlyToNetworkFrame::WindowUpdate(f) => f.into(),
             DirectlyToNetworkFrame::Ping(f) => f.into(),
             DirectlyToNetworkFrame::Settings(f) => f.into(),
+            DirectlyToNetworkFrame::Goaway(f) => f.into(),
+        }
+    }
+
+    /// Error code of `GOAWAY` sent because of connection error
+    fn conn_error(&self) -> Option<ErrorCode> {
+        match self {
+            &DirectlyToNetworkFrame::Goaway(ref f) if f.error_code() != ErrorCode::NoError => {
+                Some(f.error_code())
+            }
+            _ => None,
         }
     }
 }
@@ -79,8 +99,12 @@ pub enum CommonToWriteMessage {
     Frame(DirectlyToNetworkFrame),    // write frame immediately to the network
     StreamEnqueue(StreamId, HttpStreamPart),
     StreamEnd(StreamId, ErrorCode),   // send when user provided handler completed the stream
+    StreamReset(StreamId, ErrorCode), // reset stream locally, e. g. when response is dropped
 }
 
+/// How many locally reset streams to remember
+const RECENTLY_RESET_STREAMS_MAX: usize = 100;
+
 pub trait ConnDataSpecific : 'static {
 }
 
@@ -107,6 +131,17 @@ pub struct ConnData<T : Types> {
     pub goaway_sent: Option<GoawayFrame>,
     pub goaway_received: Option<GoawayFrame>,
     pub ping_sent: Option<u64>,
+    /// Streams for which we sent `RST_STREAM`, frames on these streams are ignored
+    pub recently_reset_streams: VecDeque<StreamId>,
+    pub metrics: Arc<Metrics>,
+    /// Present if `CommonConf::frame_tracer` is set
+    pub frame_tracer: Option<ConnFrameTracer>,
+}
+
+impl<T : Types> Drop for ConnData<T> {
+    fn drop(&mut self) {
+        self.metrics.conn_closed();
+    }
 }
 
 
@@ -137,7 +172,7 @@ impl<T : Types> ConnData<T>
         loop_handle: reactor::Handle,
         exec: CpuPoolOption,
         specific: T::ConnDataSpecific,
-        _conf: CommonConf,
+        conf: CommonConf,
         sent_settings: HttpSettings,
         to_write_tx: UnboundedSender<T::ToWriteMessage>)
             -> ConnData<T>
@@ -147,6 +182,11 @@ impl<T : Types> ConnData<T>
 
         let pump_window_size = window_size::ConnOutWindowSender::new(conn.out_window_size.0 as u32);
 
+        let metrics = conf.metrics.unwrap_or_else(|| Arc::new(NoMetrics));
+        metrics.conn_opened();
+
+        let frame_tracer = conf.frame_tracer.map(ConnFrameTracer::new);
+
         ConnData {
             specific: specific,
             to_write_tx: to_write_tx,
@@ -159,7 +199,10 @@ impl<T : Types> ConnData<T>
             goaway_sent: None,
             goaway_received: None,
             ping_sent: None,
+            recently_reset_streams: VecDeque::new(),
             pump_out_window_size: pump_window_size,
+            metrics: metrics,
+            frame_tracer: frame_tracer,
         }
     }
 
@@ -197,6 +240,7 @@ impl<T : Types> ConnData<T>
             self.conn.peer_settings.initial_window_size,
             inc_tx,
             out_window_sender,
+            self.metrics.clone(),
             specific);
 
         let stream = self.streams.insert(stream_id, stream);
@@ -216,6 +260,7 @@ impl<T : Types> ConnData<T>
             stream_id: stream_id,
             to_write_tx: self.to_write_tx.clone(),
             in_window_size: in_window_size,
+            done: false,
         }
     }
 
@@ -319,6 +364,8 @@ impl<T : Types> ConnData<T>
                 }
             }
             HttpStreamCommand::Rst(error_code) => {
+                self.metrics.rst_stream_sent(error_code);
+
                 let frame = RstStreamFrame::new(stream_id, error_code);
 
                 debug!("sending frame {:?}", frame);
@@ -354,9 +401,17 @@ impl<T : Types> ConnData<T>
     }
 
     fn process_headers_frame(&mut self, self_rc: RcMut<Self>, frame: HeadersFrame) -> result::Result<Option<HttpStreamRef<T>>> {
-        let headers = self.conn.decoder
-            .decode(&frame.header_fragment())
-            .map_err(error::Error::CompressionError)?;
+        let headers = match self.conn.decoder.decode(&frame.header_fragment()) {
+            Ok(headers) => headers,
+            Err(e) => {
+                // 4.3
+                // A decoding error in a header block MUST be treated
+                // as a connection error of type COMPRESSION_ERROR.
+                warn!("failed to decode headers on stream {}: {:?}", frame.stream_id, e);
+                self.send_goaway(ErrorCode::CompressionError)?;
+                return Ok(None);
+            }
+        };
         let headers = Headers(headers.into_iter().map(|h| Header::new(h.0, h.1)).collect());
 
         let end_stream = if frame.is_end_of_stream() { EndStream::Yes } else { EndStream::No };
@@ -484,6 +539,8 @@ impl<T : Types> ConnData<T>
     fn process_rst_stream_frame(&mut self, frame: RstStreamFrame)
         -> result::Result<Option<HttpStreamRef<T>>>
     {
+        self.metrics.rst_stream_received(frame.error_code());
+
         if let Some(stream) = self.streams.get_mut(frame.get_stream_id()) {
             stream.rst_remove(frame.error_code());
         } else {
@@ -496,10 +553,33 @@ impl<T : Types> ConnData<T>
     pub fn send_rst_stream(&mut self, stream_id: StreamId, error_code: ErrorCode)
         -> result::Result<()>
     {
+        self.metrics.rst_stream_sent(error_code);
+
         let rst_stream = RstStreamFrame::new(stream_id, error_code);
         self.send_directly_to_network(DirectlyToNetworkFrame::RstStream(rst_stream))
     }
 
+    /// Reset stream by local side: remove the stream and send `RST_STREAM` to the peer
+    pub fn reset_stream_locally(&mut self, stream_id: StreamId, error_code: ErrorCode, error: error::Error)
+        -> result::Result<()>
+    {
+        if let Some(mut stream) = self.streams.get_mut(stream_id) {
+            debug!("resetting stream {} locally: {:?}", stream_id, error_code);
+            stream.stream().stats.rst_sent = Some(error_code);
+            stream.stream().reset_locally(error);
+            stream.remove_if_closed();
+        } else {
+            return Ok(());
+        }
+
+        if self.recently_reset_streams.len() == RECENTLY_RESET_STREAMS_MAX {
+            self.recently_reset_streams.pop_front();
+        }
+        self.recently_reset_streams.push_back(stream_id);
+
+        self.send_rst_stream(stream_id, error_code)
+    }
+
     pub fn get_stream_or_send_stream_closed(&mut self, stream_id: StreamId)
         -> result::Result<Option<HttpStreamRef<T>>>
     {
@@ -508,6 +588,14 @@ impl<T : Types> ConnData<T>
             return Ok(Some(self.streams.get_mut(stream_id).unwrap()));
         }
 
+        // 5.1
+        // An endpoint MUST ignore frames that it receives on closed streams
+        // after it has sent a RST_STREAM frame.
+        if self.recently_reset_streams.contains(&stream_id) {
+            debug!("ignoring frame on stream {} which was reset locally", stream_id);
+            return Ok(None);
+        }
+
         debug!("stream not found: {}, sending RST_STREAM", stream_id);
         self.send_rst_stream(stream_id, ErrorCode::StreamClosed)?;
 
@@ -584,6 +672,8 @@ impl<T : Types> ConnData<T>
         let last_stream_id = frame.last_stream_id;
         let raw_error_code = frame.raw_error_code;
 
+        self.metrics.goaway_received(ErrorCode::from(raw_error_code));
+
         self.goaway_received = Some(frame);
 
         for (stream_id, mut stream) in self.streams.remove_local_streams_with_id_gt(last_stream_id) {
@@ -624,7 +714,12 @@ impl<T : Types> ConnData<T>
             HttpFrameStream::Headers(headers) => self.process_headers_frame(self_rc, headers)?,
             HttpFrameStream::Priority(priority) => self.process_priority_frame(priority)?,
             HttpFrameStream::RstStream(rst) => self.process_rst_stream_frame(rst)?,
-            HttpFrameStream::PushPromise(_f) => unimplemented!(),
+            // 8.2: client disables push with SETTINGS_ENABLE_PUSH = 0,
+            // and client must never send PUSH_PROMISE
+            HttpFrameStream::PushPromise(_f) => {
+                warn!("unexpected PUSH_PROMISE on stream {}", stream_id);
+                return self.send_goaway(ErrorCode::ProtocolError);
+            }
             HttpFrameStream::WindowUpdate(window_update) => self.process_stream_window_update_frame(window_update)?,
             HttpFrameStream::Continuation(_continuation) => unreachable!("must be joined with HEADERS before that"),
         };
@@ -642,6 +737,10 @@ impl<T : Types> ConnData<T>
     fn process_http_frame(&mut self, self_rc: RcMut<Self>, frame: HttpFrame) -> result::Result<()> {
         // TODO: decode headers
         debug!("received frame: {:?}", frame);
+        self.metrics.frame_received(frame.frame_type(), FRAME_HEADER_LEN + frame.payload_len() as usize);
+        if let Some(ref mut tracer) = self.frame_tracer {
+            tracer.frame_received(&frame);
+        }
         match HttpFrameClassified::from(frame) {
             HttpFrameClassified::Conn(f) => self.process_conn_frame(f),
             HttpFrameClassified::Stream(f) => self.process_stream_frame(self_rc, f),
@@ -677,6 +776,28 @@ impl<T : Types> ConnData<T>
         self.send_directly_to_network(DirectlyToNetworkFrame::Settings(settings))
     }
 
+    /// Send `GOAWAY` with the last processed peer stream.
+    ///
+    /// With error code other than `NO_ERROR` frames are no longer read,
+    /// and the connection is closed once `GOAWAY` is written.
+    fn send_goaway(&mut self, error_code: ErrorCode) -> result::Result<()> {
+        if self.goaway_sent.is_some() {
+            return Ok(());
+        }
+
+        let goaway = GoawayFrame::new(self.last_peer_stream_id, error_code);
+        self.goaway_sent = Some(goaway.clone());
+        self.send_directly_to_network(DirectlyToNetworkFrame::Goaway(goaway))
+    }
+
+    /// `GOAWAY` was sent because of connection error
+    fn conn_error_sent(&self) -> bool {
+        match self.goaway_sent {
+            Some(ref f) => f.error_code() != ErrorCode::NoError,
+            None => false,
+        }
+    }
+
     /// Should we close the connection because of GOAWAY state
     pub fn end_loop(&self) -> bool {
         let goaway = self.goaway_sent.is_some() || self.goaway_received.is_some();
@@ -797,6 +918,11 @@ impl<I, T> ReadLoopData<I, T>
     }
 
     fn loop_iter(self) -> HttpFuture<Loop<(), Self>> {
+        if self.inner.with(|inner| inner.conn_error_sent()) {
+            // write loop closes the connection after `GOAWAY` is written
+            return Box::new(future::empty());
+        }
+
         if self.inner.with(|inner| inner.end_loop()) {
             return Box::new(future::err(error::Error::Other("GOAWAY")));
             //return Box::new(future::ok(Loop::Break(())));
@@ -827,6 +953,13 @@ impl<I, T> WriteLoopData<I, T>
         HttpStreamCommon<T> : HttpStream<Types=T>,
 {
     fn write_all(self, buf: Vec<u8>) -> HttpFuture<Self> {
+        self.inner.with(|inner| {
+            metrics::frames_sent(&*inner.metrics, &buf);
+            if let Some(ref mut tracer) = inner.frame_tracer {
+                tracer.frames_sent(&buf);
+            }
+        });
+
         let WriteLoopData { write, inner } = self;
 
         Box::new(tokio_io::write_all(write, buf)
@@ -903,6 +1036,15 @@ impl<I, T> WriteLoopData<I, T>
         Box::new(future::result(r.map(|()| self)))
     }
 
+    pub fn process_stream_reset(self, stream_id: StreamId, error_code: ErrorCode, error: error::Error)
+        -> HttpFuture<Self>
+    {
+        let r = self.inner.with(move |inner| {
+            inner.reset_stream_locally(stream_id, error_code, error)
+        });
+        Box::new(future::result(r.map(|()| self)))
+    }
+
     pub fn process_common(self, common: CommonToWriteMessage) -> HttpFuture<Self> {
         match common {
             CommonToWriteMessage::TryFlushStream(None) => {
@@ -912,7 +1054,13 @@ impl<I, T> WriteLoopData<I, T>
                 self.send_outg_stream(stream_id)
             },
             CommonToWriteMessage::Frame(frame) => {
-                self.write_frame(frame.into_http_frame())
+                match frame.conn_error() {
+                    Some(error_code) => {
+                        Box::new(self.write_frame(frame.into_http_frame())
+                            .and_then(move |_| Err(error::Error::CodeError(error_code))))
+                    }
+                    None => self.write_frame(frame.into_http_frame()),
+                }
             },
             CommonToWriteMessage::StreamEnd(stream_id, error_code) => {
                 self.process_stream_end(stream_id, error_code)
@@ -923,6 +1071,9 @@ impl<I, T> WriteLoopData<I, T>
             CommonToWriteMessage::IncreaseInWindow(stream_id, increase) => {
                 self.increase_in_window(stream_id, increase)
             },
+            CommonToWriteMessage::StreamReset(stream_id, error_code) => {
+                self.process_stream_reset(stream_id, error_code, error::Error::CodeError(error_code))
+            },
         }
     }
 }
@@ -11,6 +11,7 @@ use solicit::StreamId;
 use stream_part::HttpPartStream;
 use stream_part::HttpStreamPartContent;
 
+use error;
 use error::ErrorCode;
 
 use super::*;
@@ -48,7 +49,12 @@ impl<T : Types> Future for PumpStreamToWriteLoop<T> {
                 Ok(Async::Ready(r)) => r,
                 Err(e) => {
                     warn!("stream error: {:?}", e);
-                    let stream_end = CommonToWriteMessage::StreamEnd(self.stream_id, ErrorCode::InternalError);
+                    // Stream reset by the other peer of a proxy is reset with the same code
+                    let error_code = match e {
+                        error::Error::CodeError(error_code) => error_code,
+                        _ => ErrorCode::InternalError,
+                    };
+                    let stream_end = CommonToWriteMessage::StreamEnd(self.stream_id, error_code);
                     if let Err(e) = self.to_write_tx.unbounded_send(stream_end.into()) {
                         warn!("failed to write to channel, probably connection is closed: {:?}", e);
                     }
@@ -1,4 +1,5 @@
 use std::cmp;
+use std::sync::Arc;
 
 use bytes::Bytes;
 
@@ -15,6 +16,8 @@ use stream_part::*;
 
 use error::ErrorCode;
 
+use metrics::Metrics;
+
 use super::types::Types;
 
 use super::stream_queue::StreamQueue;
@@ -55,8 +58,23 @@ pub struct HttpStreamStateSnapshot {
 }
 
 
+/// Counters of the stream
+#[derive(Debug, Default, Clone)]
+pub struct HttpStreamStats {
+    /// Bytes of `DATA` frames received
+    pub in_data_bytes: u64,
+    /// Bytes of `DATA` frames sent
+    pub out_data_bytes: u64,
+    /// Error code of `RST_STREAM` received from the peer
+    pub rst_received: Option<ErrorCode>,
+    /// Error code of `RST_STREAM` sent to the peer
+    pub rst_sent: Option<ErrorCode>,
+}
+
+
 pub struct HttpStreamCommon<T : Types> {
     pub specific: T::HttpStreamSpecific,
+    pub stats: HttpStreamStats,
     pub state: StreamState,
     pub out_window_size: WindowSize,
     pub in_window_size: WindowSize,
@@ -64,6 +82,9 @@ pub struct HttpStreamCommon<T : Types> {
     pub peer_tx: Option<StreamQueueSyncSender>,
     // task waiting for window increase
     pub pump_out_window: window_size::StreamOutWindowSender,
+    /// Data is pending, but window is exhausted
+    pub out_window_stalled: bool,
+    pub metrics: Arc<Metrics>,
 }
 
 impl<T : Types> HttpStreamCommon<T> {
@@ -72,17 +93,22 @@ impl<T : Types> HttpStreamCommon<T> {
         out_window_size: u32,
         incoming: StreamQueueSyncSender,
         pump_out_window: window_size::StreamOutWindowSender,
+        metrics: Arc<Metrics>,
         specific: T::HttpStreamSpecific)
             -> HttpStreamCommon<T>
     {
+        metrics.stream_opened();
         HttpStreamCommon {
             specific: specific,
+            stats: HttpStreamStats::default(),
             state: StreamState::Open,
             in_window_size: WindowSize::new(in_window_size as i32),
             out_window_size: WindowSize::new(out_window_size as i32),
             outgoing: StreamQueue::new(),
             peer_tx: Some(incoming),
             pump_out_window: pump_out_window,
+            out_window_stalled: false,
+            metrics: metrics,
         }
     }
 
@@ -126,7 +152,10 @@ impl<T : Types> HttpStreamCommon<T> {
                         self.close_local();
                         Some(match error_code {
                             ErrorCode::NoError => HttpStreamCommand::Data(Bytes::new(), EndStream::Yes),
-                            error_code => HttpStreamCommand::Rst(error_code),
+                            error_code => {
+                                self.stats.rst_sent = Some(error_code);
+                                HttpStreamCommand::Rst(error_code)
+                            }
                         })
                     }
                 } else {
@@ -153,9 +182,15 @@ impl<T : Types> HttpStreamCommon<T> {
         }
 
         if self.out_window_size.size() <= 0 || conn_out_window_size.size() <= 0 {
+            if !self.out_window_stalled {
+                self.out_window_stalled = true;
+                self.metrics.window_stall();
+            }
             return None
         }
 
+        self.out_window_stalled = false;
+
         let mut data =
             if let Some(HttpStreamPartContent::Data(data)) = self.outgoing.pop_front() {
                 data
@@ -175,6 +210,7 @@ impl<T : Types> HttpStreamCommon<T> {
 
         self.out_window_size.try_decrease_to_positive(data.len() as i32).unwrap();
         conn_out_window_size.try_decrease_to_positive(data.len() as i32).unwrap();
+        self.stats.out_data_bytes += data.len() as u64;
 
         let last = self.outgoing.end() == Some(ErrorCode::NoError);
         if last {
@@ -196,6 +232,7 @@ impl<T : Types> HttpStreamCommon<T> {
     }
 
     pub fn new_data_chunk(&mut self, data: Bytes, last: bool) {
+        self.stats.in_data_bytes += data.len() as u64;
         if let Some(ref mut response_handler) = self.peer_tx {
             // TODO: reset stream if rx is dead
             drop(response_handler.send(ResultOrEof::Item(HttpStreamPart {
@@ -206,11 +243,21 @@ impl<T : Types> HttpStreamCommon<T> {
     }
 
     pub fn rst(&mut self, error_code: ErrorCode) {
+        self.stats.rst_received = Some(error_code);
         if let Some(ref mut response_handler) = self.peer_tx.take() {
             drop(response_handler.send(ResultOrEof::Error(error::Error::CodeError(error_code))));
         }
     }
 
+    /// Stream is reset by local side
+    pub fn reset_locally(&mut self, error: error::Error) {
+        if let Some(response_handler) = self.peer_tx.take() {
+            // it is OK to ignore error: handler may be already dead
+            drop(response_handler.send(ResultOrEof::Error(error)));
+        }
+        self.state = StreamState::Closed;
+    }
+
     pub fn goaway_recvd(&mut self, _raw_error_code: u32) {
         if let Some(response_handler) = self.peer_tx.take() {
             // it is OK to ignore error: handler may be already dead
@@ -220,7 +267,17 @@ impl<T : Types> HttpStreamCommon<T> {
 }
 
 
+impl<T : Types> Drop for HttpStreamCommon<T> {
+    fn drop(&mut self) {
+        self.specific.stream_closed(&self.stats);
+        self.metrics.stream_closed();
+    }
+}
+
+
 pub trait HttpStreamDataSpecific {
+    /// Stream is removed from the connection
+    fn stream_closed(&mut self, _stats: &HttpStreamStats) {}
 }
 
 pub trait HttpStream {
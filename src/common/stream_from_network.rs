@@ -11,6 +11,7 @@ use solicit::StreamId;
 use solicit::DEFAULT_SETTINGS;
 
 use error;
+use error::ErrorCode;
 
 use super::conn::CommonToWriteMessage;
 use super::types::Types;
@@ -24,6 +25,8 @@ pub struct StreamFromNetwork<T : Types> {
     pub stream_id: StreamId,
     pub to_write_tx: UnboundedSender<T::ToWriteMessage>,
     pub in_window_size: u32,
+    /// End of stream or error received
+    pub done: bool,
 }
 
 impl<T : Types> Stream for StreamFromNetwork<T> {
@@ -33,8 +36,14 @@ impl<T : Types> Stream for StreamFromNetwork<T> {
     fn poll(&mut self) -> Poll<Option<HttpStreamPart>, error::Error> {
         let part = match self.rx.poll() {
             Ok(Async::NotReady) => return Ok(Async::NotReady),
-            Err(e) => return Err(e),
-            Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+            Err(e) => {
+                self.done = true;
+                return Err(e);
+            }
+            Ok(Async::Ready(None)) => {
+                self.done = true;
+                return Ok(Async::Ready(None));
+            }
             Ok(Async::Ready(Some(part))) => part,
         };
 
@@ -60,6 +69,11 @@ impl<T : Types> Stream for StreamFromNetwork<T> {
 
 impl<T : Types> Drop for StreamFromNetwork<T> {
     fn drop(&mut self) {
-        // TODO: reset stream
+        // Stream is already half-closed (remote) if peer sent END_STREAM
+        if !self.done && !self.rx.closed_remote() && T::cancel_stream_on_drop() {
+            let m = CommonToWriteMessage::StreamReset(self.stream_id, ErrorCode::Cancel);
+            // ignore error, connection may be already dead
+            drop(self.to_write_tx.unbounded_send(m.into()));
+        }
     }
 }
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
@@ -20,6 +21,8 @@ use stream_part::*;
 
 struct Shared {
     data_size: AtomicUsize,
+    /// End of stream received from peer
+    closed_remote: AtomicBool,
 }
 
 pub struct StreamQueueSyncSender {
@@ -40,6 +43,10 @@ impl StreamQueueSyncSender {
             }
         }
 
+        if let ResultOrEof::Eof = item {
+            self.shared.closed_remote.store(true, Ordering::SeqCst);
+        }
+
         self.sender.unbounded_send(item).map_err(|_| ())
     }
 
@@ -60,6 +67,11 @@ impl StreamQueueSyncReceiver {
     pub fn data_size(&self) -> u32 {
         self.shared.data_size.load(Ordering::SeqCst) as u32
     }
+
+    /// Peer closed the stream, even if end of stream is not yet polled
+    pub fn closed_remote(&self) -> bool {
+        self.shared.closed_remote.load(Ordering::SeqCst)
+    }
 }
 
 impl Stream for StreamQueueSyncReceiver {
@@ -87,7 +99,8 @@ impl Stream for StreamQueueSyncReceiver {
 
 pub fn stream_queue_sync() -> (StreamQueueSyncSender, StreamQueueSyncReceiver) {
     let shared = Arc::new(Shared {
-        data_size: AtomicUsize::new(0)
+        data_size: AtomicUsize::new(0),
+        closed_remote: AtomicBool::new(false),
     });
 
     let (utx, urx) = unbounded();
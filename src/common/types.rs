@@ -13,6 +13,10 @@ pub trait Types : 'static {
     /// First stream id used by either client or server
     fn first_id() -> StreamId;
 
+    /// Send `RST_STREAM(CANCEL)` when receiver of the peer's stream
+    /// is dropped before the end of stream
+    fn cancel_stream_on_drop() -> bool;
+
     /// True if stream is initiated locally,
     /// e. g. `is_init_locally(3)` returns `true` for client and `false` for server.
     fn is_init_locally(stream_id: StreamId) -> bool {
@@ -0,0 +1,461 @@
+//! `content-encoding` support: response compression layer
+//! and transparent decompression of client responses
+
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use futures::Async;
+use futures::Poll;
+use futures::future::Future;
+use futures::stream::Stream;
+
+use bytes::Bytes;
+
+use flate2;
+use flate2::write::GzEncoder;
+use flate2::write::GzDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::write::ZlibDecoder;
+
+use brotli;
+
+use error::Error;
+use result::Result;
+use solicit::header::Header;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use stream_part::HttpStreamPart;
+use stream_part::HttpStreamPartContent;
+use resp::Response;
+use service_layer::Layer;
+use service_layer::Next;
+
+
+/// `accept-encoding` sent by client with `ClientConf::decompress`
+pub const ACCEPT_ENCODING: &'static str = "br, gzip, deflate";
+
+/// Content types which are not compressed, in addition to
+/// `image/*` (except SVG), `audio/*` and `video/*`
+const COMPRESSED_CONTENT_TYPES: &'static [&'static str] = &[
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/zstd",
+    "font/woff",
+    "font/woff2",
+];
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_LGWIN: u32 = 22;
+
+
+/// Supported `content-encoding` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    /// zlib format, as specified by HTTP
+    Deflate,
+    Brotli,
+}
+
+impl ContentEncoding {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ContentEncoding> {
+        match &name.trim().to_lowercase()[..] {
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            "deflate" => Some(ContentEncoding::Deflate),
+            "br" => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+}
+
+/// Choose encoding with highest `q` from `accept-encoding`,
+/// ties are resolved by order of `supported`
+fn negotiate(accept_encoding: &str, supported: &[ContentEncoding]) -> Option<ContentEncoding> {
+    let accepted: Vec<(String, f32)> = accept_encoding.split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let name = params.next().unwrap().trim().to_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|p| {
+                    let p = p.trim();
+                    if p.starts_with("q=") { p[2..].parse().ok() } else { None }
+                })
+                .next()
+                .unwrap_or(1.0);
+            Some((name, q))
+        })
+        .collect();
+
+    let q_of = |name: &str| {
+        accepted.iter().find(|&&(ref n, _)| n == name)
+            .or_else(|| accepted.iter().find(|&&(ref n, _)| n == "*"))
+            .map(|&(_, q)| q)
+            .unwrap_or(0.0)
+    };
+
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for &encoding in supported {
+        let q = q_of(encoding.name());
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+fn is_compressed_content_type(content_type: &str) -> bool {
+    let content_type = content_type.split(';').next().unwrap().trim().to_lowercase();
+    if content_type == "image/svg+xml" {
+        return false;
+    }
+    content_type.starts_with("image/")
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        // gRPC uses its own per-message compression
+        || content_type.starts_with("application/grpc")
+        || COMPRESSED_CONTENT_TYPES.contains(&&content_type[..])
+}
+
+/// Response can be compressed regardless of `accept-encoding`
+fn is_compressible(headers: &Headers) -> bool {
+    let status: u32 = headers.get_opt_parse(":status").unwrap_or(0);
+    // partial content ranges refer to uncompressed representation
+    status >= 200 && status != 204 && status != 206 && status != 304
+        && headers.get_opt("content-encoding").is_none()
+        && !headers.get_opt("cache-control").map_or(false, |c| c.contains("no-transform"))
+        && !headers.get_opt("content-type").map_or(false, is_compressed_content_type)
+}
+
+
+/// Output of encoder or decoder, taken after each write
+#[derive(Clone, Default)]
+struct OutBuf(Arc<Mutex<Vec<u8>>>);
+
+impl OutBuf {
+    fn take(&self) -> Bytes {
+        Bytes::from(mem::replace(&mut *self.0.lock().unwrap(), Vec::new()))
+    }
+}
+
+impl Write for OutBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Codec {
+    GzipEncoder(GzEncoder<OutBuf>),
+    DeflateEncoder(ZlibEncoder<OutBuf>),
+    BrotliEncoder(Box<brotli::CompressorWriter<OutBuf>>),
+    GzipDecoder(GzDecoder<OutBuf>),
+    DeflateDecoder(ZlibDecoder<OutBuf>),
+    BrotliDecoder(Box<brotli::DecompressorWriter<OutBuf>>),
+}
+
+impl Codec {
+    fn encoder(encoding: ContentEncoding, out: OutBuf) -> Codec {
+        match encoding {
+            ContentEncoding::Gzip =>
+                Codec::GzipEncoder(GzEncoder::new(out, flate2::Compression::default())),
+            ContentEncoding::Deflate =>
+                Codec::DeflateEncoder(ZlibEncoder::new(out, flate2::Compression::default())),
+            ContentEncoding::Brotli =>
+                Codec::BrotliEncoder(Box::new(brotli::CompressorWriter::new(
+                    out, BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_LGWIN))),
+        }
+    }
+
+    fn decoder(encoding: ContentEncoding, out: OutBuf) -> Codec {
+        match encoding {
+            ContentEncoding::Gzip => Codec::GzipDecoder(GzDecoder::new(out)),
+            ContentEncoding::Deflate => Codec::DeflateDecoder(ZlibDecoder::new(out)),
+            ContentEncoding::Brotli =>
+                Codec::BrotliDecoder(Box::new(brotli::DecompressorWriter::new(out, BROTLI_BUFFER_SIZE))),
+        }
+    }
+
+    fn writer(&mut self) -> &mut Write {
+        match *self {
+            Codec::GzipEncoder(ref mut w) => w,
+            Codec::DeflateEncoder(ref mut w) => w,
+            Codec::BrotliEncoder(ref mut w) => &mut **w,
+            Codec::GzipDecoder(ref mut w) => w,
+            Codec::DeflateDecoder(ref mut w) => w,
+            Codec::BrotliDecoder(ref mut w) => &mut **w,
+        }
+    }
+
+    /// Write data and flush, so the peer can process everything sent so far
+    fn write_flush(&mut self, data: &[u8]) -> io::Result<()> {
+        let writer = self.writer();
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Codec::GzipEncoder(w) => w.finish().map(|_| ()),
+            Codec::DeflateEncoder(w) => w.finish().map(|_| ()),
+            Codec::BrotliEncoder(w) => {
+                // finishes the stream
+                let w = *w;
+                w.into_inner();
+                Ok(())
+            }
+            Codec::GzipDecoder(mut w) => w.try_finish(),
+            Codec::DeflateDecoder(mut w) => w.try_finish(),
+            Codec::BrotliDecoder(mut w) => w.flush(),
+        }
+    }
+}
+
+/// Encode or decode DATA frames of a stream, trailers are passed as is
+struct CodecStream {
+    stream: HttpPartStream,
+    codec: Option<Codec>,
+    out: OutBuf,
+    /// Trailers returned after the last data chunk
+    trailers: Option<HttpStreamPart>,
+    done: bool,
+}
+
+impl CodecStream {
+    fn new<F>(stream: HttpPartStream, codec: F) -> CodecStream
+        where F : FnOnce(OutBuf) -> Codec
+    {
+        let out = OutBuf::default();
+        CodecStream {
+            stream: stream,
+            codec: Some(codec(out.clone())),
+            out: out,
+            trailers: None,
+            done: false,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<Bytes> {
+        match self.codec {
+            Some(ref mut codec) => codec.write_flush(data)?,
+            None => return Err(Error::InvalidFrame("DATA after trailers".to_owned())),
+        }
+        Ok(self.out.take())
+    }
+
+    fn finish(&mut self) -> Result<Bytes> {
+        if let Some(codec) = self.codec.take() {
+            codec.finish()?;
+        }
+        Ok(self.out.take())
+    }
+}
+
+impl Stream for CodecStream {
+    type Item = HttpStreamPart;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<HttpStreamPart>, Error> {
+        if let Some(trailers) = self.trailers.take() {
+            return Ok(Async::Ready(Some(trailers)));
+        }
+
+        loop {
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            // END_STREAM flag of data is dropped,
+            // because data is followed by the tail of encoder output
+            let HttpStreamPart { content, last } = match try_ready!(self.stream.poll()) {
+                Some(part) => part,
+                None => {
+                    self.done = true;
+                    let tail = self.finish()?;
+                    if tail.is_empty() {
+                        continue;
+                    }
+                    return Ok(Async::Ready(Some(HttpStreamPart::intermediate_data(tail))));
+                }
+            };
+
+            match content {
+                HttpStreamPartContent::Data(data) => {
+                    let out = self.write(&data)?;
+                    if !out.is_empty() {
+                        return Ok(Async::Ready(Some(HttpStreamPart::intermediate_data(out))));
+                    }
+                }
+                HttpStreamPartContent::Headers(headers) => {
+                    let trailers = HttpStreamPart {
+                        content: HttpStreamPartContent::Headers(headers),
+                        last: last,
+                    };
+                    let tail = self.finish()?;
+                    if tail.is_empty() {
+                        return Ok(Async::Ready(Some(trailers)));
+                    }
+                    self.trailers = Some(trailers);
+                    return Ok(Async::Ready(Some(HttpStreamPart::intermediate_data(tail))));
+                }
+            }
+        }
+    }
+}
+
+
+/// Compress responses with encoding negotiated with `accept-encoding`.
+///
+/// Each DATA chunk is flushed, so streaming responses are not delayed
+/// by the encoder. Responses with `content-encoding`, `cache-control: no-transform`,
+/// partial content and already compressed content types are not compressed.
+pub struct CompressionLayer {
+    /// Supported encodings in order of preference
+    pub encodings: Vec<ContentEncoding>,
+}
+
+impl CompressionLayer {
+    pub fn new() -> CompressionLayer {
+        CompressionLayer {
+            encodings: vec![ContentEncoding::Brotli, ContentEncoding::Gzip, ContentEncoding::Deflate],
+        }
+    }
+}
+
+impl Layer for CompressionLayer {
+    fn start_request(&self, headers: Headers, req: HttpPartStream, next: Next) -> Response {
+        let encoding = match headers.get_opt(":method") {
+            Some("HEAD") => None,
+            _ => headers.get_opt("accept-encoding").and_then(|a| negotiate(a, &self.encodings)),
+        };
+
+        let response = next.start_request(headers, req);
+        Response::new(response.0.map(move |(mut headers, body)| {
+            if !is_compressible(&headers) {
+                return (headers, body);
+            }
+
+            let vary = headers.0.iter()
+                .filter(|h| h.name() == b"vary")
+                .any(|h| {
+                    let vary = String::from_utf8_lossy(h.value()).to_lowercase();
+                    vary.split(',').any(|v| v.trim() == "accept-encoding" || v.trim() == "*")
+                });
+            if !vary {
+                headers.add("vary", "accept-encoding");
+            }
+
+            let encoding = match encoding {
+                Some(encoding) => encoding,
+                None => return (headers, body),
+            };
+
+            headers.0.retain(|h| h.name() != b"content-length");
+            headers.add("content-encoding", encoding.name());
+            // encoded representation is not byte-for-byte identical
+            for header in &mut headers.0 {
+                if header.name() == b"etag" && header.value().starts_with(b"\"") {
+                    let weak = format!("W/{}", String::from_utf8_lossy(header.value()));
+                    *header = Header::new("etag", weak);
+                }
+            }
+
+            let body = CodecStream::new(body, |out| Codec::encoder(encoding, out));
+            (headers, HttpPartStream::new(body))
+        }))
+    }
+}
+
+/// Decode response body according to `content-encoding`,
+/// responses with unsupported encodings are returned as is
+pub fn decompress_response(response: Response) -> Response {
+    Response::new(response.0.map(|(mut headers, body)| {
+        let encoding = headers.get_opt("content-encoding").and_then(ContentEncoding::from_name);
+        match encoding {
+            Some(encoding) => {
+                headers.0.retain(|h| h.name() != b"content-encoding" && h.name() != b"content-length");
+                let body = CodecStream::new(body, |out| Codec::decoder(encoding, out));
+                (headers, HttpPartStream::new(body))
+            }
+            None => (headers, body),
+        }
+    }))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::stream;
+
+    #[test]
+    fn negotiate_encoding() {
+        let all = [ContentEncoding::Brotli, ContentEncoding::Gzip, ContentEncoding::Deflate];
+        assert_eq!(Some(ContentEncoding::Brotli), negotiate("gzip, deflate, br", &all));
+        assert_eq!(Some(ContentEncoding::Gzip), negotiate("gzip;q=1.0, br;q=0.5", &all));
+        assert_eq!(Some(ContentEncoding::Deflate), negotiate("deflate", &all));
+        assert_eq!(Some(ContentEncoding::Gzip), negotiate("*, br;q=0", &all[1..]));
+        assert_eq!(None, negotiate("br;q=0, identity", &all));
+        assert_eq!(None, negotiate("", &all));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut trailers = Headers::new();
+        trailers.add("x-trailer", "1");
+
+        for &encoding in &[ContentEncoding::Gzip, ContentEncoding::Deflate, ContentEncoding::Brotli] {
+            let parts = vec![
+                HttpStreamPart::intermediate_data(Bytes::from(&b"hello "[..])),
+                HttpStreamPart::intermediate_data(Bytes::from(&b"world"[..])),
+                HttpStreamPart::last_headers(trailers.clone()),
+            ];
+            let body = HttpPartStream::new(stream::iter_ok(parts));
+            let encoded = CodecStream::new(body, |out| Codec::encoder(encoding, out));
+            let decoded = CodecStream::new(HttpPartStream::new(encoded), |out| Codec::decoder(encoding, out));
+
+            let parts: Vec<HttpStreamPart> = decoded.collect().wait().unwrap();
+            let mut data = Vec::new();
+            for part in &parts[..parts.len() - 1] {
+                match part.content {
+                    HttpStreamPartContent::Data(ref d) => data.extend_from_slice(d),
+                    _ => panic!("expecting data"),
+                }
+            }
+            assert_eq!(&b"hello world"[..], &data[..], "{:?}", encoding);
+            assert!(parts.last().unwrap().last);
+        }
+    }
+
+    #[test]
+    fn compressed_content_types() {
+        assert!(is_compressed_content_type("image/png"));
+        assert!(is_compressed_content_type("application/grpc+proto"));
+        assert!(!is_compressed_content_type("image/svg+xml"));
+        assert!(!is_compressed_content_type("text/html; charset=utf-8"));
+    }
+}
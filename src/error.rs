@@ -8,12 +8,14 @@ use hpack::decoder::DecoderError;
 
 use tls_api;
 
+use grpc::GrpcStatus;
+
 use tokio_timer::TimeoutError;
 
 /// The enum represents an error code that are used in `RST_STREAM` and `GOAWAY` frames.
 /// These are defined in [Section 7](http://http2.github.io/http2-spec/#ErrorCodes) of the HTTP/2
 /// spec.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ErrorCode {
     /// The associated condition is not a result of an error. For example, a GOAWAY might include
     /// this code to indicate graceful shutdown of a connection.
@@ -124,11 +126,18 @@ pub enum Error {
     WindowSizeOverflow,
     UnknownStreamId,
     UnableToConnect,
+    /// TLS peer did not negotiate `h2` protocol with ALPN,
+    /// field is protocol negotiated instead
+    AlpnMismatch(Option<Vec<u8>>),
     MalformedResponse,
     ConnectionTimeout,
+    /// Request deadline expired, stream was cancelled
+    RequestTimeout,
     /// Shutdown of local client or server
     Shutdown,
     HandlerPanicked(String),
+    /// gRPC call failed with non-OK status
+    GrpcError(GrpcStatus),
     Other(&'static str),
 }
 
@@ -174,10 +183,13 @@ impl StdError for Error {
             Error::WindowSizeOverflow => "The connection flow control window overflowed",
             Error::UnknownStreamId => "Attempted an operation with an unknown HTTP/2 stream ID",
             Error::UnableToConnect => "An error attempting to establish an HTTP/2 connection",
+            Error::AlpnMismatch(_) => "TLS peer did not negotiate h2 protocol",
             Error::MalformedResponse => "The received response was malformed",
             Error::ConnectionTimeout => "Connection time out",
+            Error::RequestTimeout => "Request deadline expired",
             Error::Shutdown => "Local shutdown",
             Error::HandlerPanicked(_) => "Handler panicked",
+            Error::GrpcError(_) => "gRPC call failed",
             Error::Other(_) => "An unknown error",
         }
     }
@@ -0,0 +1,525 @@
+//! Frame-level tracing of connections
+
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use hpack;
+
+use solicit::StreamId;
+use solicit::header::Header;
+use solicit::header::Headers;
+use solicit::frame::Frame;
+use solicit::frame::FrameIR;
+use solicit::frame::RawFrame;
+use solicit::frame::FRAME_HEADER_LEN;
+use solicit::frame::headers::HeadersFlag;
+use solicit::frame::continuation::ContinuationFlag;
+use solicit::connection::HttpFrame;
+use solicit::connection::HttpFrameType;
+
+
+/// Headers which values are replaced with `REDACTED` in traces
+pub const SENSITIVE_HEADERS: &'static [&'static str] =
+    &["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+pub const REDACTED: &'static str = "<redacted>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    Received,
+    Sent,
+}
+
+/// Frame passed to `FrameTracer`
+#[derive(Debug)]
+pub struct TracedFrame<'a> {
+    pub direction: FrameDirection,
+    pub time: SystemTime,
+    /// Connection id, unique within the process
+    pub conn_id: u64,
+    pub stream_id: StreamId,
+    pub frame_type: HttpFrameType,
+    pub flags: u8,
+    /// Serialized frame including frame header
+    pub bytes: &'a [u8],
+    /// Parsed frame, `None` if frame is malformed
+    pub frame: Option<&'a HttpFrame>,
+    /// Decoded header block with `SENSITIVE_HEADERS` redacted,
+    /// present on the frame which completes a header block
+    /// (HEADERS or the last CONTINUATION)
+    pub headers: Option<&'a Headers>,
+}
+
+/// Receiver of every frame sent and received by `Client` or `Server` connections.
+///
+/// Received frames are traced after CONTINUATION frames are joined into HEADERS.
+/// Connection preface and initial SETTINGS frame sent during handshake are not traced.
+///
+/// Tracer is invoked from event loop threads, and it slows connections down,
+/// so it is meant for debugging.
+pub trait FrameTracer : Send + Sync + 'static {
+    fn frame(&self, frame: &TracedFrame);
+}
+
+
+static NEXT_CONN_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// HPACK decoder state of one direction of connection
+struct HeaderBlockDecoder {
+    decoder: hpack::Decoder<'static>,
+    /// Fragments of header block without END_HEADERS yet
+    block: Vec<u8>,
+}
+
+impl HeaderBlockDecoder {
+    fn new() -> HeaderBlockDecoder {
+        HeaderBlockDecoder {
+            decoder: hpack::Decoder::new(),
+            block: Vec::new(),
+        }
+    }
+
+    /// Feed a fragment, return decoded headers if it completes the block
+    fn fragment(&mut self, fragment: &[u8], end_headers: bool) -> Option<Headers> {
+        self.block.extend_from_slice(fragment);
+        if !end_headers {
+            return None;
+        }
+
+        let block = ::std::mem::replace(&mut self.block, Vec::new());
+        let headers = match self.decoder.decode(&block) {
+            Ok(headers) => headers,
+            Err(e) => {
+                warn!("frame tracer failed to decode headers: {:?}", e);
+                return None;
+            }
+        };
+
+        Some(Headers(headers.into_iter().map(|(n, v)| {
+            let sensitive = SENSITIVE_HEADERS.iter().any(|s| s.as_bytes() == &n[..]);
+            if sensitive {
+                Header::new(n, REDACTED)
+            } else {
+                Header::new(n, v)
+            }
+        }).collect()))
+    }
+}
+
+/// `FrameTracer` of one connection
+pub struct ConnFrameTracer {
+    tracer: Arc<FrameTracer>,
+    conn_id: u64,
+    received: HeaderBlockDecoder,
+    sent: HeaderBlockDecoder,
+}
+
+impl ConnFrameTracer {
+    pub fn new(tracer: Arc<FrameTracer>) -> ConnFrameTracer {
+        ConnFrameTracer {
+            tracer: tracer,
+            conn_id: NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed) as u64,
+            received: HeaderBlockDecoder::new(),
+            sent: HeaderBlockDecoder::new(),
+        }
+    }
+
+    pub fn frame_received(&mut self, frame: &HttpFrame) {
+        let raw_frame = RawFrame::from(frame.clone().serialize_into_vec());
+        self.trace(FrameDirection::Received, &raw_frame, Some(frame));
+    }
+
+    /// Trace frames contained in serialized bytes
+    pub fn frames_sent(&mut self, mut bytes: &[u8]) {
+        while bytes.len() >= FRAME_HEADER_LEN {
+            let payload_len =
+                ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | (bytes[2] as usize);
+            let len = cmp::min(FRAME_HEADER_LEN + payload_len, bytes.len());
+            let raw_frame = RawFrame::from(&bytes[..len]);
+            let frame = HttpFrame::from_raw(&raw_frame).ok();
+            self.trace(FrameDirection::Sent, &raw_frame, frame.as_ref());
+            bytes = &bytes[len..];
+        }
+    }
+
+    fn trace(&mut self, direction: FrameDirection, raw_frame: &RawFrame, frame: Option<&HttpFrame>) {
+        let headers = {
+            let decoder = match direction {
+                FrameDirection::Received => &mut self.received,
+                FrameDirection::Sent => &mut self.sent,
+            };
+
+            match frame {
+                Some(&HttpFrame::Headers(ref f)) =>
+                    decoder.fragment(f.header_fragment(), f.flags().is_set(HeadersFlag::EndHeaders)),
+                Some(&HttpFrame::Continuation(ref f)) =>
+                    decoder.fragment(&f.header_fragment, f.flags().is_set(ContinuationFlag::EndHeaders)),
+                _ => None,
+            }
+        };
+
+        let header = raw_frame.header();
+
+        self.tracer.frame(&TracedFrame {
+            direction: direction,
+            time: SystemTime::now(),
+            conn_id: self.conn_id,
+            stream_id: header.stream_id,
+            frame_type: HttpFrameType::from_u8(header.frame_type),
+            flags: header.flags,
+            bytes: &raw_frame.serialize()[..],
+            frame: frame,
+            headers: headers.as_ref(),
+        });
+    }
+}
+
+
+fn since_epoch(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0))
+}
+
+fn frame_flags(frame: &HttpFrame) -> String {
+    match frame {
+        &HttpFrame::Data(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::Headers(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::Settings(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::PushPromise(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::Ping(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::Continuation(ref f) => format!("{:?}", f.flags()),
+        &HttpFrame::Priority(..) |
+        &HttpFrame::RstStream(..) |
+        &HttpFrame::Goaway(..) |
+        &HttpFrame::WindowUpdate(..) => "0".to_owned(),
+        &HttpFrame::Unknown(ref f) => format!("{:#x}", f.header().flags),
+    }
+}
+
+fn frame_details(frame: &HttpFrame) -> String {
+    match frame {
+        &HttpFrame::Settings(ref f) => format!(" {:?}", f.settings),
+        &HttpFrame::Ping(ref f) => format!(" data={:016x}", f.opaque_data()),
+        &HttpFrame::RstStream(ref f) => format!(" error={:?}", f.error_code()),
+        &HttpFrame::Goaway(ref f) =>
+            format!(" last_stream={} error={:?}", f.last_stream_id(), f.error_code()),
+        &HttpFrame::WindowUpdate(ref f) => format!(" increment={}", f.increment),
+        &HttpFrame::Priority(ref f) =>
+            format!(" dep={} weight={} exclusive={}", f.stream_dep, f.weight, f.exclusive),
+        _ => String::new(),
+    }
+}
+
+/// Format frame as text lines: one line for frame, and one line per header
+pub fn format_frame(frame: &TracedFrame) -> String {
+    let time = since_epoch(frame.time);
+    let direction = match frame.direction {
+        FrameDirection::Received => "recv",
+        FrameDirection::Sent => "send",
+    };
+
+    format!(
+        "{}.{:06} conn={} {} {}",
+        time.as_secs(), time.subsec_nanos() / 1000,
+        frame.conn_id,
+        direction,
+        format_frame_contents(frame))
+}
+
+/// Like `format_frame`, but without time, connection and direction
+pub fn format_frame_contents(frame: &TracedFrame) -> String {
+    let (flags, details) = match frame.frame {
+        Some(f) => (frame_flags(f), frame_details(f)),
+        None => (format!("{:#x}", frame.flags), " malformed".to_owned()),
+    };
+
+    let mut r = format!(
+        "{} stream={} flags={} len={}{}\n",
+        frame.frame_type.name(),
+        frame.stream_id,
+        flags,
+        frame.bytes.len() - FRAME_HEADER_LEN,
+        details);
+
+    if let Some(headers) = frame.headers {
+        for header in &headers.0 {
+            r.push_str(&format!("    {}: {}\n",
+                String::from_utf8_lossy(header.name()),
+                String::from_utf8_lossy(header.value())));
+        }
+    }
+
+    r
+}
+
+
+/// Tracer which writes frames as text, see `format_frame`
+pub struct TextFrameDump<W : Write + Send + 'static> {
+    write: Mutex<W>,
+}
+
+impl<W : Write + Send + 'static> TextFrameDump<W> {
+    pub fn new(write: W) -> TextFrameDump<W> {
+        TextFrameDump {
+            write: Mutex::new(write),
+        }
+    }
+}
+
+impl<W : Write + Send + 'static> FrameTracer for TextFrameDump<W> {
+    fn frame(&self, frame: &TracedFrame) {
+        let text = format_frame(frame);
+        if let Err(e) = self.write.lock().unwrap().write_all(text.as_bytes()) {
+            warn!("failed to write frame dump: {:?}", e);
+        }
+    }
+}
+
+
+/// First bytes of file written by `FrameCapture`, last byte is format version
+pub const CAPTURE_MAGIC: &'static [u8; 8] = b"h2cap\0\0\x01";
+
+/// Tracer which writes frames in binary format readable by `CaptureReader`.
+///
+/// File is `CAPTURE_MAGIC` followed by records:
+/// direction (1 byte, 0 is received, 1 is sent), connection id (8 bytes),
+/// microseconds since epoch (8 bytes), and serialized frame.
+/// Integers are big-endian.
+pub struct FrameCapture<W : Write + Send + 'static> {
+    write: Mutex<W>,
+}
+
+impl FrameCapture<File> {
+    /// Create or truncate capture file
+    pub fn create<P : AsRef<Path>>(path: P) -> io::Result<FrameCapture<File>> {
+        FrameCapture::new(File::create(path)?)
+    }
+}
+
+impl<W : Write + Send + 'static> FrameCapture<W> {
+    pub fn new(mut write: W) -> io::Result<FrameCapture<W>> {
+        write.write_all(CAPTURE_MAGIC)?;
+        Ok(FrameCapture {
+            write: Mutex::new(write),
+        })
+    }
+
+    /// Flush and return underlying writer
+    pub fn into_inner(self) -> io::Result<W> {
+        let mut write = self.write.into_inner().unwrap();
+        write.flush()?;
+        Ok(write)
+    }
+}
+
+fn u64_be(n: u64) -> [u8; 8] {
+    let mut r = [0; 8];
+    for i in 0..8 {
+        r[i] = (n >> ((7 - i) * 8)) as u8;
+    }
+    r
+}
+
+impl<W : Write + Send + 'static> FrameTracer for FrameCapture<W> {
+    fn frame(&self, frame: &TracedFrame) {
+        let time = since_epoch(frame.time);
+        let micros = time.as_secs() * 1_000_000 + (time.subsec_nanos() / 1000) as u64;
+
+        let mut record = Vec::with_capacity(17 + frame.bytes.len());
+        record.push(match frame.direction {
+            FrameDirection::Received => 0,
+            FrameDirection::Sent => 1,
+        });
+        record.extend_from_slice(&u64_be(frame.conn_id));
+        record.extend_from_slice(&u64_be(micros));
+        record.extend_from_slice(frame.bytes);
+
+        if let Err(e) = self.write.lock().unwrap().write_all(&record) {
+            warn!("failed to write frame capture: {:?}", e);
+        }
+    }
+}
+
+/// Frame read by `CaptureReader`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    pub direction: FrameDirection,
+    pub conn_id: u64,
+    pub time: SystemTime,
+    pub frame: RawFrame,
+}
+
+impl fmt::Display for CapturedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frame = HttpFrame::from_raw(&self.frame).ok();
+        let header = self.frame.header();
+        let traced = TracedFrame {
+            direction: self.direction,
+            time: self.time,
+            conn_id: self.conn_id,
+            stream_id: header.stream_id,
+            frame_type: HttpFrameType::from_u8(header.frame_type),
+            flags: header.flags,
+            bytes: &self.frame.serialize()[..],
+            frame: frame.as_ref(),
+            headers: None,
+        };
+        write!(f, "{}", format_frame(&traced).trim_right())
+    }
+}
+
+/// Read file written by `FrameCapture`
+pub struct CaptureReader<R : Read> {
+    read: R,
+}
+
+impl<R : Read> CaptureReader<R> {
+    /// Check magic and create reader
+    pub fn new(mut read: R) -> io::Result<CaptureReader<R>> {
+        let mut magic = [0; 8];
+        read.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a frame capture"));
+        }
+        Ok(CaptureReader { read: read })
+    }
+
+    /// Read next frame, `None` on EOF
+    pub fn read_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        let mut record_header = [0; 17 + FRAME_HEADER_LEN];
+        match self.read.read(&mut record_header[..1])? {
+            0 => return Ok(None),
+            _ => {}
+        }
+        self.read.read_exact(&mut record_header[1..])?;
+
+        let direction = match record_header[0] {
+            0 => FrameDirection::Received,
+            1 => FrameDirection::Sent,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid direction")),
+        };
+        let u64_at = |pos: usize| {
+            record_header[pos..pos + 8].iter().fold(0u64, |r, &b| (r << 8) | b as u64)
+        };
+        let conn_id = u64_at(1);
+        let micros = u64_at(9);
+
+        let frame_header = &record_header[17..];
+        let payload_len =
+            ((frame_header[0] as usize) << 16) | ((frame_header[1] as usize) << 8) | (frame_header[2] as usize);
+        let mut frame = frame_header.to_vec();
+        frame.resize(FRAME_HEADER_LEN + payload_len, 0);
+        self.read.read_exact(&mut frame[FRAME_HEADER_LEN..])?;
+
+        Ok(Some(CapturedFrame {
+            direction: direction,
+            conn_id: conn_id,
+            time: UNIX_EPOCH + Duration::new(micros / 1_000_000, (micros % 1_000_000) as u32 * 1000),
+            frame: RawFrame::from(frame),
+        }))
+    }
+}
+
+impl<R : Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<io::Result<CapturedFrame>> {
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hpack::Encoder;
+    use solicit::frame::headers::HeadersFrame;
+    use solicit::frame::continuation::ContinuationFrame;
+    use solicit::frame::data::DataFrame;
+    use solicit::frame::data::DataFlag;
+    use solicit::frame::settings::SettingsFrame;
+
+    struct Collect(Mutex<Vec<String>>);
+
+    impl FrameTracer for Collect {
+        fn frame(&self, frame: &TracedFrame) {
+            // skip time
+            let text = format_frame(frame);
+            let text = text.splitn(2, ' ').nth(1).unwrap().to_owned();
+            self.0.lock().unwrap().push(text);
+        }
+    }
+
+    #[test]
+    fn headers_continuation_redacted() {
+        let collect = Arc::new(Collect(Mutex::new(Vec::new())));
+        let mut tracer = ConnFrameTracer::new(collect.clone());
+
+        let block = Encoder::new().encode(vec![
+            (&b":path"[..], &b"/x"[..]),
+            (&b"authorization"[..], &b"secret"[..]),
+        ]);
+
+        let headers = HeadersFrame::new(&block[..3], 1);
+        let mut continuation = ContinuationFrame::new(&block[3..], 1);
+        continuation.set_flag(ContinuationFlag::EndHeaders);
+        let mut data = DataFrame::with_data(1, &b"abc"[..]);
+        data.set_flag(DataFlag::EndStream);
+
+        let mut bytes = headers.serialize_into_vec();
+        bytes.extend(continuation.serialize_into_vec());
+        bytes.extend(data.serialize_into_vec());
+        tracer.frames_sent(&bytes);
+
+        tracer.frame_received(&HttpFrame::Settings(SettingsFrame::new_ack()));
+
+        let id = tracer.conn_id;
+        assert_eq!(
+            vec![
+                format!("conn={} send HEADERS stream=1 flags=0 len=3\n", id),
+                format!("conn={} send CONTINUATION stream=1 flags=EndHeaders len={}\n    :path: /x\n    authorization: <redacted>\n", id, block.len() - 3),
+                format!("conn={} send DATA stream=1 flags=EndStream len=3\n", id),
+                format!("conn={} recv SETTINGS stream=0 flags=Ack len=0 []\n", id),
+            ],
+            *collect.0.lock().unwrap());
+    }
+
+    #[test]
+    fn capture_read_write() {
+        let capture = Arc::new(FrameCapture::new(Vec::new()).unwrap());
+        let mut tracer = ConnFrameTracer::new(capture.clone());
+
+        let data = DataFrame::with_data(3, &b"abc"[..]).serialize_into_vec();
+        tracer.frames_sent(&data);
+        tracer.frame_received(&HttpFrame::Settings(SettingsFrame::new_ack()));
+        drop(tracer);
+
+        let bytes = Arc::try_unwrap(capture).ok().unwrap().into_inner().unwrap();
+
+        let frames: Vec<CapturedFrame> = CaptureReader::new(&bytes[..]).unwrap()
+            .map(|f| f.unwrap())
+            .collect();
+        assert_eq!(2, frames.len());
+        assert_eq!(FrameDirection::Sent, frames[0].direction);
+        assert_eq!(&data[..], &frames[0].frame.serialize()[..]);
+        assert_eq!(FrameDirection::Received, frames[1].direction);
+        assert_eq!(frames[0].conn_id, frames[1].conn_id);
+        assert_eq!(HttpFrameType::Settings, HttpFrameType::from_u8(frames[1].frame.header().frame_type));
+    }
+}
@@ -0,0 +1,622 @@
+//! gRPC message framing over `HttpPartStream`.
+//!
+//! gRPC messages are sent in DATA frames prefixed with compressed flag byte
+//! and 4-byte big-endian length. Messages may be split across DATA frames,
+//! or several messages may be packed into one frame. Call status is sent
+//! in `grpc-status` and `grpc-message` trailers.
+
+use std::cmp;
+use std::fmt;
+use std::io::Read;
+use std::time::Duration;
+
+use futures::Async;
+use futures::Poll;
+use futures::future::Future;
+use futures::stream;
+use futures::stream::Stream;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+
+use flate2;
+
+use brotli;
+
+use compression::ContentEncoding;
+use error::Error;
+use result::Result;
+use solicit::HttpScheme;
+use solicit::header::Header;
+use solicit::header::Headers;
+use solicit_async::HttpFutureStreamSend;
+use stream_part::HttpPartStream;
+use stream_part::HttpStreamPart;
+use stream_part::HttpStreamPartContent;
+use resp::Response;
+
+
+/// Compressed flag and message length
+pub const MESSAGE_HEADER_SIZE: usize = 5;
+
+/// Default limit of received message size, same as in other gRPC implementations
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+pub const CONTENT_TYPE: &'static str = "application/grpc";
+
+/// gRPC status codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcCode {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl GrpcCode {
+    /// Unknown codes are mapped to `Unknown`
+    pub fn from_u32(code: u32) -> GrpcCode {
+        match code {
+            0 => GrpcCode::Ok,
+            1 => GrpcCode::Cancelled,
+            3 => GrpcCode::InvalidArgument,
+            4 => GrpcCode::DeadlineExceeded,
+            5 => GrpcCode::NotFound,
+            6 => GrpcCode::AlreadyExists,
+            7 => GrpcCode::PermissionDenied,
+            8 => GrpcCode::ResourceExhausted,
+            9 => GrpcCode::FailedPrecondition,
+            10 => GrpcCode::Aborted,
+            11 => GrpcCode::OutOfRange,
+            12 => GrpcCode::Unimplemented,
+            13 => GrpcCode::Internal,
+            14 => GrpcCode::Unavailable,
+            15 => GrpcCode::DataLoss,
+            16 => GrpcCode::Unauthenticated,
+            _ => GrpcCode::Unknown,
+        }
+    }
+
+    /// Code for response with HTTP status other than 200,
+    /// as specified in gRPC `http-grpc-status-mapping.md`
+    pub fn from_http_status(status: u32) -> GrpcCode {
+        match status {
+            400 => GrpcCode::Internal,
+            401 => GrpcCode::Unauthenticated,
+            403 => GrpcCode::PermissionDenied,
+            404 => GrpcCode::Unimplemented,
+            429 | 502 | 503 | 504 => GrpcCode::Unavailable,
+            _ => GrpcCode::Unknown,
+        }
+    }
+}
+
+/// Status of gRPC call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcStatus {
+    pub code: GrpcCode,
+    pub message: String,
+}
+
+/// Percent-encode `grpc-message` value
+fn encode_grpc_message(message: &str) -> String {
+    let mut r = String::new();
+    for &b in message.as_bytes() {
+        if b >= 0x20 && b < 0x7f && b != b'%' {
+            r.push(b as char);
+        } else {
+            r.push_str(&format!("%{:02X}", b));
+        }
+    }
+    r
+}
+
+/// Decode `grpc-message`, invalid escapes are kept as is
+fn decode_grpc_message(message: &str) -> String {
+    let bytes = message.as_bytes();
+    let mut r = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = if bytes[i] == b'%' {
+            message.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match decoded {
+            Some(b) => {
+                r.push(b);
+                i += 3;
+            }
+            None => {
+                r.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&r).into_owned()
+}
+
+impl GrpcStatus {
+    pub fn new<S : Into<String>>(code: GrpcCode, message: S) -> GrpcStatus {
+        GrpcStatus {
+            code: code,
+            message: message.into(),
+        }
+    }
+
+    pub fn ok() -> GrpcStatus {
+        GrpcStatus::new(GrpcCode::Ok, "")
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.code == GrpcCode::Ok
+    }
+
+    /// Parse `grpc-status` and `grpc-message` from trailers
+    /// or headers of trailers-only response
+    pub fn from_headers(headers: &Headers) -> Option<GrpcStatus> {
+        let code = match headers.get_opt("grpc-status") {
+            Some(code) => match code.trim().parse() {
+                Ok(code) => GrpcCode::from_u32(code),
+                Err(_) => GrpcCode::Unknown,
+            },
+            None => return None,
+        };
+        let message = headers.get_opt("grpc-message").map(decode_grpc_message).unwrap_or_default();
+        Some(GrpcStatus::new(code, message))
+    }
+
+    /// Status trailers
+    pub fn to_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        headers.add("grpc-status", &format!("{}", self.code as u32));
+        if !self.message.is_empty() {
+            headers.add("grpc-message", &encode_grpc_message(&self.message));
+        }
+        headers
+    }
+
+    /// Status to send for failed response stream
+    fn from_error(error: Error) -> GrpcStatus {
+        match error {
+            Error::GrpcError(status) => status,
+            Error::RequestTimeout => GrpcStatus::new(GrpcCode::DeadlineExceeded, "deadline exceeded"),
+            e => GrpcStatus::new(GrpcCode::Internal, format!("{:?}", e)),
+        }
+    }
+}
+
+impl fmt::Display for GrpcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+
+/// Headers of gRPC request, `path` is `/{service}/{method}`
+pub fn request_headers(scheme: HttpScheme, authority: &str, path: &str) -> Headers {
+    Headers(vec![
+        Header::new(":method", "POST"),
+        Header::new(":scheme", scheme.as_bytes()),
+        Header::new(":path", path.to_owned()),
+        Header::new(":authority", authority.to_owned()),
+        Header::new("content-type", CONTENT_TYPE),
+        Header::new("te", "trailers"),
+    ])
+}
+
+/// Headers of successful gRPC response, status is sent in trailers
+pub fn response_headers() -> Headers {
+    let mut headers = Headers::ok_200();
+    headers.add("content-type", CONTENT_TYPE);
+    headers
+}
+
+/// Set `grpc-timeout` header
+pub fn set_timeout(headers: &mut Headers, timeout: Duration) {
+    // value is at most 8 digits
+    let value = if timeout.as_secs() < 100_000 {
+        format!("{}m", timeout.as_secs() * 1000 + (timeout.subsec_nanos() / 1_000_000) as u64)
+    } else if timeout.as_secs() < 100_000_000 {
+        format!("{}S", timeout.as_secs())
+    } else {
+        format!("{}H", cmp::min(timeout.as_secs() / 3600, 99_999_999))
+    };
+    headers.0.retain(|h| h.name() != b"grpc-timeout");
+    headers.add("grpc-timeout", &value);
+}
+
+/// Parse `grpc-timeout` header
+pub fn parse_timeout(headers: &Headers) -> Option<Duration> {
+    let value = match headers.get_opt("grpc-timeout") {
+        Some(value) if value.len() >= 2 => value,
+        _ => return None,
+    };
+    let (amount, unit) = value.split_at(value.len() - 1);
+    // TimeoutValue is at most 8 digits
+    if amount.len() > 8 || !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = match amount.parse() {
+        Ok(amount) => amount,
+        Err(_) => return None,
+    };
+    match unit {
+        "H" => amount.checked_mul(3600).map(Duration::from_secs),
+        "M" => amount.checked_mul(60).map(Duration::from_secs),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::new(amount / 1_000_000, ((amount % 1_000_000) * 1000) as u32)),
+        "n" => Some(Duration::new(amount / 1_000_000_000, (amount % 1_000_000_000) as u32)),
+        _ => None,
+    }
+}
+
+/// Frame one uncompressed message
+pub fn encode_message(message: &[u8]) -> Bytes {
+    let len = message.len() as u32;
+    let mut r = BytesMut::with_capacity(MESSAGE_HEADER_SIZE + message.len());
+    r.extend_from_slice(&[0, (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+    r.extend_from_slice(message);
+    r.freeze()
+}
+
+/// Frame each message as DATA, e. g. for request body
+pub fn encode_messages<S>(messages: S) -> HttpPartStream
+    where S : Stream<Item=Bytes, Error=Error> + Send + 'static
+{
+    HttpPartStream::bytes(messages.map(|m| encode_message(&m)))
+}
+
+
+/// Stream of messages decoded from DATA frames.
+///
+/// Fails with `Error::GrpcError` if trailers contain non-OK status,
+/// or if `grpc-status` is missing when `require_status` is set.
+pub struct GrpcMessageStream {
+    stream: HttpPartStream,
+    buf: BytesMut,
+    /// Encoding of compressed messages, from `grpc-encoding` header
+    pub encoding: Option<ContentEncoding>,
+    /// Larger messages fail the stream with `RESOURCE_EXHAUSTED`
+    pub max_message_size: usize,
+    /// Stream must end with `grpc-status` trailers, true for responses
+    pub require_status: bool,
+    trailers: Option<Headers>,
+    done: bool,
+    finished: bool,
+}
+
+impl GrpcMessageStream {
+    pub fn new(stream: HttpPartStream) -> GrpcMessageStream {
+        GrpcMessageStream {
+            stream: stream,
+            buf: BytesMut::new(),
+            encoding: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            require_status: false,
+            trailers: None,
+            done: false,
+            finished: false,
+        }
+    }
+
+    /// Trailers, available after the end of the stream
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.trailers.as_ref()
+    }
+
+    fn resource_exhausted(&self, size: usize) -> Error {
+        Error::GrpcError(GrpcStatus::new(
+            GrpcCode::ResourceExhausted,
+            format!("message size {} exceeds limit {}", size, self.max_message_size)))
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Bytes> {
+        let read: Box<Read> = match self.encoding {
+            Some(ContentEncoding::Gzip) => Box::new(flate2::read::GzDecoder::new(data)),
+            Some(ContentEncoding::Deflate) => Box::new(flate2::read::ZlibDecoder::new(data)),
+            Some(ContentEncoding::Brotli) => Box::new(brotli::Decompressor::new(data, 4096)),
+            None => {
+                return Err(Error::GrpcError(GrpcStatus::new(
+                    GrpcCode::Internal, "compressed message without grpc-encoding")));
+            }
+        };
+
+        let mut r = Vec::new();
+        read.take(self.max_message_size as u64 + 1).read_to_end(&mut r)?;
+        if r.len() > self.max_message_size {
+            return Err(self.resource_exhausted(r.len()));
+        }
+        Ok(Bytes::from(r))
+    }
+
+    /// Take complete message from the buffer
+    fn next_message(&mut self) -> Result<Option<Bytes>> {
+        if self.buf.len() < MESSAGE_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let compressed = match self.buf[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(Error::InvalidFrame(format!("invalid gRPC compressed flag: {}", flag))),
+        };
+        let len = ((self.buf[1] as usize) << 24) | ((self.buf[2] as usize) << 16)
+            | ((self.buf[3] as usize) << 8) | (self.buf[4] as usize);
+        if len > self.max_message_size {
+            return Err(self.resource_exhausted(len));
+        }
+        if self.buf.len() < MESSAGE_HEADER_SIZE + len {
+            return Ok(None);
+        }
+
+        let message = self.buf.split_to(MESSAGE_HEADER_SIZE + len).freeze().slice_from(MESSAGE_HEADER_SIZE);
+        if compressed {
+            self.decompress(&message).map(Some)
+        } else {
+            Ok(Some(message))
+        }
+    }
+}
+
+impl Stream for GrpcMessageStream {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        loop {
+            if self.finished {
+                return Ok(Async::Ready(None));
+            }
+
+            if let Some(message) = self.next_message()? {
+                return Ok(Async::Ready(Some(message)));
+            }
+
+            if self.done {
+                self.finished = true;
+                if !self.buf.is_empty() {
+                    return Err(Error::InvalidFrame("truncated gRPC message".to_owned()));
+                }
+                match self.trailers.as_ref().and_then(GrpcStatus::from_headers) {
+                    Some(ref status) if !status.is_ok() => {
+                        return Err(Error::GrpcError(status.clone()));
+                    }
+                    None if self.require_status => {
+                        return Err(Error::GrpcError(GrpcStatus::new(
+                            GrpcCode::Internal, "missing grpc-status")));
+                    }
+                    _ => {}
+                }
+                return Ok(Async::Ready(None));
+            }
+
+            match try_ready!(self.stream.poll()) {
+                Some(HttpStreamPart { content: HttpStreamPartContent::Data(data), .. }) => {
+                    self.buf.extend_from_slice(&data);
+                }
+                Some(HttpStreamPart { content: HttpStreamPartContent::Headers(headers), .. }) => {
+                    self.trailers = Some(headers);
+                }
+                None => {
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
+
+/// Messages of gRPC response.
+///
+/// Fails with `Error::GrpcError` if call failed, including failed trailers-only
+/// responses and responses with HTTP status other than 200.
+pub fn response_messages(response: Response) -> HttpFutureStreamSend<Bytes> {
+    Box::new(response.0.map(|(headers, body)| -> HttpFutureStreamSend<Bytes> {
+        let http_status: u32 = headers.get_opt_parse(":status").unwrap_or(0);
+        if http_status != 200 {
+            let status = GrpcStatus::new(
+                GrpcCode::from_http_status(http_status), format!("HTTP status {}", http_status));
+            return Box::new(stream::once::<Bytes, Error>(Err(Error::GrpcError(status))));
+        }
+
+        // trailers-only response
+        if let Some(status) = GrpcStatus::from_headers(&headers) {
+            if !status.is_ok() {
+                return Box::new(stream::once::<Bytes, Error>(Err(Error::GrpcError(status))));
+            }
+            return Box::new(stream::empty::<Bytes, Error>());
+        }
+
+        let mut messages = GrpcMessageStream::new(body);
+        messages.require_status = true;
+        messages.encoding = headers.get_opt("grpc-encoding").and_then(ContentEncoding::from_name);
+        Box::new(messages)
+    }).flatten_stream())
+}
+
+/// Frames messages and ends response with status trailers
+struct ResponseBody<S> {
+    messages: S,
+    done: bool,
+}
+
+impl<S> Stream for ResponseBody<S>
+    where S : Stream<Item=Bytes, Error=Error>
+{
+    type Item = HttpStreamPart;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<HttpStreamPart>, Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        let status = match self.messages.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(Some(message))) => {
+                return Ok(Async::Ready(Some(HttpStreamPart::intermediate_data(encode_message(&message)))));
+            }
+            Ok(Async::Ready(None)) => GrpcStatus::ok(),
+            Err(e) => {
+                warn!("gRPC response stream failed: {:?}", e);
+                GrpcStatus::from_error(e)
+            }
+        };
+
+        self.done = true;
+        Ok(Async::Ready(Some(HttpStreamPart::last_headers(status.to_headers()))))
+    }
+}
+
+/// gRPC response with messages of the stream.
+///
+/// Stream failed with `Error::GrpcError` ends response with its status,
+/// other errors are sent as `INTERNAL`.
+pub fn response<S>(messages: S) -> Response
+    where S : Stream<Item=Bytes, Error=Error> + Send + 'static
+{
+    Response::headers_and_stream(response_headers(), HttpPartStream::new(ResponseBody {
+        messages: messages,
+        done: false,
+    }))
+}
+
+/// Trailers-only response with error status
+pub fn response_status(status: GrpcStatus) -> Response {
+    let mut headers = response_headers();
+    headers.extend(status.to_headers());
+    Response::headers(headers)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    fn parts_stream(parts: Vec<HttpStreamPartContent>) -> HttpPartStream {
+        HttpPartStream::new(stream::iter_ok(parts.into_iter().map(|content| HttpStreamPart {
+            content: content,
+            last: false,
+        })))
+    }
+
+    #[test]
+    fn split_and_packed_messages() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&encode_message(b"first"));
+        data.extend_from_slice(&encode_message(b""));
+        data.extend_from_slice(&encode_message(b"third"));
+
+        let parts = vec![
+            HttpStreamPartContent::Data(Bytes::from(&data[..3])),
+            HttpStreamPartContent::Data(Bytes::from(&data[3..17])),
+            HttpStreamPartContent::Data(Bytes::from(&data[17..])),
+        ];
+        let messages: Vec<Bytes> = GrpcMessageStream::new(parts_stream(parts)).collect().wait().unwrap();
+        assert_eq!(vec![Bytes::from("first"), Bytes::new(), Bytes::from("third")], messages);
+    }
+
+    #[test]
+    fn compressed_message() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut data = encode_message(&compressed).to_vec();
+        data[0] = 1;
+
+        let mut messages = GrpcMessageStream::new(parts_stream(vec![HttpStreamPartContent::Data(Bytes::from(data))]));
+        messages.encoding = Some(ContentEncoding::Gzip);
+        assert_eq!(vec![Bytes::from("hello")], messages.collect().wait().unwrap());
+    }
+
+    #[test]
+    fn max_message_size() {
+        let data = encode_message(&[0; 100]);
+        let mut messages = GrpcMessageStream::new(parts_stream(vec![HttpStreamPartContent::Data(data)]));
+        messages.max_message_size = 99;
+        match messages.collect().wait() {
+            Err(Error::GrpcError(ref status)) if status.code == GrpcCode::ResourceExhausted => {}
+            r => panic!("expecting RESOURCE_EXHAUSTED: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn status_trailers() {
+        let status = GrpcStatus::new(GrpcCode::NotFound, "no such user: 100%");
+        let trailers = status.to_headers();
+        assert_eq!("5", trailers.get("grpc-status"));
+        assert_eq!("no such user: 100%25", trailers.get("grpc-message"));
+        assert_eq!(Some(status.clone()), GrpcStatus::from_headers(&trailers));
+
+        let parts = vec![
+            HttpStreamPartContent::Data(encode_message(b"x")),
+            HttpStreamPartContent::Headers(trailers),
+        ];
+        let mut messages = GrpcMessageStream::new(parts_stream(parts)).wait();
+        assert_eq!(Bytes::from("x"), messages.next().unwrap().unwrap());
+        match messages.next() {
+            Some(Err(Error::GrpcError(ref s))) if *s == status => {}
+            r => panic!("expecting status error: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let mut headers = Headers::new();
+        set_timeout(&mut headers, Duration::from_millis(1500));
+        assert_eq!("1500m", headers.get("grpc-timeout"));
+        assert_eq!(Some(Duration::from_millis(1500)), parse_timeout(&headers));
+
+        headers.0.clear();
+        headers.add("grpc-timeout", "99999999H");
+        assert_eq!(Some(Duration::from_secs(99999999 * 3600)), parse_timeout(&headers));
+
+        headers.0.clear();
+        headers.add("grpc-timeout", "999999999999999999H");
+        assert_eq!(None, parse_timeout(&headers));
+
+        headers.0.clear();
+        headers.add("grpc-timeout", "+1S");
+        assert_eq!(None, parse_timeout(&headers));
+    }
+
+    #[test]
+    fn missing_status() {
+        let parts = vec![HttpStreamPartContent::Data(encode_message(b"x"))];
+        let mut messages = GrpcMessageStream::new(parts_stream(parts));
+        messages.require_status = true;
+        let mut messages = messages.wait();
+        assert_eq!(Bytes::from("x"), messages.next().unwrap().unwrap());
+        match messages.next() {
+            Some(Err(Error::GrpcError(ref s))) if s.code == GrpcCode::Internal => {}
+            r => panic!("expecting missing status error: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn trailers_only_ok() {
+        let mut headers = Headers::ok_200();
+        headers.add("content-type", "application/grpc");
+        headers.add("grpc-status", "0");
+        let response = Response::headers_and_stream(headers, HttpPartStream::empty());
+        let messages: Vec<Bytes> = response_messages(response).collect().wait().unwrap();
+        assert!(messages.is_empty());
+    }
+}
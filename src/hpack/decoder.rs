@@ -358,7 +358,7 @@ impl<'a> Decoder<'a> {
                 },
                 FieldRepresentation::SizeUpdate => {
                     // Handle the dynamic table size update...
-                    self.update_max_dynamic_size(buffer_leftover)
+                    self.update_max_dynamic_size(buffer_leftover)?
                 }
             };
 
@@ -448,15 +448,15 @@ impl<'a> Decoder<'a> {
     /// octet in the `SizeUpdate` block.
     ///
     /// Returns the number of octets consumed from the given buffer.
-    fn update_max_dynamic_size(&mut self, buf: &[u8]) -> usize {
-        let (new_size, consumed) = decode_integer(buf, 5).ok().unwrap();
+    fn update_max_dynamic_size(&mut self, buf: &[u8]) -> Result<usize, DecoderError> {
+        let (new_size, consumed) = decode_integer(buf, 5)?;
         self.header_table.dynamic_table.set_max_table_size(new_size);
 
         info!("Decoder changed max table size from {} to {}",
               self.header_table.dynamic_table.get_size(),
               new_size);
 
-        consumed
+        Ok(consumed)
     }
 }
 
@@ -1350,6 +1350,20 @@ mod tests {
             _ => false,
         });
     }
+
+    /// Tests that a size update cut short after the prefix is an error.
+    #[test]
+    fn test_size_update_missing_octets() {
+        let mut decoder = Decoder::new();
+        // The prefix is all ones, so the size continues in the next octets
+        let result = decoder.decode(&[0x3f]);
+
+        assert!(match result {
+            Err(DecoderError::IntegerDecodingError(
+                    IntegerDecodingError::NotEnoughOctets)) => true,
+            _ => false,
+        });
+    }
 }
 
 /// The module defines interop tests between this HPACK decoder
@@ -0,0 +1,116 @@
+//! Calendar conversions and HTTP date format (RFC 7231 IMF-fixdate)
+
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+
+pub const MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// UTC date and time: year, month, day, hour, minute, second
+pub fn utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as u32, day as u32,
+        (secs_of_day / 3600) as u32, (secs_of_day / 60 % 60) as u32, (secs_of_day % 60) as u32)
+}
+
+/// Days since 1970-01-01, inverse of date part of `utc`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Format time like `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn format_http_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    let days = days_from_civil(year, month, day);
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[((days % 7 + 11) % 7) as usize], day, MONTHS[month as usize - 1], year,
+        hour, minute, second)
+}
+
+/// Parse date in IMF-fixdate format, obsolete formats are not supported
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    parse_imf_fixdate(s).ok()
+}
+
+fn parse_imf_fixdate(s: &str) -> Result<SystemTime, ()> {
+    let parts: Vec<&str> = s.split(' ').collect();
+    if parts.len() != 6 || !parts[0].ends_with(',') || parts[5] != "GMT" {
+        return Err(());
+    }
+
+    let day: u32 = parts[1].parse().map_err(|_| ())?;
+    let month = MONTHS.iter().position(|m| *m == parts[2]).ok_or(())? as u32 + 1;
+    // four digit year, larger values would overflow calendar arithmetic
+    if parts[3].len() != 4 {
+        return Err(());
+    }
+    let year: i64 = parts[3].parse().map_err(|_| ())?;
+
+    let time: Vec<&str> = parts[4].split(':').collect();
+    if time.len() != 3 {
+        return Err(());
+    }
+    let hour: u64 = time[0].parse().map_err(|_| ())?;
+    let minute: u64 = time[1].parse().map_err(|_| ())?;
+    let second: u64 = time[2].parse().map_err(|_| ())?;
+    if day < 1 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return Err(());
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return Err(());
+    }
+
+    UNIX_EPOCH.checked_add(Duration::from_secs(days as u64 * 86400 + hour * 3600 + minute * 60 + second))
+        .ok_or(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!((1970, 1, 1, 0, 0, 0), utc(UNIX_EPOCH));
+        assert_eq!((2000, 2, 29, 23, 59, 59), utc(UNIX_EPOCH + Duration::from_secs(951868799)));
+    }
+
+    #[test]
+    fn http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", format_http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", format_http_date(UNIX_EPOCH));
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 300000000000 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 100000000000000000 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov +994 08:49:37 GMT"));
+    }
+}
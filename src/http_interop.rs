@@ -0,0 +1,290 @@
+//! Conversions between `Headers` and types of `http` crate
+//! (enabled with `http` feature).
+//!
+//! Pseudo-headers are mapped to method, URI and status,
+//! regular headers are mapped to `HeaderMap`.
+
+use http;
+use http::HeaderMap;
+use http::Request;
+use http::Version;
+use http::header::HeaderName;
+use http::header::HeaderValue;
+use http::request;
+use http::response;
+
+use error::Error;
+use result::Result;
+use resp::Response;
+use service::Service;
+use service::RequestContext;
+use service_proxy::strip_hop_by_hop_headers;
+use solicit::HttpScheme;
+use solicit::header::Header;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+
+
+fn invalid<E : ::std::fmt::Display>(e: E) -> Error {
+    Error::InvalidFrame(format!("cannot convert headers: {}", e))
+}
+
+fn add_header_map(headers: &mut Headers, map: &HeaderMap) {
+    for (name, value) in map.iter() {
+        headers.0.push(Header::new(name.as_str().as_bytes(), value.as_bytes()));
+    }
+}
+
+impl Headers {
+    /// Regular headers, e. g. trailers
+    pub fn from_header_map(map: &HeaderMap) -> Headers {
+        let mut headers = Headers::new();
+        add_header_map(&mut headers, map);
+        headers
+    }
+
+    /// Regular headers, pseudo-headers are skipped
+    pub fn to_header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for header in &self.0 {
+            if header.name().starts_with(b":") {
+                continue;
+            }
+            let name = HeaderName::from_bytes(header.name()).map_err(invalid)?;
+            let value = HeaderValue::from_bytes(header.value()).map_err(invalid)?;
+            map.append(name, value);
+        }
+        Ok(map)
+    }
+
+    /// Request headers: `:method`, `:scheme`, `:authority` and `:path`
+    /// from method and URI, `host` header is used if URI has no authority.
+    ///
+    /// `default_scheme` is used if URI has no scheme, `CONNECT` has no `:scheme` and `:path`.
+    /// Connection-specific headers are not allowed in HTTP/2 and are skipped.
+    pub fn from_request_parts(parts: &request::Parts, default_scheme: HttpScheme) -> Headers {
+        let uri = &parts.uri;
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        let mut headers = Headers(vec![Header::new(":method", parts.method.as_str())]);
+        // 8.3: `:scheme` and `:path` are omitted for `CONNECT`
+        let connect = parts.method == http::Method::CONNECT;
+        match uri.scheme_part() {
+            Some(scheme) => headers.0.push(Header::new(":scheme", scheme.as_str())),
+            None if connect => {}
+            None => headers.0.push(Header::new(":scheme", default_scheme.as_bytes())),
+        }
+        let authority = uri.authority_part().map(|a| a.as_str())
+            .or_else(|| parts.headers.get(http::header::HOST).and_then(|h| h.to_str().ok()));
+        if let Some(authority) = authority {
+            headers.0.push(Header::new(":authority", authority));
+        }
+        if !connect {
+            headers.0.push(Header::new(":path", path));
+        }
+
+        for (name, value) in parts.headers.iter() {
+            if name != http::header::HOST {
+                headers.0.push(Header::new(name.as_str().as_bytes(), value.as_bytes()));
+            }
+        }
+        strip_hop_by_hop_headers(&mut headers);
+        headers
+    }
+
+    /// Request parts from pseudo-headers and regular headers.
+    ///
+    /// `:authority` without `:scheme` cannot be a part of URI,
+    /// so it is passed in `host` header.
+    pub fn to_request_parts(&self) -> Result<request::Parts> {
+        let method = self.get_opt(":method").ok_or_else(|| invalid("no :method"))?;
+        let path = self.get_opt(":path").unwrap_or("/");
+        let mut host = None;
+        let uri = match (self.get_opt(":scheme"), self.get_opt(":authority")) {
+            (Some(scheme), Some(authority)) => format!("{}://{}{}", scheme, authority, path),
+            (None, Some(authority)) => {
+                host = Some(authority);
+                path.to_owned()
+            }
+            _ => path.to_owned(),
+        };
+
+        let mut builder = Request::builder();
+        builder.method(method);
+        builder.uri(&uri[..]);
+        builder.version(Version::HTTP_2);
+        let request = builder.body(()).map_err(invalid)?;
+
+        let (mut parts, ()) = request.into_parts();
+        parts.headers = self.to_header_map()?;
+        if let Some(host) = host {
+            if !parts.headers.contains_key(http::header::HOST) {
+                let host = HeaderValue::from_str(host).map_err(invalid)?;
+                parts.headers.insert(http::header::HOST, host);
+            }
+        }
+        Ok(parts)
+    }
+
+    /// Response headers: `:status` and regular headers
+    pub fn from_response_parts(parts: &response::Parts) -> Headers {
+        let mut headers = Headers::from_status(parts.status.as_u16() as u32);
+        add_header_map(&mut headers, &parts.headers);
+        headers
+    }
+
+    /// Response parts from `:status` and regular headers
+    pub fn to_response_parts(&self) -> Result<response::Parts> {
+        let status: u16 = self.get_opt_parse(":status").ok_or_else(|| invalid("no valid :status"))?;
+
+        let mut builder = http::Response::builder();
+        builder.status(status);
+        builder.version(Version::HTTP_2);
+        let response = builder.body(()).map_err(invalid)?;
+
+        let (mut parts, ()) = response.into_parts();
+        parts.headers = self.to_header_map()?;
+        Ok(parts)
+    }
+}
+
+impl Response {
+    /// Response with headers and body of `http::Response`
+    pub fn from_http(response: http::Response<HttpPartStream>) -> Response {
+        let (parts, body) = response.into_parts();
+        Response::headers_and_stream(Headers::from_response_parts(&parts), body)
+    }
+}
+
+
+/// `Service` calling a function with `http::Request`.
+///
+/// `RequestContext` is available in request extensions
+/// if the service is called by `Server`.
+pub struct HttpService<F> {
+    f: F,
+}
+
+impl<F> HttpService<F>
+    where F : Fn(Request<HttpPartStream>) -> Response + Send + Sync + 'static
+{
+    pub fn new(f: F) -> HttpService<F> {
+        HttpService {
+            f: f,
+        }
+    }
+
+    fn call(&self, context: Option<RequestContext>, headers: Headers, req: HttpPartStream) -> Response {
+        let mut parts = match headers.to_request_parts() {
+            Ok(parts) => parts,
+            Err(e) => {
+                warn!("malformed request headers: {:?}", e);
+                return Response::headers(Headers::from_status(400));
+            }
+        };
+        if let Some(context) = context {
+            parts.extensions.insert(context);
+        }
+        (self.f)(Request::from_parts(parts, req))
+    }
+}
+
+impl<F> Service for HttpService<F>
+    where F : Fn(Request<HttpPartStream>) -> Response + Send + Sync + 'static
+{
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        self.call(None, headers, req)
+    }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        self.call(Some(context), headers, req)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request() {
+        let mut headers = Headers(vec![
+            Header::new(":method", "POST"),
+            Header::new(":scheme", "https"),
+            Header::new(":authority", "example.com:8443"),
+            Header::new(":path", "/a?b=c"),
+            Header::new("x-a", "1"),
+            Header::new("x-a", "2"),
+        ]);
+
+        let parts = headers.to_request_parts().unwrap();
+        assert_eq!(http::Method::POST, parts.method);
+        assert_eq!("https://example.com:8443/a?b=c", parts.uri.to_string());
+        assert_eq!(2, parts.headers.get_all("x-a").iter().count());
+
+        assert_eq!(headers, Headers::from_request_parts(&parts, HttpScheme::Http));
+
+        headers.0.retain(|h| h.name() != b":scheme");
+        let parts = headers.to_request_parts().unwrap();
+        assert_eq!("/a?b=c", parts.uri.to_string());
+        assert_eq!("example.com:8443", parts.headers["host"]);
+        let headers = Headers::from_request_parts(&parts, HttpScheme::Https);
+        assert_eq!("https", headers.get(":scheme"));
+        assert_eq!("example.com:8443", headers.get(":authority"));
+    }
+
+    #[test]
+    fn request_host_header() {
+        let request = Request::get("/").header("host", "example.com").body(()).unwrap();
+        let (parts, ()) = request.into_parts();
+        let headers = Headers::from_request_parts(&parts, HttpScheme::Http);
+        assert_eq!("http", headers.get(":scheme"));
+        assert_eq!("example.com", headers.get(":authority"));
+        assert_eq!(None, headers.get_opt("host"));
+    }
+
+    #[test]
+    fn request_connection_specific_headers() {
+        let request = Request::get("http://example.com/")
+            .header("connection", "keep-alive, x-hop")
+            .header("keep-alive", "timeout=5")
+            .header("transfer-encoding", "chunked")
+            .header("x-hop", "1")
+            .header("te", "trailers")
+            .header("x-a", "1")
+            .body(())
+            .unwrap();
+        let (parts, ()) = request.into_parts();
+        let headers = Headers::from_request_parts(&parts, HttpScheme::Https);
+        assert_eq!("http", headers.get(":scheme"));
+        for name in &["connection", "keep-alive", "transfer-encoding", "x-hop"] {
+            assert_eq!(None, headers.get_opt(name), "{}", name);
+        }
+        assert_eq!("trailers", headers.get("te"));
+        assert_eq!("1", headers.get("x-a"));
+    }
+
+    #[test]
+    fn request_connect() {
+        let request = Request::connect("example.com:443").body(()).unwrap();
+        let (parts, ()) = request.into_parts();
+        let headers = Headers::from_request_parts(&parts, HttpScheme::Https);
+        assert_eq!(None, headers.get_opt(":scheme"));
+        assert_eq!(None, headers.get_opt(":path"));
+        assert_eq!("example.com:443", headers.get(":authority"));
+    }
+
+    #[test]
+    fn response() {
+        let mut headers = Headers::from_status(404);
+        headers.add("content-type", "text/plain");
+
+        let parts = headers.to_response_parts().unwrap();
+        assert_eq!(http::StatusCode::NOT_FOUND, parts.status);
+        assert_eq!("text/plain", parts.headers["content-type"]);
+
+        assert_eq!(headers, Headers::from_response_parts(&parts));
+    }
+}
@@ -16,6 +16,14 @@ extern crate tokio_tls_api;
 extern crate void;
 extern crate net2;
 extern crate bytes;
+extern crate flate2;
+extern crate brotli;
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "http")]
+extern crate http;
+#[cfg(feature = "std-future")]
+extern crate futures03;
 
 pub mod solicit;
 
@@ -25,15 +33,29 @@ mod result;
 
 pub mod client_conf;
 pub mod client_conn;
+mod client_backoff;
 mod client_tls;
 mod service;
 mod service_paths;
+pub mod service_layer;
+mod service_proxy;
+mod service_static;
+mod compression;
+mod router;
 pub mod client;
+mod client_blocking;
 pub mod server_conf;
 pub mod server_conn;
 mod server_tls;
+mod server_sni;
+mod server_tls_watch;
+mod server_access_log;
+mod http_date;
+mod metrics;
+mod frame_trace;
 pub mod socket;
 pub mod socket_tcp;
+pub mod socket_memory;
 pub mod server;
 
 #[cfg(unix)]
@@ -47,6 +69,11 @@ mod common;
 
 pub mod stream_part;
 pub mod message;
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http_interop;
+#[cfg(feature = "std-future")]
+pub mod std_future;
 
 pub mod futures_misc;
 
@@ -59,6 +86,7 @@ pub mod solicit_async; // TODO: make private
 pub mod solicit_misc;
 
 pub mod misc;
+pub mod testing;
 mod rc_mut;
 
 mod resp;
@@ -70,20 +98,58 @@ pub use solicit::header::Header;
 pub use solicit::header::Headers;
 
 pub use service::Service;
+pub use service::RequestContext;
+pub use service::ConnectionInfo;
 pub use service_paths::ServicePaths;
+pub use router::Router;
+pub use router::RouteParams;
+pub use service_layer::Layer;
+pub use service_proxy::ProxyService;
+pub use service_static::StaticFiles;
+pub use compression::ContentEncoding;
+#[cfg(feature = "http")]
+pub use http_interop::HttpService;
 
 pub use exec::CpuPoolOption;
 
 pub use client::Client;
 pub use client::ClientBuilder;
+pub use client::ClientConnectionState;
+pub use client_blocking::BlockingClient;
+pub use client_blocking::BlockingResponse;
+pub use client_blocking::BlockingBody;
 pub use client_conf::ClientConf;
+pub use client_conf::ClientReconnectConf;
+pub use client_conf::ClientAlpn;
+pub use client_conn::ClientRequestOptions;
 pub use client_tls::ClientTlsOption;
 
 pub use server::Server;
 pub use server::ServerBuilder;
 pub use server_conf::ServerConf;
 pub use server_conf::ServerAlpn;
+pub use server_access_log::AccessLogRecord;
+pub use server_access_log::AccessLogFormat;
+pub use server_access_log::ServerAccessLog;
 pub use server_tls::ServerTlsOption;
+pub use server_tls::ServerSniAcceptors;
+pub use server_tls::ServerTlsHandle;
+pub use server_tls_watch::ServerTlsFileWatcher;
+
+pub use metrics::Metrics;
+pub use metrics::MetricsRegistry;
+pub use metrics::MetricsSnapshot;
+pub use metrics::HANDLER_LATENCY_BUCKETS;
+
+pub use frame_trace::FrameTracer;
+pub use frame_trace::TracedFrame;
+pub use frame_trace::FrameDirection;
+pub use frame_trace::TextFrameDump;
+pub use frame_trace::FrameCapture;
+pub use frame_trace::CapturedFrame;
+pub use frame_trace::CaptureReader;
+pub use frame_trace::format_frame;
+pub use frame_trace::format_frame_contents;
 
 pub use resp::Response;
 pub use stream_part::HttpPartStream;
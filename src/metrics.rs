@@ -0,0 +1,347 @@
+//! Connection metrics
+
+use std::collections::HashMap;
+use std::fmt::Write as _Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use error::ErrorCode;
+use solicit::connection::HttpFrameType;
+use solicit::frame::FRAME_HEADER_LEN;
+use solicit::frame::goaway::GOAWAY_FRAME_TYPE;
+
+
+/// Receiver of connection events of `Client` or `Server`.
+///
+/// Callbacks are invoked from event loop threads, so they should be cheap.
+/// All methods do nothing by default.
+pub trait Metrics : Send + Sync + 'static {
+    /// Connection handshake completed
+    fn conn_opened(&self) {}
+    fn conn_closed(&self) {}
+    fn stream_opened(&self) {}
+    fn stream_closed(&self) {}
+    /// Frame received, `len` includes frame header
+    fn frame_received(&self, _frame_type: HttpFrameType, _len: usize) {}
+    /// Frame sent, `len` includes frame header
+    fn frame_sent(&self, _frame_type: HttpFrameType, _len: usize) {}
+    fn rst_stream_received(&self, _error_code: ErrorCode) {}
+    fn rst_stream_sent(&self, _error_code: ErrorCode) {}
+    fn goaway_received(&self, _error_code: ErrorCode) {}
+    fn goaway_sent(&self, _error_code: ErrorCode) {}
+    /// Stream has data to send, but stream or connection window is exhausted
+    fn window_stall(&self) {}
+    /// Server only: time from request headers received to response headers produced by service
+    fn handler_latency(&self, _latency: Duration) {}
+}
+
+/// Metrics which ignores all events
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    ((bytes[0] as u32) << 24) | ((bytes[1] as u32) << 16) | ((bytes[2] as u32) << 8) | (bytes[3] as u32)
+}
+
+/// Report frames contained in serialized bytes
+pub fn frames_sent(metrics: &Metrics, mut bytes: &[u8]) {
+    while bytes.len() >= FRAME_HEADER_LEN {
+        let payload_len =
+            ((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | (bytes[2] as usize);
+        let len = FRAME_HEADER_LEN + payload_len;
+        metrics.frame_sent(HttpFrameType::from_u8(bytes[3]), len);
+        // GOAWAY payload: last stream id and error code
+        if bytes[3] == GOAWAY_FRAME_TYPE && payload_len >= 8 && bytes.len() >= FRAME_HEADER_LEN + 8 {
+            let error_code = read_u32(&bytes[FRAME_HEADER_LEN + 4..]);
+            metrics.goaway_sent(ErrorCode::from(error_code));
+        }
+        bytes = &bytes[::std::cmp::min(len, bytes.len())..];
+    }
+}
+
+
+/// Upper bounds of handler latency histogram buckets in seconds
+pub const HANDLER_LATENCY_BUCKETS: [f64; 12] =
+    [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Counters collected by `MetricsRegistry`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    pub conns_opened: u64,
+    pub conns_closed: u64,
+    pub streams_opened: u64,
+    pub streams_closed: u64,
+    pub frames_received: HashMap<HttpFrameType, u64>,
+    pub frames_sent: HashMap<HttpFrameType, u64>,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub rst_stream_received: HashMap<ErrorCode, u64>,
+    pub rst_stream_sent: HashMap<ErrorCode, u64>,
+    pub goaway_received: HashMap<ErrorCode, u64>,
+    pub goaway_sent: HashMap<ErrorCode, u64>,
+    pub window_stalls: u64,
+    /// Number of latencies in each of `HANDLER_LATENCY_BUCKETS` (not cumulative),
+    /// last element counts latencies greater than the last bucket
+    pub handler_latency_buckets: Vec<u64>,
+    pub handler_latency_sum: Duration,
+    pub handler_latency_count: u64,
+}
+
+impl Default for MetricsSnapshot {
+    fn default() -> MetricsSnapshot {
+        MetricsSnapshot {
+            conns_opened: 0,
+            conns_closed: 0,
+            streams_opened: 0,
+            streams_closed: 0,
+            frames_received: HashMap::new(),
+            frames_sent: HashMap::new(),
+            bytes_received: 0,
+            bytes_sent: 0,
+            rst_stream_received: HashMap::new(),
+            rst_stream_sent: HashMap::new(),
+            goaway_received: HashMap::new(),
+            goaway_sent: HashMap::new(),
+            window_stalls: 0,
+            handler_latency_buckets: vec![0; HANDLER_LATENCY_BUCKETS.len() + 1],
+            handler_latency_sum: Duration::from_secs(0),
+            handler_latency_count: 0,
+        }
+    }
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+fn write_metric(out: &mut String, name: &str, metric_type: &str, help: &str, samples: Vec<(String, String)>) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, metric_type).unwrap();
+    for (labels, value) in samples {
+        writeln!(out, "{}{} {}", name, labels, value).unwrap();
+    }
+}
+
+fn single(value: u64) -> Vec<(String, String)> {
+    vec![(String::new(), format!("{}", value))]
+}
+
+fn by_frame_type(counters: &HashMap<HttpFrameType, u64>) -> Vec<(String, String)> {
+    let mut samples: Vec<_> = counters.iter()
+        .map(|(t, c)| (format!("{{type=\"{}\"}}", t.name()), format!("{}", c)))
+        .collect();
+    samples.sort();
+    samples
+}
+
+fn by_error_code(counters: &HashMap<ErrorCode, u64>) -> Vec<(String, String)> {
+    let mut samples: Vec<_> = counters.iter()
+        .map(|(e, c)| (format!("{{error_code=\"{:?}\"}}", e), format!("{}", c)))
+        .collect();
+    samples.sort();
+    samples
+}
+
+impl MetricsSnapshot {
+    /// Format counters in Prometheus text exposition format.
+    ///
+    /// `prefix` is prepended to metric names, e. g. `httpbis_server`.
+    pub fn to_prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        let name = |n: &str| format!("{}_{}", prefix, n);
+
+        write_metric(&mut out, &name("connections_opened_total"), "counter",
+            "Connections opened", single(self.conns_opened));
+        write_metric(&mut out, &name("connections_active"), "gauge",
+            "Connections currently open", single(self.conns_opened - self.conns_closed));
+        write_metric(&mut out, &name("streams_opened_total"), "counter",
+            "Streams opened", single(self.streams_opened));
+        write_metric(&mut out, &name("streams_active"), "gauge",
+            "Streams currently open", single(self.streams_opened - self.streams_closed));
+        write_metric(&mut out, &name("frames_received_total"), "counter",
+            "Frames received by type", by_frame_type(&self.frames_received));
+        write_metric(&mut out, &name("frames_sent_total"), "counter",
+            "Frames sent by type", by_frame_type(&self.frames_sent));
+        write_metric(&mut out, &name("received_bytes_total"), "counter",
+            "Bytes of frames received", single(self.bytes_received));
+        write_metric(&mut out, &name("sent_bytes_total"), "counter",
+            "Bytes of frames sent", single(self.bytes_sent));
+        write_metric(&mut out, &name("rst_stream_received_total"), "counter",
+            "RST_STREAM frames received by error code", by_error_code(&self.rst_stream_received));
+        write_metric(&mut out, &name("rst_stream_sent_total"), "counter",
+            "RST_STREAM frames sent by error code", by_error_code(&self.rst_stream_sent));
+        write_metric(&mut out, &name("goaway_received_total"), "counter",
+            "GOAWAY frames received by error code", by_error_code(&self.goaway_received));
+        write_metric(&mut out, &name("goaway_sent_total"), "counter",
+            "GOAWAY frames sent by error code", by_error_code(&self.goaway_sent));
+        write_metric(&mut out, &name("window_stalls_total"), "counter",
+            "Times stream data was blocked by flow control window", single(self.window_stalls));
+
+        let mut samples = Vec::new();
+        let mut cumulative = 0;
+        for (i, count) in self.handler_latency_buckets.iter().enumerate() {
+            cumulative += *count;
+            let le = match HANDLER_LATENCY_BUCKETS.get(i) {
+                Some(le) => format!("{}", le),
+                None => "+Inf".to_owned(),
+            };
+            samples.push((format!("_bucket{{le=\"{}\"}}", le), format!("{}", cumulative)));
+        }
+        samples.push(("_sum".to_owned(), format!("{}", duration_secs(self.handler_latency_sum))));
+        samples.push(("_count".to_owned(), format!("{}", self.handler_latency_count)));
+        write_metric(&mut out, &name("handler_latency_seconds"), "histogram",
+            "Time from request headers to response headers", samples);
+
+        out
+    }
+}
+
+
+/// `Metrics` implementation which counts events.
+///
+/// Can be shared by several clients and servers.
+#[derive(Default)]
+pub struct MetricsRegistry {
+    counters: Mutex<MetricsSnapshot>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> MetricsRegistry {
+        Default::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.counters.lock().unwrap().clone()
+    }
+
+    /// Format current counters in Prometheus text exposition format
+    pub fn to_prometheus(&self, prefix: &str) -> String {
+        self.snapshot().to_prometheus(prefix)
+    }
+
+    fn with<F : FnOnce(&mut MetricsSnapshot)>(&self, f: F) {
+        f(&mut self.counters.lock().unwrap())
+    }
+}
+
+impl Metrics for MetricsRegistry {
+    fn conn_opened(&self) {
+        self.with(|c| c.conns_opened += 1);
+    }
+
+    fn conn_closed(&self) {
+        self.with(|c| c.conns_closed += 1);
+    }
+
+    fn stream_opened(&self) {
+        self.with(|c| c.streams_opened += 1);
+    }
+
+    fn stream_closed(&self) {
+        self.with(|c| c.streams_closed += 1);
+    }
+
+    fn frame_received(&self, frame_type: HttpFrameType, len: usize) {
+        self.with(|c| {
+            *c.frames_received.entry(frame_type).or_insert(0) += 1;
+            c.bytes_received += len as u64;
+        });
+    }
+
+    fn frame_sent(&self, frame_type: HttpFrameType, len: usize) {
+        self.with(|c| {
+            *c.frames_sent.entry(frame_type).or_insert(0) += 1;
+            c.bytes_sent += len as u64;
+        });
+    }
+
+    fn rst_stream_received(&self, error_code: ErrorCode) {
+        self.with(|c| *c.rst_stream_received.entry(error_code).or_insert(0) += 1);
+    }
+
+    fn rst_stream_sent(&self, error_code: ErrorCode) {
+        self.with(|c| *c.rst_stream_sent.entry(error_code).or_insert(0) += 1);
+    }
+
+    fn goaway_received(&self, error_code: ErrorCode) {
+        self.with(|c| *c.goaway_received.entry(error_code).or_insert(0) += 1);
+    }
+
+    fn goaway_sent(&self, error_code: ErrorCode) {
+        self.with(|c| *c.goaway_sent.entry(error_code).or_insert(0) += 1);
+    }
+
+    fn window_stall(&self) {
+        self.with(|c| c.window_stalls += 1);
+    }
+
+    fn handler_latency(&self, latency: Duration) {
+        let secs = duration_secs(latency);
+        let bucket = HANDLER_LATENCY_BUCKETS.iter()
+            .position(|&le| secs <= le)
+            .unwrap_or(HANDLER_LATENCY_BUCKETS.len());
+        self.with(|c| {
+            c.handler_latency_buckets[bucket] += 1;
+            c.handler_latency_sum += latency;
+            c.handler_latency_count += 1;
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use solicit::frame::FrameIR;
+    use solicit::frame::goaway::GoawayFrame;
+    use solicit::frame::ping::PingFrame;
+    use solicit::frame::rst_stream::RstStreamFrame;
+
+    #[test]
+    fn frames_sent_from_bytes() {
+        let mut bytes = PingFrame::new().serialize_into_vec();
+        bytes.extend(RstStreamFrame::new(1, ErrorCode::Cancel).serialize_into_vec());
+        bytes.extend(GoawayFrame::new(3, ErrorCode::ProtocolError).serialize_into_vec());
+
+        let registry = MetricsRegistry::new();
+        frames_sent(&registry, &bytes);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(Some(&1), snapshot.frames_sent.get(&HttpFrameType::Ping));
+        assert_eq!(Some(&1), snapshot.frames_sent.get(&HttpFrameType::RstStream));
+        assert_eq!(Some(&1), snapshot.frames_sent.get(&HttpFrameType::Goaway));
+        assert_eq!(Some(&1), snapshot.goaway_sent.get(&ErrorCode::ProtocolError));
+        assert_eq!(bytes.len() as u64, snapshot.bytes_sent);
+    }
+
+    #[test]
+    fn prometheus() {
+        let registry = MetricsRegistry::new();
+        registry.conn_opened();
+        registry.frame_received(HttpFrameType::Headers, 20);
+        registry.frame_received(HttpFrameType::Data, 15);
+        registry.frame_received(HttpFrameType::Data, 15);
+        registry.rst_stream_sent(ErrorCode::Cancel);
+        registry.goaway_sent(ErrorCode::NoError);
+        registry.handler_latency(Duration::from_millis(3));
+        registry.handler_latency(Duration::from_secs(10));
+
+        let text = registry.to_prometheus("h2");
+
+        assert!(text.contains("# TYPE h2_connections_opened_total counter\nh2_connections_opened_total 1\n"));
+        assert!(text.contains("h2_connections_active 1\n"));
+        assert!(text.contains("h2_frames_received_total{type=\"DATA\"} 2\n\
+            h2_frames_received_total{type=\"HEADERS\"} 1\n"));
+        assert!(text.contains("h2_received_bytes_total 50\n"));
+        assert!(text.contains("h2_rst_stream_sent_total{error_code=\"Cancel\"} 1\n"));
+        assert!(text.contains("h2_goaway_sent_total{error_code=\"NoError\"} 1\n"));
+        assert!(text.contains("h2_handler_latency_seconds_bucket{le=\"0.0025\"} 0\n"));
+        assert!(text.contains("h2_handler_latency_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("h2_handler_latency_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("h2_handler_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("h2_handler_latency_seconds_count 2\n"));
+    }
+}
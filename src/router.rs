@@ -0,0 +1,356 @@
+//! Routing by path pattern and method
+
+use std::sync::Arc;
+
+use service::Service;
+use service::RequestContext;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use resp::Response;
+
+
+/// Values of `:param` and `*wildcard` segments captured by `Router`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteParams(pub Vec<(String, String)>);
+
+impl RouteParams {
+    pub fn new() -> RouteParams {
+        Default::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref v)| &v[..])
+    }
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    /// More specific segments have lower rank
+    fn rank(&self) -> u8 {
+        match self {
+            &Segment::Literal(..) => 0,
+            &Segment::Param(..) => 1,
+            &Segment::Wildcard(..) => 2,
+        }
+    }
+}
+
+/// Path without query string split on `/`
+fn split_path(path: &str) -> Vec<&str> {
+    let path = match path.find('?') {
+        Some(q) => &path[..q],
+        None => path,
+    };
+    path.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    assert!(pattern.starts_with("/"), "pattern must start with slash: {}", pattern);
+
+    let segments: Vec<_> = split_path(pattern).into_iter()
+        .map(|s| {
+            if s.starts_with(":") {
+                Segment::Param(s[1..].to_owned())
+            } else if s.starts_with("*") {
+                Segment::Wildcard(s[1..].to_owned())
+            } else {
+                Segment::Literal(s.to_owned())
+            }
+        })
+        .collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        if let &Segment::Wildcard(..) = segment {
+            assert!(i == segments.len() - 1, "wildcard must be the last segment: {}", pattern);
+        }
+    }
+
+    segments
+}
+
+/// Function registered with `Router::add_fn`
+type RouteFn = Fn(RouteParams, Headers, HttpPartStream) -> Response + Send + Sync;
+
+#[derive(Clone)]
+enum Handler {
+    Service(Arc<Service>),
+    Fn(Arc<RouteFn>),
+}
+
+impl Handler {
+    fn start_request(&self, params: RouteParams, headers: Headers, req: HttpPartStream) -> Response {
+        match self {
+            // `Service` can receive params only with context
+            &Handler::Service(ref service) => service.start_request(headers, req),
+            &Handler::Fn(ref f) => f(params, headers, req),
+        }
+    }
+
+    fn start_request_with_context(
+        &self,
+        mut context: RequestContext,
+        params: RouteParams,
+        headers: Headers,
+        req: HttpPartStream)
+            -> Response
+    {
+        match self {
+            &Handler::Service(ref service) => {
+                context.params = params;
+                service.start_request_with_context(context, headers, req)
+            }
+            &Handler::Fn(ref f) => f(params, headers, req),
+        }
+    }
+}
+
+struct Route {
+    segments: Vec<Segment>,
+    /// Method, or `None` for any method
+    handlers: Vec<(Option<String>, Handler)>,
+}
+
+impl Route {
+    /// Return captured params if path matches
+    fn matches(&self, path: &[&str]) -> Option<RouteParams> {
+        let mut params = RouteParams::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                &Segment::Literal(ref literal) => {
+                    if path.get(i) != Some(&&literal[..]) {
+                        return None;
+                    }
+                }
+                &Segment::Param(ref name) => {
+                    match path.get(i) {
+                        Some(value) => params.0.push((name.clone(), (*value).to_owned())),
+                        None => return None,
+                    }
+                }
+                &Segment::Wildcard(ref name) => {
+                    let rest = if i < path.len() { path[i..].join("/") } else { String::new() };
+                    params.0.push((name.clone(), rest));
+                    return Some(params);
+                }
+            }
+        }
+
+        if path.len() == self.segments.len() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+
+    fn rank(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
+
+    fn handler(&self, method: &str) -> Option<&Handler> {
+        self.handlers.iter()
+            .find(|&&(ref m, _)| m.as_ref().map(|m| &m[..]) == Some(method))
+            .or_else(|| self.handlers.iter().find(|&&(ref m, _)| m.is_none()))
+            .map(|&(_, ref s)| s)
+    }
+}
+
+/// Service which dispatches requests by path pattern and method.
+///
+/// Pattern segments can be literals, `:param` matching one segment
+/// or `*wildcard` matching the rest of the path. Query string is ignored.
+/// When several patterns match, the most specific one is used.
+///
+/// If path matches but no handler exists for the method, `405` with `allow` header is returned.
+///
+/// Captured params are passed to functions registered with `add_fn`.
+/// Services receive them in `RequestContext::params`
+/// when router is called with `start_request_with_context`, e. g. by server.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+enum RouteResult<'a> {
+    Found(&'a Handler, RouteParams),
+    MethodNotAllowed(Vec<String>),
+    NotFound,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Default::default()
+    }
+
+    fn add_handler(&mut self, method: Option<&str>, pattern: &str, handler: Handler) {
+        let segments = parse_pattern(pattern);
+        let method = method.map(|m| m.to_owned());
+
+        if let Some(route) = self.routes.iter_mut().find(|r| r.segments == segments) {
+            route.handlers.retain(|&(ref m, _)| *m != method);
+            route.handlers.push((method, handler));
+            return;
+        }
+
+        self.routes.push(Route {
+            segments: segments,
+            handlers: vec![(method, handler)],
+        });
+    }
+
+    /// Handle requests with given method and path pattern
+    pub fn add(&mut self, method: &str, pattern: &str, service: Arc<Service>) {
+        self.add_handler(Some(method), pattern, Handler::Service(service));
+    }
+
+    /// Handle requests with any method
+    pub fn add_any(&mut self, pattern: &str, service: Arc<Service>) {
+        self.add_handler(None, pattern, Handler::Service(service));
+    }
+
+    /// Handle requests with given method and path pattern by a function
+    /// receiving captured params
+    pub fn add_fn<F>(&mut self, method: &str, pattern: &str, f: F)
+        where F : Fn(RouteParams, Headers, HttpPartStream) -> Response + Send + Sync + 'static
+    {
+        self.add_handler(Some(method), pattern, Handler::Fn(Arc::new(f)));
+    }
+
+    fn route(&self, method: &str, path: &str) -> RouteResult {
+        let path = split_path(path);
+
+        let mut matched: Vec<(&Route, RouteParams)> = self.routes.iter()
+            .filter_map(|r| r.matches(&path).map(|p| (r, p)))
+            .collect();
+        matched.sort_by_key(|&(r, _)| r.rank());
+
+        let mut allow = Vec::new();
+        for (route, params) in matched {
+            if let Some(service) = route.handler(method) {
+                return RouteResult::Found(service, params);
+            }
+            for &(ref m, _) in &route.handlers {
+                if let &Some(ref m) = m {
+                    if !allow.contains(m) {
+                        allow.push(m.clone());
+                    }
+                }
+            }
+        }
+
+        if allow.is_empty() {
+            RouteResult::NotFound
+        } else {
+            RouteResult::MethodNotAllowed(allow)
+        }
+    }
+
+    fn method_not_allowed_405(allow: Vec<String>) -> Response {
+        let mut headers = Headers::from_status(405);
+        headers.add("allow", &allow.join(", "));
+        Response::headers(headers)
+    }
+}
+
+impl Service for Router {
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        let r = self.route(headers.method(), headers.path());
+        match r {
+            RouteResult::Found(handler, params) => handler.start_request(params, headers, req),
+            RouteResult::MethodNotAllowed(allow) => Router::method_not_allowed_405(allow),
+            RouteResult::NotFound => Response::not_found_404(),
+        }
+    }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        let r = self.route(headers.method(), headers.path());
+        match r {
+            RouteResult::Found(handler, params) => {
+                handler.start_request_with_context(context, params, headers, req)
+            }
+            RouteResult::MethodNotAllowed(allow) => Router::method_not_allowed_405(allow),
+            RouteResult::NotFound => Response::not_found_404(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::future::Future;
+
+    fn params(pairs: &[(&str, &str)]) -> RouteParams {
+        RouteParams(pairs.iter().map(|&(n, v)| (n.to_owned(), v.to_owned())).collect())
+    }
+
+    fn route(pattern: &str) -> Route {
+        Route {
+            segments: parse_pattern(pattern),
+            handlers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn matches() {
+        assert_eq!(Some(params(&[])), route("/").matches(&split_path("/")));
+        assert_eq!(Some(params(&[])), route("/a/b").matches(&split_path("/a/b?x=1")));
+        assert_eq!(None, route("/a/b").matches(&split_path("/a")));
+        assert_eq!(None, route("/a").matches(&split_path("/a/b")));
+        assert_eq!(Some(params(&[("id", "12")])), route("/users/:id").matches(&split_path("/users/12")));
+        assert_eq!(None, route("/users/:id").matches(&split_path("/users")));
+        assert_eq!(Some(params(&[("p", "x/y")])), route("/static/*p").matches(&split_path("/static/x/y")));
+        assert_eq!(Some(params(&[("p", "")])), route("/static/*p").matches(&split_path("/static")));
+    }
+
+    fn status(router: &Router, method: &str, path: &str) -> (u32, Option<String>) {
+        let mut headers = Headers::new();
+        headers.add(":method", method);
+        headers.add(":path", path);
+        let resp = router.start_request(headers, HttpPartStream::empty()).collect().wait().unwrap();
+        (resp.headers.status(), resp.headers.get_opt("allow").map(|a| a.to_owned()))
+    }
+
+    #[test]
+    fn route_by_specificity_and_method() {
+        let mut router = Router::new();
+        router.add_fn("GET", "/users/:id", |_, _, _| Response::headers(Headers::from_status(201)));
+        router.add_fn("GET", "/users/me", |_, _, _| Response::headers(Headers::from_status(202)));
+        router.add_fn("POST", "/users/:id", |_, _, _| Response::headers(Headers::from_status(203)));
+        router.add_fn("GET", "/*rest", |_, _, _| Response::headers(Headers::from_status(204)));
+
+        assert_eq!((201, None), status(&router, "GET", "/users/12"));
+        assert_eq!((202, None), status(&router, "GET", "/users/me?q"));
+        assert_eq!((203, None), status(&router, "POST", "/users/me"));
+        assert_eq!((204, None), status(&router, "GET", "/other"));
+        assert_eq!((405, Some("GET, POST".to_owned())), status(&router, "PUT", "/users/12"));
+        assert_eq!((405, Some("GET".to_owned())), status(&router, "PUT", "/other"));
+        assert_eq!((404, None), status(&Router::new(), "GET", "/"));
+    }
+
+    #[test]
+    fn add_fn_params() {
+        let mut router = Router::new();
+        router.add_fn("GET", "/users/:id/*rest", |params, _, _| {
+            let body = format!("{} {}", params.get("id").unwrap(), params.get("rest").unwrap());
+            Response::headers_and_bytes(Headers::ok_200(), body)
+        });
+
+        let mut headers = Headers::new();
+        headers.add(":method", "GET");
+        headers.add(":path", "/users/12/a/b?c");
+        let resp = router.start_request(headers, HttpPartStream::empty()).collect().wait().unwrap();
+        assert_eq!(&b"12 a/b"[..], &resp.body[..]);
+    }
+}
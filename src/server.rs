@@ -1,5 +1,5 @@
 use std::thread;
-use std::net::SocketAddr;
+use std::mem;
 use std::net::ToSocketAddrs;
 use std::collections::HashMap;
 use std::sync::mpsc;
@@ -35,14 +35,24 @@ use super::server_conn::*;
 use super::common::*;
 
 use service::Service;
+use service::ConnectionInfo;
 use service_paths::ServicePaths;
+use service_layer::Layer;
+use service_layer::Layered;
+use server_access_log::AccessLogFormat;
+use server_access_log::AccessLogRecord;
+use server_access_log::ServerAccessLog;
+use metrics::Metrics;
+use frame_trace::FrameTracer;
 
 use server_conf::*;
 use socket::AnySocketAddr;
 use socket::ToSocketListener;
 use socket::ToTokioListener;
+use socket_memory::MemoryAddr;
 
 pub use server_tls::ServerTlsOption;
+use server_tls::ServerTlsHandle;
 
 pub struct ServerBuilder<A : tls_api::TlsAcceptor = tls_api_stub::TlsAcceptor> {
     pub conf: ServerConf,
@@ -53,6 +63,8 @@ pub struct ServerBuilder<A : tls_api::TlsAcceptor = tls_api_stub::TlsAcceptor> {
     /// If not specified, builder will create new event loop in a new thread.
     pub event_loop: Option<reactor::Remote>,
     pub service: ServicePaths,
+    /// Layers around `service`, first layer is called first
+    pub layers: Vec<Arc<Layer>>,
 }
 
 impl ServerBuilder<tls_api_stub::TlsAcceptor> {
@@ -104,6 +116,14 @@ impl<A: tls_api::TlsAcceptor> ServerBuilder<A> {
     }
 }
 
+impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
+    /// Listen on in-process addr, clients connect with `ClientBuilder::set_memory_addr`
+    pub fn set_memory_addr(&mut self, name: &str) -> Result<()> {
+        self.addr = Some(AnySocketAddr::Memory(MemoryAddr(name.to_owned())));
+        Ok(())
+    }
+}
+
 impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
     /// New server builder with defaults.
     ///
@@ -119,6 +139,7 @@ impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
             addr: None,
             event_loop: None,
             service: ServicePaths::new(),
+            layers: Vec::new(),
         }
     }
 
@@ -131,8 +152,62 @@ impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
         self.cpu_pool = CpuPoolOption::CpuPool(cpu_pool);
     }
 
+    /// Use TLS with this acceptor.
+    ///
+    /// If acceptors for host names are added with `add_tls_for_host`,
+    /// this acceptor is used when client host name does not match any of them.
     pub fn set_tls(&mut self, acceptor: A) {
-        self.tls = ServerTlsOption::Tls(Arc::new(acceptor));
+        let tls = mem::replace(&mut self.tls, ServerTlsOption::Plain);
+        self.tls = tls.with_acceptor(Arc::new(acceptor));
+    }
+
+    /// Use this TLS acceptor when client sends given host name with SNI.
+    ///
+    /// Host name like `*.example.com` matches any subdomain of `example.com`.
+    pub fn add_tls_for_host(&mut self, host: &str, acceptor: A) {
+        let tls = mem::replace(&mut self.tls, ServerTlsOption::Plain);
+        self.tls = tls.with_acceptor_for_host(host, Arc::new(acceptor));
+    }
+
+    /// Get a handle to replace TLS configuration after server is started.
+    ///
+    /// `set_tls` and `add_tls_for_host` called after this function update the handle.
+    pub fn tls_handle(&mut self) -> ServerTlsHandle<A> {
+        if let ServerTlsOption::Handle(ref handle) = self.tls {
+            return handle.clone();
+        }
+        let handle = ServerTlsHandle::new(mem::replace(&mut self.tls, ServerTlsOption::Plain));
+        self.tls = ServerTlsOption::Handle(handle.clone());
+        handle
+    }
+
+    /// Call a function for each served stream
+    pub fn set_access_log<F>(&mut self, callback: F)
+        where F : Fn(&AccessLogRecord) + Send + Sync + 'static
+    {
+        self.conf.access_log = Some(ServerAccessLog::Callback(Arc::new(callback)));
+    }
+
+    /// Write access log with `log` crate to target `httpbis::access_log`
+    pub fn set_access_log_format(&mut self, format: AccessLogFormat) {
+        self.conf.access_log = Some(ServerAccessLog::Log(format));
+    }
+
+    /// Report connection events, e. g. to `MetricsRegistry`
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.conf.common.metrics = Some(metrics);
+    }
+
+    /// Trace every frame of every connection, e. g. with `TextFrameDump`
+    pub fn set_frame_tracer(&mut self, tracer: Arc<FrameTracer>) {
+        self.conf.common.frame_tracer = Some(tracer);
+    }
+
+    /// Wrap all services with a layer.
+    ///
+    /// Layers are called in order they are added.
+    pub fn add_layer<L : Layer>(&mut self, layer: L) {
+        self.layers.push(Arc::new(layer));
     }
 
     pub fn build(self) -> Result<Server> {
@@ -149,20 +224,22 @@ impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
 
         let listen = self.addr.unwrap().to_listener(&self.conf);
 
-        let local_addr = listen.local_addr().unwrap();
+        let local_addr = listen.local_addr()?;
         //let local_addr = local_addr.downcast_ref::<T>().expect("downcast socket_addr").clone();
 
         let join = if let Some(remote) = self.event_loop {
+            let local_addr_copy = local_addr.clone();
             let tls = self.tls;
             let cpu_pool = self.cpu_pool;
             let conf = self.conf;
-            let service = self.service;
+            let service = Layered::new(self.layers, Arc::new(self.service));
             remote.spawn(move |handle| {
                 drop(spawn_server_event_loop(
                     handle.clone(),
                     state_copy,
                     tls,
                     listen,
+                    local_addr_copy,
                     cpu_pool,
                     shutdown_future,
                     conf,
@@ -173,10 +250,11 @@ impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
             });
             Completion::Rx(done_rx)
         } else {
+            let local_addr_copy = local_addr.clone();
             let tls = self.tls;
             let cpu_pool = self.cpu_pool;
             let conf = self.conf;
-            let service = self.service;
+            let service = Layered::new(self.layers, Arc::new(self.service));
             let join_handle = thread::Builder::new()
                 .name(conf.thread_name.clone().unwrap_or_else(|| "http2-server-loop".to_owned()).to_string())
                 .spawn(move || {
@@ -186,6 +264,7 @@ impl<A : tls_api::TlsAcceptor> ServerBuilder<A> {
                         state_copy,
                         tls,
                         listen,
+                        local_addr_copy,
                         cpu_pool,
                         shutdown_future,
                         conf,
@@ -256,6 +335,7 @@ fn spawn_server_event_loop<S, A>(
     state: Arc<Mutex<ServerState>>,
     tls: ServerTlsOption<A>,
     listen: Box<ToTokioListener + Send>,
+    local_addr: AnySocketAddr,
     exec: CpuPoolOption,
     shutdown_future: ShutdownFuture,
     conf: ServerConf,
@@ -268,30 +348,41 @@ fn spawn_server_event_loop<S, A>(
 
     let tokio_listener = listen.to_tokio_listener(&handle);
 
-    let stuff = stream::repeat((handle.clone(), service, state, tls, conf));
+    let stuff = stream::repeat((handle.clone(), service, state, tls, conf, local_addr));
 
     let loop_run = tokio_listener.incoming().map_err(Error::from).zip(stuff)
-        .for_each(move |((socket, peer_addr), (loop_handle, service, state, tls, conf))| {
+        .for_each(move |((socket, peer_addr), (loop_handle, service, state, tls, conf, local_addr))| {
 
-            if socket.is_tcp() {
-                info!("accepted connection from {}",
-                    peer_addr.downcast_ref::<SocketAddr>().unwrap());
+            info!("accepted connection from {}", peer_addr);
 
+            if socket.is_tcp() {
                 let no_delay = conf.no_delay.unwrap_or(true);
                 socket.set_nodelay(no_delay).expect("failed to set TCP_NODELAY");
             }
 
-            let (conn, future) = ServerConnection::new(
-                &loop_handle, socket, tls, exec.clone(), conf, service);
-
             let conn_id = {
                 let mut g = state.lock().expect("lock");
                 g.last_conn_id += 1;
-                let conn_id = g.last_conn_id;
+                g.last_conn_id
+            };
+
+            let conn_info = ConnectionInfo {
+                conn_id: conn_id,
+                peer_addr: peer_addr,
+                local_addr: local_addr,
+                tls: false,
+                sni_host_name: None,
+                alpn_protocol: None,
+            };
+
+            let (conn, future) = ServerConnection::new_with_conn_info(
+                &loop_handle, socket, conn_info, tls, exec.clone(), conf, service);
+
+            {
+                let mut g = state.lock().expect("lock");
                 let prev = g.conns.insert(conn_id, conn);
                 assert!(prev.is_none());
-                conn_id
-            };
+            }
 
             loop_handle.spawn(future
                 .then(move |r| {
@@ -0,0 +1,258 @@
+//! Server access log
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use error::ErrorCode;
+use solicit::StreamId;
+use solicit::header::Headers;
+use socket::AnySocketAddr;
+use service::ConnectionInfo;
+use common::HttpStreamStats;
+use http_date::MONTHS;
+use http_date::utc;
+
+
+/// Information about a served stream
+#[derive(Debug, Clone)]
+pub struct AccessLogRecord {
+    pub conn_id: u64,
+    pub peer_addr: AnySocketAddr,
+    pub stream_id: StreamId,
+    /// Time when request was received
+    pub time: SystemTime,
+    pub method: String,
+    pub path: String,
+    pub authority: String,
+    /// `None` if response headers were not sent
+    pub status: Option<u32>,
+    /// Bytes of request body
+    pub request_bytes: u64,
+    /// Bytes of response body
+    pub response_bytes: u64,
+    /// Time from request received to stream closed
+    pub duration: Duration,
+    /// Error code of `RST_STREAM` received from the client
+    pub rst_received: Option<ErrorCode>,
+    /// Error code of `RST_STREAM` sent to the client
+    pub rst_sent: Option<ErrorCode>,
+}
+
+fn json_string(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+fn json_opt<T : fmt::Debug>(value: Option<T>) -> String {
+    match value {
+        Some(value) => json_string(&format!("{:?}", value)),
+        None => "null".to_owned(),
+    }
+}
+
+impl AccessLogRecord {
+    /// Format record in NCSA Common Log Format
+    pub fn to_common(&self) -> String {
+        let host = match self.peer_addr {
+            AnySocketAddr::Inet(ref addr) => format!("{}", addr.ip()),
+            AnySocketAddr::Unix(ref path) if !path.is_empty() => path.clone(),
+            AnySocketAddr::Unix(..) => "-".to_owned(),
+            AnySocketAddr::Memory(..) => "-".to_owned(),
+        };
+        let (year, month, day, hour, minute, second) = utc(self.time);
+        let status = match self.status {
+            Some(status) => format!("{}", status),
+            None => "-".to_owned(),
+        };
+        let bytes = match self.response_bytes {
+            0 => "-".to_owned(),
+            bytes => format!("{}", bytes),
+        };
+        format!("{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} HTTP/2.0\" {} {}",
+            host, day, MONTHS[month as usize - 1], year, hour, minute, second,
+            self.method, self.path, status, bytes)
+    }
+
+    /// Format record as single line JSON object
+    pub fn to_json(&self) -> String {
+        let (year, month, day, hour, minute, second) = utc(self.time);
+        let time = format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second);
+        let status = match self.status {
+            Some(status) => format!("{}", status),
+            None => "null".to_owned(),
+        };
+        let duration_us = self.duration.as_secs() * 1_000_000 + (self.duration.subsec_nanos() / 1000) as u64;
+        format!("{{\"time\":{},\"conn_id\":{},\"peer_addr\":{},\"stream_id\":{},\"method\":{},\
+                \"path\":{},\"authority\":{},\"status\":{},\"request_bytes\":{},\"response_bytes\":{},\
+                \"duration_us\":{},\"rst_received\":{},\"rst_sent\":{}}}",
+            json_string(&time),
+            self.conn_id,
+            json_string(&format!("{}", self.peer_addr)),
+            self.stream_id,
+            json_string(&self.method),
+            json_string(&self.path),
+            json_string(&self.authority),
+            status,
+            self.request_bytes,
+            self.response_bytes,
+            duration_us,
+            json_opt(self.rst_received),
+            json_opt(self.rst_sent))
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// NCSA Common Log Format
+    Common,
+    /// One JSON object per line
+    Json,
+}
+
+/// Where server sends access log records
+#[derive(Clone)]
+pub enum ServerAccessLog {
+    Callback(Arc<Fn(&AccessLogRecord) + Send + Sync>),
+    /// Log formatted records with `info!` to target `httpbis::access_log`
+    Log(AccessLogFormat),
+}
+
+impl fmt::Debug for ServerAccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ServerAccessLog::Callback(..) => write!(f, "Callback(..)"),
+            &ServerAccessLog::Log(format) => write!(f, "Log({:?})", format),
+        }
+    }
+}
+
+impl ServerAccessLog {
+    fn write(&self, record: &AccessLogRecord) {
+        match self {
+            &ServerAccessLog::Callback(ref callback) => callback(record),
+            &ServerAccessLog::Log(AccessLogFormat::Common) => {
+                info!(target: "httpbis::access_log", "{}", record.to_common());
+            }
+            &ServerAccessLog::Log(AccessLogFormat::Json) => {
+                info!(target: "httpbis::access_log", "{}", record.to_json());
+            }
+        }
+    }
+}
+
+
+/// Access log record being collected while stream is open
+pub struct StreamAccessLog {
+    log: ServerAccessLog,
+    conn: Arc<ConnectionInfo>,
+    stream_id: StreamId,
+    time: SystemTime,
+    start: Instant,
+    method: String,
+    path: String,
+    authority: String,
+    /// Response status, zero if response headers were not sent
+    pub status: Arc<AtomicUsize>,
+}
+
+impl StreamAccessLog {
+    pub fn new(log: ServerAccessLog, conn: Arc<ConnectionInfo>, stream_id: StreamId, headers: &Headers)
+        -> StreamAccessLog
+    {
+        StreamAccessLog {
+            log: log,
+            conn: conn,
+            stream_id: stream_id,
+            time: SystemTime::now(),
+            start: Instant::now(),
+            method: headers.get_opt(":method").unwrap_or("").to_owned(),
+            path: headers.get_opt(":path").unwrap_or("").to_owned(),
+            authority: headers.get_opt(":authority").unwrap_or("").to_owned(),
+            status: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Stream is closed, write the record
+    pub fn finish(self, stats: &HttpStreamStats) {
+        let status = self.status.load(Ordering::SeqCst);
+        let record = AccessLogRecord {
+            conn_id: self.conn.conn_id,
+            peer_addr: self.conn.peer_addr.clone(),
+            stream_id: self.stream_id,
+            time: self.time,
+            method: self.method,
+            path: self.path,
+            authority: self.authority,
+            status: if status != 0 { Some(status as u32) } else { None },
+            request_bytes: stats.in_data_bytes,
+            response_bytes: stats.out_data_bytes,
+            duration: self.start.elapsed(),
+            rst_received: stats.rst_received,
+            rst_sent: stats.rst_sent,
+        };
+        self.log.write(&record);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    fn record() -> AccessLogRecord {
+        AccessLogRecord {
+            conn_id: 3,
+            peer_addr: AnySocketAddr::Inet("127.0.0.1:4567".parse().unwrap()),
+            stream_id: 5,
+            // 2000-10-10T13:55:36Z
+            time: UNIX_EPOCH + Duration::from_secs(971186136),
+            method: "GET".to_owned(),
+            path: "/a\"b".to_owned(),
+            authority: "example.com".to_owned(),
+            status: Some(200),
+            request_bytes: 0,
+            response_bytes: 2326,
+            duration: Duration::from_millis(1500),
+            rst_received: None,
+            rst_sent: Some(ErrorCode::Cancel),
+        }
+    }
+
+    #[test]
+    fn common() {
+        assert_eq!(
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /a\"b HTTP/2.0\" 200 2326",
+            record().to_common());
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            "{\"time\":\"2000-10-10T13:55:36Z\",\"conn_id\":3,\"peer_addr\":\"127.0.0.1:4567\",\
+            \"stream_id\":5,\"method\":\"GET\",\"path\":\"/a\\\"b\",\"authority\":\"example.com\",\
+            \"status\":200,\"request_bytes\":0,\"response_bytes\":2326,\"duration_us\":1500000,\
+            \"rst_received\":null,\"rst_sent\":\"Cancel\"}",
+            record().to_json());
+    }
+}
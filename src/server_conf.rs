@@ -1,5 +1,7 @@
 use common::CommonConf;
 
+use server_access_log::ServerAccessLog;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServerAlpn {
     // Ignore negotiated ALPN
@@ -23,6 +25,9 @@ pub struct ServerConf {
     pub reuse_port: Option<bool>,
     pub backlog: Option<i32>,
 
+    /// Record served streams
+    pub access_log: Option<ServerAccessLog>,
+
     pub common: CommonConf,
 }
 
@@ -1,6 +1,11 @@
 use std::io;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::panic;
+use std::time::Instant;
 
 use error;
 use result;
@@ -36,11 +41,18 @@ use futures_misc::*;
 
 use solicit_async::*;
 use service::Service;
+use service::ConnectionInfo;
+use service::RequestContext;
+use router::RouteParams;
 use stream_part::*;
 use common::*;
 
 use server_tls::*;
 use server_conf::*;
+use server_sni::PeekClientHelloSni;
+use server_access_log::StreamAccessLog;
+use server_access_log::ServerAccessLog;
+use socket::AnySocketAddr;
 use socket::StreamItem;
 
 use misc::any_to_string;
@@ -58,13 +70,24 @@ impl Types for ServerTypes {
     fn first_id() -> StreamId {
         2
     }
+
+    fn cancel_stream_on_drop() -> bool {
+        // Handler may ignore request body
+        false
+    }
 }
 
 
 pub struct ServerStreamData {
+    access_log: Option<StreamAccessLog>,
 }
 
 impl HttpStreamDataSpecific for ServerStreamData {
+    fn stream_closed(&mut self, stats: &HttpStreamStats) {
+        if let Some(access_log) = self.access_log.take() {
+            access_log.finish(stats);
+        }
+    }
 }
 
 type ServerStream = HttpStreamCommon<ServerTypes>;
@@ -88,6 +111,8 @@ impl HttpStream for ServerStream {
 
 struct ServerConnData {
     factory: Arc<Service>,
+    conn_info: Arc<ConnectionInfo>,
+    access_log: Option<ServerAccessLog>,
 }
 
 impl ConnDataSpecific for ServerConnData {
@@ -112,20 +137,36 @@ impl ServerInner {
 
         debug!("new stream: {}", stream_id);
 
+        let access_log = self.specific.access_log.clone().map(|log| {
+            StreamAccessLog::new(log, self.specific.conn_info.clone(), stream_id, &headers)
+        });
+        let response_status = access_log.as_ref().map(|l| l.status.clone());
+
         let (_, req_stream, out_window) = self.new_stream_data(
             stream_id,
-            ServerStreamData {});
+            ServerStreamData {
+                access_log: access_log,
+            });
 
         let req_stream = HttpPartStream::new(req_stream);
 
         let factory = self.specific.factory.clone();
 
+        let context = RequestContext {
+            conn: self.specific.conn_info.clone(),
+            stream_id: stream_id,
+            params: RouteParams::new(),
+        };
+
         let to_write_tx = self.to_write_tx.clone();
 
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+
         self.exec.execute(Box::new(future::lazy(move || {
             let response = panic::catch_unwind(panic::AssertUnwindSafe(|| {
                 // TODO: do start request in executor
-                factory.start_request(headers, req_stream)
+                factory.start_request_with_context(context, headers, req_stream)
             }));
 
             let response = response.unwrap_or_else(|e| {
@@ -139,8 +180,21 @@ impl ServerInner {
                 ]))
             });
 
-            let response = response.into_part_stream();
-            let response = response.catch_unwind();
+            let mut response_headers_seen = false;
+            let response = response.into_part_stream().map(move |part| {
+                if let HttpStreamPartContent::Headers(ref headers) = part.content {
+                    if !response_headers_seen {
+                        response_headers_seen = true;
+                        metrics.handler_latency(start.elapsed());
+                        if let Some(ref response_status) = response_status {
+                            let status = headers.get_opt_parse(":status").unwrap_or(0);
+                            response_status.store(status, Ordering::SeqCst);
+                        }
+                    }
+                }
+                part
+            });
+            let response = HttpPartStream::new(response).catch_unwind();
 
             PumpStreamToWriteLoop::<ServerTypes> {
                 to_write_tx: to_write_tx,
@@ -254,12 +308,29 @@ impl ServerCommandLoop {
 
 
 
+/// Ids of connections created by `new_plain_single_thread`,
+/// `Server` allocates ids of its connections itself
+static NEXT_SINGLE_THREAD_CONN_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Info of connection created without `Server`, unknown addresses are unspecified
+fn single_conn_info(peer_addr: Option<SocketAddr>, local_addr: Option<SocketAddr>) -> ConnectionInfo {
+    let unspecified = SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 0);
+    ConnectionInfo {
+        conn_id: NEXT_SINGLE_THREAD_CONN_ID.fetch_add(1, Ordering::Relaxed) as u64,
+        peer_addr: AnySocketAddr::Inet(peer_addr.unwrap_or(unspecified)),
+        local_addr: AnySocketAddr::Inet(local_addr.unwrap_or(unspecified)),
+        tls: false,
+        sni_host_name: None,
+        alpn_protocol: None,
+    }
+}
+
 pub struct ServerConnection {
     command_tx: UnboundedSender<ServerCommandMessage>,
 }
 
 impl ServerConnection {
-    fn connected<F, I>(lh: &reactor::Handle, socket: HttpFutureSend<I>, cpu_pool: CpuPoolOption, conf: ServerConf, service: Arc<F>)
+    fn connected<F, I>(lh: &reactor::Handle, socket: HttpFutureSend<(I, ConnectionInfo)>, cpu_pool: CpuPoolOption, conf: ServerConf, service: Arc<F>)
         -> (ServerConnection, HttpFuture<()>)
         where
             F : Service,
@@ -277,9 +348,11 @@ impl ServerConnection {
         let mut settings = DEFAULT_SETTINGS;
         settings.apply_from_frame(&settings_frame);
 
-        let handshake = socket.and_then(|conn| server_handshake(conn, settings_frame));
+        let handshake = socket.and_then(|(conn, conn_info)| {
+            server_handshake(conn, settings_frame).map(move |conn| (conn, conn_info))
+        });
 
-        let run = handshake.and_then(move |socket| {
+        let run = handshake.and_then(move |(socket, conn_info)| {
             let (read, write) = socket.split();
 
             let inner = RcMut::new(ConnData::new(
@@ -287,6 +360,8 @@ impl ServerConnection {
                 cpu_pool,
                 ServerConnData {
                     factory: service,
+                    conn_info: Arc::new(conn_info),
+                    access_log: conf.access_log,
                 },
                 conf.common,
                 settings,
@@ -306,6 +381,7 @@ impl ServerConnection {
         }, future)
     }
 
+    /// Serve connection, addresses in `ConnectionInfo` passed to service are unspecified
     pub fn new<S, A>(
         lh: &reactor::Handle,
         socket: Box<StreamItem>,
@@ -314,27 +390,108 @@ impl ServerConnection {
         conf: ServerConf, service: Arc<S>)
             -> (ServerConnection, HttpFuture<()>)
         where S : Service, A : TlsAcceptor
+    {
+        let conn_info = single_conn_info(None, None);
+        ServerConnection::new_with_conn_info(lh, socket, conn_info, tls, exec, conf, service)
+    }
+
+    /// Serve connection, `conn_info` is passed to service in `RequestContext`
+    pub fn new_with_conn_info<S, A>(
+        lh: &reactor::Handle,
+        socket: Box<StreamItem>,
+        conn_info: ConnectionInfo,
+        tls: ServerTlsOption<A>,
+        exec: CpuPoolOption,
+        conf: ServerConf, service: Arc<S>)
+            -> (ServerConnection, HttpFuture<()>)
+        where S : Service, A : TlsAcceptor
     {
         match tls {
             ServerTlsOption::Plain => {
-                let socket = Box::new(future::finished(socket));
+                let socket = Box::new(future::finished((socket, conn_info)));
                 ServerConnection::connected(lh, socket, exec, conf, service)
             }
             ServerTlsOption::Tls(acceptor) => {
-                let socket = Box::new(
-                    tokio_tls_api::accept_async(&*acceptor, socket).map_err(error::Error::from));
+                let socket = ServerConnection::accept_tls(socket, conn_info, &conf, move |_| Some(acceptor));
+                ServerConnection::connected(lh, socket, exec, conf, service)
+            }
+            ServerTlsOption::Sni(acceptors) => {
+                let socket = ServerConnection::accept_tls(socket, conn_info, &conf, move |sni_host_name| {
+                    acceptors.select(sni_host_name).cloned()
+                });
                 ServerConnection::connected(lh, socket, exec, conf, service)
             }
+            ServerTlsOption::Handle(handle) => {
+                ServerConnection::new_with_conn_info(lh, socket, conn_info, handle.current(), exec, conf, service)
+            }
         }
     }
 
+    /// Read SNI host name from ClientHello, and do TLS handshake with selected acceptor
+    fn accept_tls<A, F>(socket: Box<StreamItem>, conn_info: ConnectionInfo, conf: &ServerConf, select_acceptor: F)
+        -> HttpFutureSend<(tokio_tls_api::TlsStream<Box<StreamItem>>, ConnectionInfo)>
+        where
+            A : TlsAcceptor,
+            F : FnOnce(Option<&str>) -> Option<Arc<A>> + Send + 'static,
+    {
+        let alpn = conf.alpn.clone().unwrap_or(ServerAlpn::Ignore);
+
+        Box::new(PeekClientHelloSni::new(socket).and_then(move |(socket, sni_host_name)| {
+            let acceptor = match select_acceptor(sni_host_name.as_ref().map(|h| &h[..])) {
+                Some(acceptor) => acceptor,
+                None => {
+                    warn!("no TLS acceptor for SNI host name {:?}", sni_host_name);
+                    let r: HttpFutureSend<_> =
+                        Box::new(future::failed(error::Error::Other("no TLS acceptor for SNI host name")));
+                    return r;
+                }
+            };
+
+            let socket = tokio_tls_api::accept_async(&*acceptor, socket).map_err(error::Error::from);
+            Box::new(socket.and_then(move |socket| {
+                let negotiated = socket.get_ref().get_alpn_protocol();
+                let is_h2 = negotiated.as_ref().map(|p| &p[..]) == Some(&b"h2"[..]);
+                if alpn == ServerAlpn::Require && !is_h2 {
+                    return Err(error::Error::AlpnMismatch(negotiated));
+                }
+                let conn_info = ConnectionInfo {
+                    tls: true,
+                    sni_host_name: sni_host_name,
+                    alpn_protocol: negotiated,
+                    ..conn_info
+                };
+                Ok((socket, conn_info))
+            }))
+        }))
+    }
+
+    /// Serve single connection accepted without `Server`.
+    ///
+    /// Addresses which cannot be obtained from socket (e. g. if peer already disconnected)
+    /// are unspecified in `ConnectionInfo`.
     pub fn new_plain_single_thread<S>(lh: &reactor::Handle, socket: TcpStream, conf: ServerConf, service: Arc<S>)
         -> (ServerConnection, HttpFuture<()>)
         where
             S : Service,
+    {
+        let conn_info = single_conn_info(socket.peer_addr().ok(), socket.local_addr().ok());
+        ServerConnection::new_plain_single_thread_with_conn_info(lh, socket, conn_info, conf, service)
+    }
+
+    /// Serve single connection accepted without `Server` with given `ConnectionInfo`
+    pub fn new_plain_single_thread_with_conn_info<S>(
+        lh: &reactor::Handle,
+        socket: TcpStream,
+        conn_info: ConnectionInfo,
+        conf: ServerConf,
+        service: Arc<S>)
+            -> (ServerConnection, HttpFuture<()>)
+        where
+            S : Service,
     {
         let no_tls: ServerTlsOption<tls_api_stub::TlsAcceptor> = ServerTlsOption::Plain;
-        ServerConnection::new(lh, Box::new(socket), no_tls, CpuPoolOption::SingleThread, conf, service)
+        ServerConnection::new_with_conn_info(
+            lh, Box::new(socket), conn_info, no_tls, CpuPoolOption::SingleThread, conf, service)
     }
 
     pub fn new_plain_single_thread_fn<F>(lh: &reactor::Handle, socket: TcpStream, conf: ServerConf, f: F)
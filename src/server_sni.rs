@@ -0,0 +1,327 @@
+//! Peek host name from TLS ClientHello before TLS handshake
+
+use std::cmp;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::mem;
+use std::str;
+
+use futures::Async;
+use futures::Poll;
+use futures::future::Future;
+
+use tokio_io::AsyncRead;
+use tokio_io::AsyncWrite;
+
+use error;
+
+use socket::StreamItem;
+
+
+/// Do not read more than this waiting for ClientHello
+const MAX_CLIENT_HELLO_LEN: usize = 64 * 1024;
+
+/// Result of parsing of the beginning of TLS connection
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClientHelloSni {
+    /// More data needed
+    Incomplete,
+    /// ClientHello parsed, SNI host name if present
+    Done(Option<String>),
+}
+
+/// Minimal reader of TLS structures
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (r, rem) = self.buf.split_at(n);
+        self.buf = rem;
+        Some(r)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<usize> {
+        self.take(2).map(|b| ((b[0] as usize) << 8) | (b[1] as usize))
+    }
+
+    fn vec_u8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    fn vec_u16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(len)
+    }
+}
+
+/// Find SNI host name in ClientHello message body
+fn parse_client_hello_body(body: &[u8]) -> Option<String> {
+    let mut r = Reader { buf: body };
+    r.take(2)?; // client_version
+    r.take(32)?; // random
+    r.vec_u8()?; // session_id
+    r.vec_u16()?; // cipher_suites
+    r.vec_u8()?; // compression_methods
+
+    let mut extensions = Reader { buf: r.vec_u16()? };
+    while !extensions.buf.is_empty() {
+        let extension_type = extensions.u16()?;
+        let extension_data = extensions.vec_u16()?;
+
+        // server_name, RFC 6066
+        if extension_type == 0 {
+            let mut names = Reader { buf: Reader { buf: extension_data }.vec_u16()? };
+            while !names.buf.is_empty() {
+                let name_type = names.u8()?;
+                let name = names.vec_u16()?;
+                // host_name
+                if name_type == 0 {
+                    return str::from_utf8(name).ok().map(|n| n.to_ascii_lowercase());
+                }
+            }
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Extract SNI host name from ClientHello at the beginning of TLS connection.
+///
+/// Malformed input is reported as `Done(None)`, so TLS implementation could report an error.
+pub fn parse_client_hello_sni(buf: &[u8]) -> ClientHelloSni {
+    // ClientHello may be split across several records
+    let mut handshake = Vec::new();
+    let mut pos = 0;
+    loop {
+        if pos >= MAX_CLIENT_HELLO_LEN {
+            return ClientHelloSni::Done(None);
+        }
+
+        if buf.len() < pos + 5 {
+            return ClientHelloSni::Incomplete;
+        }
+
+        // Content type must be handshake
+        if buf[pos] != 0x16 {
+            return ClientHelloSni::Done(None);
+        }
+
+        let len = ((buf[pos + 3] as usize) << 8) | (buf[pos + 4] as usize);
+        if buf.len() < pos + 5 + len {
+            return ClientHelloSni::Incomplete;
+        }
+
+        handshake.extend_from_slice(&buf[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+
+        if handshake.len() >= 4 {
+            // Message type must be client_hello
+            if handshake[0] != 1 {
+                return ClientHelloSni::Done(None);
+            }
+
+            let message_len =
+                ((handshake[1] as usize) << 16) | ((handshake[2] as usize) << 8) | (handshake[3] as usize);
+            if handshake.len() >= 4 + message_len {
+                return ClientHelloSni::Done(parse_client_hello_body(&handshake[4..4 + message_len]));
+            }
+        }
+    }
+}
+
+
+/// Stream which returns prefix before reading from the underlying stream
+#[derive(Debug)]
+pub struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    stream: S,
+}
+
+impl<S> PrefixedStream<S> {
+    pub fn new(prefix: Vec<u8>, stream: S) -> PrefixedStream<S> {
+        PrefixedStream {
+            prefix: prefix,
+            pos: 0,
+            stream: stream,
+        }
+    }
+}
+
+impl<S : Read> Read for PrefixedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() {
+            let count = cmp::min(buf.len(), self.prefix.len() - self.pos);
+            buf[..count].copy_from_slice(&self.prefix[self.pos..self.pos + count]);
+            self.pos += count;
+            return Ok(count);
+        }
+        self.stream.read(buf)
+    }
+}
+
+impl<S : Write> Write for PrefixedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<S : AsyncRead> AsyncRead for PrefixedStream<S> {
+}
+
+impl<S : AsyncWrite> AsyncWrite for PrefixedStream<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.stream.shutdown()
+    }
+}
+
+impl StreamItem for PrefixedStream<Box<StreamItem>> {
+    fn is_tcp(&self) -> bool {
+        self.stream.is_tcp()
+    }
+
+    fn set_nodelay(&self, no_delay: bool) -> io::Result<()> {
+        self.stream.set_nodelay(no_delay)
+    }
+}
+
+
+/// Read ClientHello from the socket, and return socket which replays it,
+/// and SNI host name
+pub struct PeekClientHelloSni {
+    socket: Option<Box<StreamItem>>,
+    buf: Vec<u8>,
+}
+
+impl PeekClientHelloSni {
+    pub fn new(socket: Box<StreamItem>) -> PeekClientHelloSni {
+        PeekClientHelloSni {
+            socket: Some(socket),
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl Future for PeekClientHelloSni {
+    type Item = (Box<StreamItem>, Option<String>);
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, error::Error> {
+        loop {
+            if let ClientHelloSni::Done(sni) = parse_client_hello_sni(&self.buf) {
+                let socket = self.socket.take().expect("poll after completed");
+                let buf = mem::replace(&mut self.buf, Vec::new());
+                let socket: Box<StreamItem> = Box::new(PrefixedStream::new(buf, socket));
+                return Ok(Async::Ready((socket, sni)));
+            }
+
+            let mut buf = [0; 4096];
+            let count = match self.socket.as_mut().expect("poll after completed").read(&mut buf) {
+                Ok(count) => count,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Async::NotReady);
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if count == 0 {
+                let io_error = io::Error::new(io::ErrorKind::UnexpectedEof, "EOF before ClientHello");
+                return Err(error::Error::from(io_error));
+            }
+
+            self.buf.extend_from_slice(&buf[..count]);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u16_bytes(n: usize) -> Vec<u8> {
+        vec![(n >> 8) as u8, n as u8]
+    }
+
+    fn client_hello(host: Option<&str>) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        // unrelated extension first
+        extensions.extend(&[0x00, 0x17, 0x00, 0x00]);
+        if let Some(host) = host {
+            let mut name = vec![0];
+            name.extend(u16_bytes(host.len()));
+            name.extend(host.as_bytes());
+            let mut list = u16_bytes(name.len());
+            list.extend(name);
+            extensions.extend(&[0x00, 0x00]);
+            extensions.extend(u16_bytes(list.len()));
+            extensions.extend(list);
+        }
+
+        let mut body = vec![0x03, 0x03];
+        body.extend(&[7; 32]);
+        body.push(0); // session_id
+        body.extend(&[0x00, 0x02, 0x13, 0x01]); // cipher_suites
+        body.extend(&[0x01, 0x00]); // compression_methods
+        body.extend(u16_bytes(extensions.len()));
+        body.extend(extensions);
+
+        let mut handshake = vec![1, 0];
+        handshake.extend(u16_bytes(body.len()));
+        handshake.extend(body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend(u16_bytes(handshake.len()));
+        record.extend(handshake);
+        record
+    }
+
+    #[test]
+    fn sni() {
+        let hello = client_hello(Some("Example.COM"));
+        assert_eq!(ClientHelloSni::Done(Some("example.com".to_owned())), parse_client_hello_sni(&hello));
+    }
+
+    #[test]
+    fn no_sni() {
+        let hello = client_hello(None);
+        assert_eq!(ClientHelloSni::Done(None), parse_client_hello_sni(&hello));
+    }
+
+    #[test]
+    fn incomplete() {
+        let hello = client_hello(Some("example.com"));
+        for len in 0..hello.len() {
+            assert_eq!(ClientHelloSni::Incomplete, parse_client_hello_sni(&hello[..len]));
+        }
+    }
+
+    #[test]
+    fn not_tls() {
+        assert_eq!(ClientHelloSni::Done(None), parse_client_hello_sni(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"));
+    }
+
+    #[test]
+    fn prefixed_stream() {
+        let mut stream = PrefixedStream::new(b"ab".to_vec(), &b"cd"[..]);
+        let mut r = Vec::new();
+        stream.read_to_end(&mut r).unwrap();
+        assert_eq!(b"abcd", &r[..]);
+    }
+}
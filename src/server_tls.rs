@@ -1,4 +1,7 @@
+use std::mem;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::collections::HashMap;
 
 use tls_api::TlsAcceptor;
 
@@ -6,6 +9,10 @@ use tls_api::TlsAcceptor;
 pub enum ServerTlsOption<A : TlsAcceptor> {
     Plain,
     Tls(Arc<A>),
+    /// Acceptor is selected by host name client sent with SNI
+    Sni(Arc<ServerSniAcceptors<A>>),
+    /// Configuration which can be replaced while server is running
+    Handle(ServerTlsHandle<A>),
 }
 
 impl<A : TlsAcceptor> Clone for ServerTlsOption<A> {
@@ -13,6 +20,158 @@ impl<A : TlsAcceptor> Clone for ServerTlsOption<A> {
         match self {
             &ServerTlsOption::Plain => ServerTlsOption::Plain,
             &ServerTlsOption::Tls(ref a) => ServerTlsOption::Tls(a.clone()),
+            &ServerTlsOption::Sni(ref a) => ServerTlsOption::Sni(a.clone()),
+            &ServerTlsOption::Handle(ref h) => ServerTlsOption::Handle(h.clone()),
         }
     }
 }
+
+impl<A : TlsAcceptor> ServerTlsOption<A> {
+    /// Configuration to be used for new connection
+    pub fn current(&self) -> ServerTlsOption<A> {
+        match self {
+            &ServerTlsOption::Handle(ref h) => h.current(),
+            tls => tls.clone(),
+        }
+    }
+
+    /// Replace acceptor, or default acceptor if acceptors are selected by SNI
+    pub fn with_acceptor(self, acceptor: Arc<A>) -> ServerTlsOption<A> {
+        match self {
+            ServerTlsOption::Sni(mut acceptors) => {
+                Arc::make_mut(&mut acceptors).default = Some(acceptor);
+                ServerTlsOption::Sni(acceptors)
+            }
+            ServerTlsOption::Handle(h) => {
+                h.set_acceptor(acceptor);
+                ServerTlsOption::Handle(h)
+            }
+            ServerTlsOption::Plain | ServerTlsOption::Tls(..) => ServerTlsOption::Tls(acceptor),
+        }
+    }
+
+    /// Add acceptor for SNI host name; previous acceptor becomes default
+    pub fn with_acceptor_for_host(self, host: &str, acceptor: Arc<A>) -> ServerTlsOption<A> {
+        let mut acceptors = match self {
+            ServerTlsOption::Plain => ServerSniAcceptors::new(),
+            ServerTlsOption::Tls(default) => {
+                let mut acceptors = ServerSniAcceptors::new();
+                acceptors.default = Some(default);
+                acceptors
+            }
+            ServerTlsOption::Sni(acceptors) => {
+                Arc::try_unwrap(acceptors).unwrap_or_else(|acceptors| (*acceptors).clone())
+            }
+            ServerTlsOption::Handle(h) => {
+                h.add_acceptor_for_host(host, acceptor);
+                return ServerTlsOption::Handle(h);
+            }
+        };
+        acceptors.add_host(host, acceptor);
+        ServerTlsOption::Sni(Arc::new(acceptors))
+    }
+}
+
+
+/// Handle to replace TLS configuration of running server.
+///
+/// New connections use the new configuration, existing connections keep theirs.
+pub struct ServerTlsHandle<A : TlsAcceptor> {
+    tls: Arc<Mutex<ServerTlsOption<A>>>,
+}
+
+impl<A : TlsAcceptor> Clone for ServerTlsHandle<A> {
+    fn clone(&self) -> Self {
+        ServerTlsHandle {
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl<A : TlsAcceptor> ServerTlsHandle<A> {
+    pub fn new(tls: ServerTlsOption<A>) -> ServerTlsHandle<A> {
+        ServerTlsHandle {
+            tls: Arc::new(Mutex::new(tls.current())),
+        }
+    }
+
+    fn update<F>(&self, f: F)
+        where F : FnOnce(ServerTlsOption<A>) -> ServerTlsOption<A>
+    {
+        let mut g = self.tls.lock().expect("lock");
+        let tls = mem::replace(&mut *g, ServerTlsOption::Plain);
+        *g = f(tls);
+    }
+
+    /// Current configuration
+    pub fn current(&self) -> ServerTlsOption<A> {
+        self.tls.lock().expect("lock").clone()
+    }
+
+    /// Replace whole configuration
+    pub fn set(&self, tls: ServerTlsOption<A>) {
+        // Store a snapshot to avoid cycles of handles
+        let tls = tls.current();
+        self.update(|_| tls);
+    }
+
+    /// Replace acceptor, or default acceptor if acceptors are selected by SNI
+    pub fn set_acceptor(&self, acceptor: Arc<A>) {
+        self.update(|tls| tls.with_acceptor(acceptor));
+    }
+
+    /// Replace acceptor for SNI host name
+    pub fn add_acceptor_for_host(&self, host: &str, acceptor: Arc<A>) {
+        self.update(|tls| tls.with_acceptor_for_host(host, acceptor));
+    }
+}
+
+
+/// TLS acceptors (i. e. certificates) for different host names
+pub struct ServerSniAcceptors<A : TlsAcceptor> {
+    /// Used when client sent no SNI or no host matched
+    pub default: Option<Arc<A>>,
+    /// Lowercase host names, possibly starting with `*.`
+    hosts: HashMap<String, Arc<A>>,
+}
+
+impl<A : TlsAcceptor> Clone for ServerSniAcceptors<A> {
+    fn clone(&self) -> Self {
+        ServerSniAcceptors {
+            default: self.default.clone(),
+            hosts: self.hosts.clone(),
+        }
+    }
+}
+
+impl<A : TlsAcceptor> ServerSniAcceptors<A> {
+    pub fn new() -> ServerSniAcceptors<A> {
+        ServerSniAcceptors {
+            default: None,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// Use acceptor for given host name.
+    ///
+    /// Host name like `*.example.com` matches any subdomain of `example.com`.
+    pub fn add_host(&mut self, host: &str, acceptor: Arc<A>) {
+        self.hosts.insert(host.to_ascii_lowercase(), acceptor);
+    }
+
+    /// Find acceptor for host name sent by client
+    pub fn select(&self, sni_host_name: Option<&str>) -> Option<&Arc<A>> {
+        if let Some(host) = sni_host_name {
+            let host = host.to_ascii_lowercase();
+            if let Some(acceptor) = self.hosts.get(&host) {
+                return Some(acceptor);
+            }
+            if let Some(dot) = host.find('.') {
+                if let Some(acceptor) = self.hosts.get(&format!("*{}", &host[dot..])) {
+                    return Some(acceptor);
+                }
+            }
+        }
+        self.default.as_ref()
+    }
+}
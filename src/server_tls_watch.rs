@@ -0,0 +1,100 @@
+//! Reload server TLS acceptor when certificate file changes
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use tls_api::TlsAcceptor;
+
+use result::Result;
+
+use server_tls::ServerTlsHandle;
+
+
+/// Modification time and size of the file
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return None,
+    };
+    match metadata.modified() {
+        Ok(modified) => Some((modified, metadata.len())),
+        Err(_) => None,
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Watch file (e. g. PKCS#12 archive) and replace acceptor of `ServerTlsHandle`
+/// when the file changes.
+///
+/// Watching stops when this object is dropped.
+pub struct ServerTlsFileWatcher {
+    _stop_tx: mpsc::Sender<()>,
+}
+
+impl ServerTlsFileWatcher {
+    /// Check file every `interval`, and when file is modified,
+    /// create acceptor with `load` from file content, e. g.
+    /// `|pkcs12| Ok(TlsAcceptorBuilder::from_pkcs12(pkcs12, "password")?.build()?)`.
+    ///
+    /// If `load` fails, previous acceptor is kept and load is retried on next check.
+    pub fn new<A, P, F>(handle: ServerTlsHandle<A>, path: P, interval: Duration, load: F)
+        -> io::Result<ServerTlsFileWatcher>
+        where
+            A : TlsAcceptor,
+            P : Into<PathBuf>,
+            F : Fn(&[u8]) -> Result<A> + Send + 'static,
+    {
+        let path = path.into();
+
+        let (stop_tx, stop_rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("httpbis-tls-watch".to_owned())
+            .spawn(move || {
+                let mut last_stamp = file_stamp(&path);
+                loop {
+                    match stop_rx.recv_timeout(interval) {
+                        Err(mpsc::RecvTimeoutError::Timeout) => {}
+                        // Watcher dropped
+                        _ => return,
+                    }
+
+                    let stamp = file_stamp(&path);
+                    if stamp.is_none() || stamp == last_stamp {
+                        continue;
+                    }
+
+                    let acceptor = read_file(&path)
+                        .map_err(From::from)
+                        .and_then(|content| load(&content));
+                    match acceptor {
+                        Ok(acceptor) => {
+                            info!("reloaded TLS acceptor from {}", path.display());
+                            handle.set_acceptor(Arc::new(acceptor));
+                            last_stamp = stamp;
+                        }
+                        Err(e) => {
+                            warn!("failed to reload TLS acceptor from {}: {:?}", path.display(), e);
+                        }
+                    }
+                }
+            })?;
+
+        Ok(ServerTlsFileWatcher {
+            _stop_tx: stop_tx,
+        })
+    }
+}
@@ -1,11 +1,64 @@
+use std::sync::Arc;
+
+use solicit::StreamId;
 use solicit::header::Headers;
+use socket::AnySocketAddr;
+use router::RouteParams;
 use stream_part::HttpPartStream;
 use resp::Response;
 
 
+/// Information about server connection
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Connection id, unique within the server
+    pub conn_id: u64,
+    /// Address of the client
+    pub peer_addr: AnySocketAddr,
+    /// Address server listens on
+    pub local_addr: AnySocketAddr,
+    /// Connection is TLS
+    pub tls: bool,
+    /// Host name client sent with TLS SNI extension
+    pub sni_host_name: Option<String>,
+    /// Protocol negotiated with TLS ALPN extension
+    pub alpn_protocol: Option<Vec<u8>>,
+}
+
+/// Information about request passed to `Service`
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub conn: Arc<ConnectionInfo>,
+    pub stream_id: StreamId,
+    /// Path params captured by `Router`
+    pub params: RouteParams,
+}
+
+impl RequestContext {
+    /// Address of the client
+    pub fn peer_addr(&self) -> &AnySocketAddr {
+        &self.conn.peer_addr
+    }
+
+    /// Host name client sent with TLS SNI extension
+    pub fn sni_host_name(&self) -> Option<&str> {
+        self.conn.sni_host_name.as_ref().map(|h| &h[..])
+    }
+}
+
+
 /// HTTP/2 service interface
 ///
 /// Implemented by `Client` and it is callback provided by user.
 pub trait Service : Send + Sync + 'static {
     fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response;
+
+    /// Server calls this function instead of `start_request`.
+    ///
+    /// Default implementation ignores the context.
+    fn start_request_with_context(&self, _context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        self.start_request(headers, req)
+    }
 }
@@ -0,0 +1,234 @@
+//! Middleware around `Service`
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::Async;
+use futures::Poll;
+use futures::future::Future;
+use futures::stream::Stream;
+
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use resp::Response;
+use service::Service;
+use service::RequestContext;
+
+pub use compression::CompressionLayer;
+
+
+/// Middleware which can inspect or rewrite request and response,
+/// or return response without calling the service.
+pub trait Layer : Send + Sync + 'static {
+    fn start_request(&self, headers: Headers, req: HttpPartStream, next: Next) -> Response;
+}
+
+/// Remaining layers and the service
+pub struct Next {
+    layers: Arc<Vec<Arc<Layer>>>,
+    pos: usize,
+    service: Arc<Service>,
+    context: Option<RequestContext>,
+}
+
+impl Next {
+    /// Context of the request, `None` if service was called without context
+    pub fn context(&self) -> Option<&RequestContext> {
+        self.context.as_ref()
+    }
+
+    /// Call next layer or the service
+    pub fn start_request(self, headers: Headers, req: HttpPartStream) -> Response {
+        if self.pos < self.layers.len() {
+            let layer = self.layers[self.pos].clone();
+            let next = Next {
+                pos: self.pos + 1,
+                ..self
+            };
+            layer.start_request(headers, req, next)
+        } else {
+            match self.context {
+                Some(context) => self.service.start_request_with_context(context, headers, req),
+                None => self.service.start_request(headers, req),
+            }
+        }
+    }
+}
+
+/// Service wrapped with layers
+pub struct Layered {
+    layers: Arc<Vec<Arc<Layer>>>,
+    service: Arc<Service>,
+}
+
+impl Layered {
+    /// First layer is outermost, i. e. it is called first
+    pub fn new(layers: Vec<Arc<Layer>>, service: Arc<Service>) -> Layered {
+        Layered {
+            layers: Arc::new(layers),
+            service: service,
+        }
+    }
+
+    fn next(&self, context: Option<RequestContext>) -> Next {
+        Next {
+            layers: self.layers.clone(),
+            pos: 0,
+            service: self.service.clone(),
+            context: context,
+        }
+    }
+}
+
+impl Service for Layered {
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        self.next(None).start_request(headers, req)
+    }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        self.next(Some(context)).start_request(headers, req)
+    }
+}
+
+
+/// Call a function when stream ends, fails or is dropped
+struct OnEnd<S, F : FnOnce()> {
+    stream: S,
+    on_end: Option<F>,
+}
+
+impl<S : Stream, F : FnOnce()> Stream for OnEnd<S, F> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        let r = self.stream.poll();
+        match r {
+            Ok(Async::Ready(None)) | Err(_) => {
+                if let Some(on_end) = self.on_end.take() {
+                    on_end();
+                }
+            }
+            _ => {}
+        }
+        r
+    }
+}
+
+impl<S, F : FnOnce()> Drop for OnEnd<S, F> {
+    fn drop(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end();
+        }
+    }
+}
+
+
+/// Log requests and response statuses with `info!`
+pub struct LogLayer;
+
+impl Layer for LogLayer {
+    fn start_request(&self, headers: Headers, req: HttpPartStream, next: Next) -> Response {
+        let request = format!("{} {}",
+            headers.get_opt(":method").unwrap_or("-"),
+            headers.get_opt(":path").unwrap_or("-"));
+        info!("request {}", request);
+
+        let response = next.start_request(headers, req);
+        Response::new(response.0.then(move |r| {
+            match r {
+                Ok((headers, body)) => {
+                    info!("response {} {}", request, headers.get_opt(":status").unwrap_or("-"));
+                    Ok((headers, body))
+                }
+                Err(e) => {
+                    warn!("response {} failed: {:?}", request, e);
+                    Err(e)
+                }
+            }
+        }))
+    }
+}
+
+
+/// Measure time from request start to the end of response body
+pub struct TimingLayer<F> {
+    callback: Arc<F>,
+}
+
+impl<F> TimingLayer<F>
+    where F : Fn(&Headers, Duration) + Send + Sync + 'static
+{
+    /// Callback is called with request headers and duration
+    pub fn new(callback: F) -> TimingLayer<F> {
+        TimingLayer {
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl<F> Layer for TimingLayer<F>
+    where F : Fn(&Headers, Duration) + Send + Sync + 'static
+{
+    fn start_request(&self, headers: Headers, req: HttpPartStream, next: Next) -> Response {
+        let start = Instant::now();
+        let request_headers = headers.clone();
+        let callback = self.callback.clone();
+
+        let response = next.start_request(headers, req);
+        Response::new(response.0.then(move |r| {
+            let on_end = move || callback(&request_headers, start.elapsed());
+            match r {
+                Ok((headers, body)) => {
+                    let body: OnEnd<HttpPartStream, _> = OnEnd {
+                        stream: body,
+                        on_end: Some(on_end),
+                    };
+                    Ok((headers, HttpPartStream::new(body)))
+                }
+                Err(e) => {
+                    on_end();
+                    Err(e)
+                }
+            }
+        }))
+    }
+}
+
+
+/// Add headers to requests and responses
+#[derive(Default)]
+pub struct HeadersLayer {
+    pub request_headers: Headers,
+    pub response_headers: Headers,
+}
+
+impl HeadersLayer {
+    pub fn new() -> HeadersLayer {
+        Default::default()
+    }
+
+    pub fn add_request_header(&mut self, name: &str, value: &str) {
+        self.request_headers.add(name, value);
+    }
+
+    pub fn add_response_header(&mut self, name: &str, value: &str) {
+        self.response_headers.add(name, value);
+    }
+}
+
+impl Layer for HeadersLayer {
+    fn start_request(&self, mut headers: Headers, req: HttpPartStream, next: Next) -> Response {
+        headers.extend(self.request_headers.clone());
+
+        let response_headers = self.response_headers.clone();
+        let response = next.start_request(headers, req);
+        Response::new(response.0.map(move |(mut headers, body)| {
+            headers.extend(response_headers);
+            (headers, body)
+        }))
+    }
+}
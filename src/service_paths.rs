@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::collections::hash_map;
 
 use service::Service;
+use service::RequestContext;
 use solicit::header::Headers;
 use stream_part::HttpPartStream;
 use resp::Response;
@@ -129,6 +130,11 @@ impl ServicePaths {
     }
 
     fn find_service(&self, path: &str) -> Option<&Service> {
+        // Query string is not a part of the path
+        let path = match path.find('?') {
+            Some(q) => &path[..q],
+            None => path,
+        };
         self.root.find_service(path)
     }
 }
@@ -141,4 +147,35 @@ impl Service for ServicePaths {
             Response::not_found_404()
         }
     }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        if let Some(service) = self.find_service(headers.path()) {
+            service.start_request_with_context(context, headers, req)
+        } else {
+            Response::not_found_404()
+        }
+    }
+}
+
+#[test]
+fn test_query_string() {
+    use futures::future::Future;
+
+    let mut paths = ServicePaths::new();
+    paths.set_service_fn("/a", |_, _| Response::headers(Headers::from_status(201)));
+    paths.set_service_fn("/a/b", |_, _| Response::headers(Headers::from_status(202)));
+
+    let status = |path: &str| {
+        let mut headers = Headers::new();
+        headers.add(":method", "GET");
+        headers.add(":path", path);
+        paths.start_request(headers, HttpPartStream::empty()).collect().wait().unwrap().headers.status()
+    };
+
+    assert_eq!(201, status("/a?b"));
+    assert_eq!(201, status("/a?x=/b"));
+    assert_eq!(202, status("/a/b?c=d"));
+    assert_eq!(404, status("/?a"));
 }
@@ -0,0 +1,325 @@
+//! Reverse proxy `Service` forwarding requests to upstream `Client`s
+
+use std::any::Any;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use futures::Poll;
+use futures::future::Future;
+use futures::stream::Stream;
+
+use client::Client;
+use client_conn::ClientRequestOptions;
+use error::Error;
+use service::Service;
+use service::RequestContext;
+use socket::AnySocketAddr;
+use solicit::header::Header;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use stream_part::HttpStreamPart;
+use stream_part::HttpStreamPartContent;
+use resp::Response;
+
+
+/// Connection-specific headers which must not be forwarded,
+/// in addition to headers listed in `connection` header
+const HOP_BY_HOP_HEADERS: &'static [&'static str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Remove hop-by-hop headers.
+///
+/// `te` is kept only with `trailers` value, which is the only value allowed in HTTP/2.
+pub fn strip_hop_by_hop_headers(headers: &mut Headers) {
+    let mut remove: Vec<String> = HOP_BY_HOP_HEADERS.iter().map(|h| (*h).to_owned()).collect();
+    for header in &headers.0 {
+        if header.name() == b"connection" {
+            let value = String::from_utf8_lossy(header.value()).into_owned();
+            remove.extend(value.split(',').map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()));
+        }
+    }
+
+    headers.0.retain(|h| {
+        if h.name() == b"te" {
+            return h.value() == b"trailers";
+        }
+        !remove.iter().any(|r| h.name() == r.as_bytes())
+    });
+}
+
+/// Remove all headers with given name, return their values joined with comma
+fn take_header_values(headers: &mut Headers, name: &str) -> Option<String> {
+    let values: Vec<String> = headers.0.iter()
+        .filter(|h| h.name() == name.as_bytes())
+        .map(|h| String::from_utf8_lossy(h.value()).into_owned())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    headers.0.retain(|h| h.name() != name.as_bytes());
+    Some(values.join(", "))
+}
+
+fn set_header(headers: &mut Headers, name: &str, value: String) {
+    match headers.0.iter().position(|h| h.name() == name.as_bytes()) {
+        Some(pos) => headers.0[pos] = Header::new(name, value),
+        // pseudo-headers must precede regular headers
+        None if name.starts_with(':') => headers.0.insert(0, Header::new(name, value)),
+        None => headers.0.push(Header::new(name, value)),
+    }
+}
+
+/// Append value to a comma-separated list header
+fn append_header(headers: &mut Headers, name: &str, value: &str) {
+    let value = match take_header_values(headers, name) {
+        Some(prev) => format!("{}, {}", prev, value),
+        None => value.to_owned(),
+    };
+    headers.0.push(Header::new(name, value));
+}
+
+/// Node of `forwarded` header (RFC 7239), IPv6 addresses are quoted
+fn forwarded_node(addr: &AnySocketAddr) -> String {
+    match *addr {
+        AnySocketAddr::Inet(ref addr) => match addr.ip() {
+            IpAddr::V4(ip) => format!("{}", ip),
+            IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+        },
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn is_timeout(e: &Error) -> bool {
+    match *e {
+        Error::RequestTimeout | Error::ConnectionTimeout => true,
+        _ => false,
+    }
+}
+
+/// Strip hop-by-hop headers from trailers
+fn strip_hop_by_hop_part(mut part: HttpStreamPart) -> HttpStreamPart {
+    if let HttpStreamPartContent::Headers(ref mut headers) = part.content {
+        strip_hop_by_hop_headers(headers);
+    }
+    part
+}
+
+/// Parts of upstream response dropped by downstream
+type Parked = Arc<Mutex<Vec<Box<Any + Send>>>>;
+
+/// Upstream response future or body.
+///
+/// Dropping upstream response resets upstream stream with `CANCEL`,
+/// so when downstream drops it, e. g. because downstream stream is reset,
+/// it is kept until forwarded request body is dropped.
+/// That way reset code received in request body is sent upstream first.
+struct KeepUntilBodyDropped<T : Send + 'static> {
+    inner: Option<T>,
+    parked: Parked,
+}
+
+impl<T : Send + 'static> KeepUntilBodyDropped<T> {
+    fn new(inner: T, parked: Parked) -> KeepUntilBodyDropped<T> {
+        KeepUntilBodyDropped {
+            inner: Some(inner),
+            parked: parked,
+        }
+    }
+}
+
+impl<F : Future + Send + 'static> Future for KeepUntilBodyDropped<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        self.inner.as_mut().expect("polled after drop").poll()
+    }
+}
+
+impl<S : Stream + Send + 'static> Stream for KeepUntilBodyDropped<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        self.inner.as_mut().expect("polled after drop").poll()
+    }
+}
+
+impl<T : Send + 'static> Drop for KeepUntilBodyDropped<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            // dropped immediately if request body is already dropped
+            self.parked.lock().unwrap().push(Box::new(inner));
+        }
+    }
+}
+
+/// Request body forwarded to upstream, holds dropped parts of upstream response
+struct ForwardedBody {
+    body: HttpPartStream,
+    _parked: Parked,
+}
+
+impl Stream for ForwardedBody {
+    type Item = HttpStreamPart;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<HttpStreamPart>, Error> {
+        self.body.poll()
+    }
+}
+
+struct Upstream {
+    client: Arc<Client>,
+    authority: String,
+}
+
+/// Reverse proxy: forwards requests to upstream clients in round-robin order.
+///
+/// Hop-by-hop headers are removed from requests, responses and trailers,
+/// `:authority` is replaced with authority of upstream, and client address
+/// is appended to `x-forwarded-for` and `forwarded` headers.
+///
+/// `RST_STREAM` is propagated in both directions: reset of upstream stream
+/// resets downstream stream with the same error code, reset of downstream
+/// stream resets upstream stream with the same code while request body
+/// is forwarded, or with `CANCEL` after that. If upstream fails before response headers,
+/// proxy responds with 504 on timeout and with 502 on other errors.
+pub struct ProxyService {
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+    /// Upstream request deadline, whole response must be received by that time
+    pub timeout: Option<Duration>,
+    /// Forward `:authority` of downstream request instead of authority of upstream
+    pub preserve_authority: bool,
+}
+
+impl ProxyService {
+    pub fn new() -> ProxyService {
+        ProxyService {
+            upstreams: Vec::new(),
+            next: AtomicUsize::new(0),
+            timeout: None,
+            preserve_authority: false,
+        }
+    }
+
+    /// Add upstream, `authority` is sent as `:authority` of forwarded requests
+    pub fn add_upstream(&mut self, client: Arc<Client>, authority: &str) {
+        self.upstreams.push(Upstream {
+            client: client,
+            authority: authority.to_owned(),
+        });
+    }
+
+    fn next_upstream(&self) -> Option<&Upstream> {
+        if self.upstreams.is_empty() {
+            return None;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.upstreams.len();
+        Some(&self.upstreams[i])
+    }
+
+    fn rewrite_request_headers(&self, context: Option<&RequestContext>, upstream: &Upstream, headers: &mut Headers) {
+        strip_hop_by_hop_headers(headers);
+
+        let authority = headers.get_opt(":authority").map(|a| a.to_owned());
+
+        if !self.preserve_authority {
+            set_header(headers, ":authority", upstream.authority.clone());
+            if let Some(ref authority) = authority {
+                if headers.get_opt("x-forwarded-host").is_none() {
+                    headers.add("x-forwarded-host", authority);
+                }
+            }
+        }
+
+        let context = match context {
+            Some(context) => context,
+            None => return,
+        };
+
+        if let AnySocketAddr::Inet(ref addr) = *context.peer_addr() {
+            append_header(headers, "x-forwarded-for", &format!("{}", addr.ip()));
+        }
+
+        let proto = if context.conn.tls { "https" } else { "http" };
+        if headers.get_opt("x-forwarded-proto").is_none() {
+            headers.add("x-forwarded-proto", proto);
+        }
+
+        let mut forwarded = format!("for={};proto={}", forwarded_node(context.peer_addr()), proto);
+        if let Some(ref authority) = authority {
+            forwarded.push_str(&format!(";host=\"{}\"", authority));
+        }
+        append_header(headers, "forwarded", &forwarded);
+    }
+
+    fn forward(&self, context: Option<RequestContext>, mut headers: Headers, req: HttpPartStream) -> Response {
+        let upstream = match self.next_upstream() {
+            Some(upstream) => upstream,
+            None => {
+                warn!("no upstreams configured");
+                return Response::headers(Headers::from_status(502));
+            }
+        };
+
+        self.rewrite_request_headers(context.as_ref(), upstream, &mut headers);
+
+        let parked = Parked::default();
+
+        let req = HttpPartStream::new(ForwardedBody {
+            body: HttpPartStream::new(req.map(strip_hop_by_hop_part)),
+            _parked: parked.clone(),
+        });
+
+        let options = match self.timeout {
+            Some(timeout) => ClientRequestOptions::with_timeout(timeout),
+            None => ClientRequestOptions::new(),
+        };
+
+        let response = upstream.client.start_request_with_options(headers, req, options);
+        let response = KeepUntilBodyDropped::new(response.0, parked.clone());
+
+        Response::new(response.then(move |r| {
+            match r {
+                Ok((mut headers, body)) => {
+                    strip_hop_by_hop_headers(&mut headers);
+                    let body = KeepUntilBodyDropped::new(body.map(strip_hop_by_hop_part), parked);
+                    Ok((headers, HttpPartStream::new(body)))
+                }
+                // Propagate RST_STREAM
+                Err(Error::CodeError(error_code)) => Err(Error::CodeError(error_code)),
+                Err(ref e) if is_timeout(e) => {
+                    warn!("upstream timed out: {:?}", e);
+                    Ok((Headers::from_status(504), HttpPartStream::empty()))
+                }
+                Err(e) => {
+                    warn!("upstream failed: {:?}", e);
+                    Ok((Headers::from_status(502), HttpPartStream::empty()))
+                }
+            }
+        }))
+    }
+}
+
+impl Service for ProxyService {
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        self.forward(None, headers, req)
+    }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        self.forward(Some(context), headers, req)
+    }
+}
@@ -0,0 +1,553 @@
+//! Static file serving `Service`
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use futures::Async;
+use futures::Poll;
+use futures::stream::Stream;
+
+use bytes::Bytes;
+
+use error::Error;
+use http_date::format_http_date;
+use http_date::parse_http_date;
+use service::Service;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use resp::Response;
+
+
+/// Default size of DATA chunks read from file
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Requests with more ranges are served with whole file
+const MAX_RANGES: usize = 16;
+
+const DEFAULT_MIME_TYPE: &'static str = "application/octet-stream";
+
+/// Content type by lowercase file extension
+const MIME_TYPES: &'static [(&'static str, &'static str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "application/javascript; charset=utf-8"),
+    ("mjs", "application/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("xml", "application/xml"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+];
+
+fn mime_type(path: &Path) -> &'static str {
+    let ext = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.to_lowercase(),
+        None => return DEFAULT_MIME_TYPE,
+    };
+    MIME_TYPES.iter()
+        .find(|&&(e, _)| e == ext)
+        .map(|&(_, mime)| mime)
+        .unwrap_or(DEFAULT_MIME_TYPE)
+}
+
+/// Decode `%XX` escapes, `None` if escape is invalid or result is not UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut r = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = match s.get(i + 1..i + 3) {
+                Some(hex) => hex,
+                None => return None,
+            };
+            match u8::from_str_radix(hex, 16) {
+                Ok(b) => r.push(b),
+                Err(_) => return None,
+            }
+            i += 3;
+        } else {
+            r.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(r).ok()
+}
+
+/// File path relative to root from URL path,
+/// `None` if path is malformed or points outside of root
+fn relative_path(url_path: &str) -> Option<PathBuf> {
+    let decoded = match percent_decode(url_path) {
+        Some(decoded) => decoded,
+        None => return None,
+    };
+
+    let mut r = PathBuf::new();
+    for segment in decoded.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        // Rejects `..`, and drive prefixes and backslashes on Windows
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !segment.contains('\0') => r.push(segment),
+            _ => return None,
+        }
+    }
+    Some(r)
+}
+
+/// Result of parsing `range` header
+#[derive(Debug, PartialEq, Eq)]
+enum Ranges {
+    /// Header is malformed, whole file is served
+    Full,
+    /// Inclusive byte ranges within the file
+    Partial(Vec<(u64, u64)>),
+    /// No range overlaps the file
+    Unsatisfiable,
+}
+
+fn parse_range(range: &str, len: u64) -> Ranges {
+    let range = range.trim();
+    if !range.starts_with("bytes=") {
+        return Ranges::Full;
+    }
+
+    let specs: Vec<&str> = range["bytes=".len()..].split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return Ranges::Full;
+    }
+
+    let mut r = Vec::new();
+    for spec in specs {
+        let dash = match spec.find('-') {
+            Some(dash) => dash,
+            None => return Ranges::Full,
+        };
+        let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+
+        if first.is_empty() {
+            // suffix range: last N bytes
+            let suffix: u64 = match last.parse() {
+                Ok(suffix) => suffix,
+                Err(_) => return Ranges::Full,
+            };
+            if suffix != 0 && len != 0 {
+                r.push((len.saturating_sub(suffix), len - 1));
+            }
+            continue;
+        }
+
+        let first: u64 = match first.parse() {
+            Ok(first) => first,
+            Err(_) => return Ranges::Full,
+        };
+        let last: u64 = if last.is_empty() {
+            u64::max_value()
+        } else {
+            match last.parse() {
+                Ok(last) => last,
+                Err(_) => return Ranges::Full,
+            }
+        };
+        if last < first {
+            return Ranges::Full;
+        }
+        if first < len {
+            r.push((first, cmp::min(last, len - 1)));
+        }
+    }
+
+    if r.is_empty() {
+        Ranges::Unsatisfiable
+    } else {
+        Ranges::Partial(r)
+    }
+}
+
+fn etag(len: u64, modified: Option<SystemTime>) -> String {
+    let modified = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", modified, len)
+}
+
+/// Check `if-none-match` or, if it is absent, `if-modified-since`
+fn not_modified(headers: &Headers, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get_opt("if-none-match") {
+        // weak comparison
+        return if_none_match.split(',')
+            .map(|t| t.trim())
+            .any(|t| t == "*" || t.trim_left_matches("W/") == etag);
+    }
+
+    let since = headers.get_opt("if-modified-since").and_then(parse_http_date);
+    match (since, modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok())) {
+        // HTTP dates have one second precision
+        (Some(since), Some(modified)) => UNIX_EPOCH + Duration::from_secs(modified.as_secs()) <= since,
+        _ => false,
+    }
+}
+
+/// `if-range` is absent or matches current file
+fn if_range_matches(headers: &Headers, etag: &str, last_modified: Option<&str>) -> bool {
+    match headers.get_opt("if-range") {
+        Some(if_range) => if_range == etag || Some(if_range) == last_modified,
+        None => true,
+    }
+}
+
+fn multipart_boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    format!("httpbis-{:08x}{:08x}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn io_error_response(e: io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::NotFound => Response::not_found_404(),
+        io::ErrorKind::PermissionDenied => Response::headers(Headers::from_status(403)),
+        _ => {
+            warn!("failed to open file: {:?}", e);
+            Response::headers(Headers::internal_error_500())
+        }
+    }
+}
+
+
+enum Segment {
+    Bytes(Bytes),
+    /// Part of file from offset
+    File(u64, u64),
+}
+
+/// Response body, file is read chunk by chunk when stream is polled,
+/// and the stream is polled only when stream window is open
+struct FileBody {
+    file: File,
+    segments: VecDeque<Segment>,
+    chunk_size: usize,
+}
+
+impl Stream for FileBody {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        loop {
+            let (offset, len) = match self.segments.pop_front() {
+                None => return Ok(Async::Ready(None)),
+                Some(Segment::Bytes(bytes)) => return Ok(Async::Ready(Some(bytes))),
+                Some(Segment::File(_, 0)) => continue,
+                Some(Segment::File(offset, len)) => (offset, len),
+            };
+
+            let n = cmp::min(len, self.chunk_size as u64);
+            let mut buf = vec![0; n as usize];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut buf)?;
+
+            if n < len {
+                self.segments.push_front(Segment::File(offset + n, len - n));
+            }
+            return Ok(Async::Ready(Some(Bytes::from(buf))));
+        }
+    }
+}
+
+
+/// Serve files from a directory.
+///
+/// Supports `GET` and `HEAD`, single and multiple `range` requests,
+/// `if-none-match`, `if-modified-since` and `if-range`.
+/// Paths with `..` segments are rejected, and files which real path
+/// (after resolving symlinks) is outside of root are not served.
+///
+/// Files are read in the thread polling the response stream,
+/// so with slow disks server should be configured with `CpuPoolOption::CpuPool`.
+pub struct StaticFiles {
+    root: PathBuf,
+    /// URL path prefix removed before mapping path to file,
+    /// e. g. `/static` if service is registered at `/static`
+    pub prefix: String,
+    /// File served for directory paths
+    pub index_file: Option<String>,
+    /// Maximum size of DATA frame payload read from file
+    pub chunk_size: usize,
+    /// Value of `cache-control` header of responses
+    pub cache_control: Option<String>,
+}
+
+impl StaticFiles {
+    pub fn new<P : AsRef<Path>>(root: P) -> StaticFiles {
+        StaticFiles {
+            root: root.as_ref().to_owned(),
+            prefix: String::new(),
+            index_file: Some("index.html".to_owned()),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cache_control: None,
+        }
+    }
+
+    /// URL path without prefix, `None` if path does not start with prefix
+    fn strip_prefix<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if !path.starts_with(&self.prefix[..]) {
+            return None;
+        }
+        let rem = &path[self.prefix.len()..];
+        if rem.is_empty() || rem.starts_with('/') || self.prefix.ends_with('/') {
+            Some(rem)
+        } else {
+            None
+        }
+    }
+
+    /// Open file by URL path, returns response instead of file for errors and redirects.
+    ///
+    /// Returned path is not canonicalized, so MIME type is detected by name of symlink.
+    fn open(&self, url_path: &str) -> Result<(File, PathBuf), Response> {
+        let relative = match self.strip_prefix(url_path) {
+            Some(rem) => match relative_path(rem) {
+                Some(relative) => relative,
+                None => return Err(Response::headers(Headers::from_status(400))),
+            },
+            None => return Err(Response::not_found_404()),
+        };
+
+        let root = fs::canonicalize(&self.root).map_err(io_error_response)?;
+        let mut path = root.join(relative);
+
+        if path.is_dir() {
+            if !url_path.ends_with('/') {
+                return Err(Response::redirect_302(&format!("{}/", url_path)));
+            }
+            match self.index_file {
+                Some(ref index_file) => path.push(index_file),
+                None => return Err(Response::not_found_404()),
+            }
+        }
+
+        // Symlinks must not lead outside of root
+        let real_path = fs::canonicalize(&path).map_err(io_error_response)?;
+        if !real_path.starts_with(&root) {
+            return Err(Response::not_found_404());
+        }
+
+        let file = File::open(&path).map_err(io_error_response)?;
+        Ok((file, path))
+    }
+
+    fn serve(&self, headers: &Headers, head: bool) -> Response {
+        let url_path = headers.get_opt(":path").unwrap_or("/");
+        let url_path = url_path.split(|c: char| c == '?' || c == '#').next().unwrap();
+
+        let (file, path) = match self.open(url_path) {
+            Ok(r) => r,
+            Err(response) => return response,
+        };
+
+        let metadata = match file.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => return io_error_response(e),
+        };
+        if !metadata.is_file() {
+            return Response::not_found_404();
+        }
+
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let etag = etag(len, modified);
+        let last_modified = modified.map(format_http_date);
+        let content_type = mime_type(&path);
+
+        let mut validators = Headers::new();
+        validators.add("etag", &etag);
+        if let Some(ref last_modified) = last_modified {
+            validators.add("last-modified", last_modified);
+        }
+        if let Some(ref cache_control) = self.cache_control {
+            validators.add("cache-control", cache_control);
+        }
+
+        if not_modified(headers, &etag, modified) {
+            let mut resp_headers = Headers::from_status(304);
+            resp_headers.extend(validators);
+            return Response::headers(resp_headers);
+        }
+
+        let ranges = match headers.get_opt("range") {
+            Some(range) if if_range_matches(headers, &etag, last_modified.as_ref().map(|s| &s[..])) => {
+                parse_range(range, len)
+            }
+            _ => Ranges::Full,
+        };
+
+        let mut segments = VecDeque::new();
+        let (mut resp_headers, content_length) = match ranges {
+            Ranges::Full => {
+                segments.push_back(Segment::File(0, len));
+                let mut resp_headers = Headers::ok_200();
+                resp_headers.add("content-type", content_type);
+                (resp_headers, len)
+            }
+            Ranges::Unsatisfiable => {
+                let mut resp_headers = Headers::from_status(416);
+                resp_headers.add("content-range", &format!("bytes */{}", len));
+                return Response::headers(resp_headers);
+            }
+            Ranges::Partial(ref ranges) if ranges.len() == 1 => {
+                let (first, last) = ranges[0];
+                segments.push_back(Segment::File(first, last - first + 1));
+                let mut resp_headers = Headers::from_status(206);
+                resp_headers.add("content-type", content_type);
+                resp_headers.add("content-range", &format!("bytes {}-{}/{}", first, last, len));
+                (resp_headers, last - first + 1)
+            }
+            Ranges::Partial(ranges) => {
+                let boundary = multipart_boundary();
+                let mut content_length = 0;
+                for (first, last) in ranges {
+                    let part_headers = format!(
+                        "\r\n--{}\r\ncontent-type: {}\r\ncontent-range: bytes {}-{}/{}\r\n\r\n",
+                        boundary, content_type, first, last, len);
+                    content_length += part_headers.len() as u64 + last - first + 1;
+                    segments.push_back(Segment::Bytes(Bytes::from(part_headers)));
+                    segments.push_back(Segment::File(first, last - first + 1));
+                }
+                let end = format!("\r\n--{}--\r\n", boundary);
+                content_length += end.len() as u64;
+                segments.push_back(Segment::Bytes(Bytes::from(end)));
+
+                let mut resp_headers = Headers::from_status(206);
+                resp_headers.add("content-type", &format!("multipart/byteranges; boundary={}", boundary));
+                (resp_headers, content_length)
+            }
+        };
+
+        resp_headers.add("content-length", &format!("{}", content_length));
+        resp_headers.add("accept-ranges", "bytes");
+        resp_headers.extend(validators);
+
+        if head {
+            return Response::headers(resp_headers);
+        }
+
+        Response::headers_and_bytes_stream(resp_headers, FileBody {
+            file: file,
+            segments: segments,
+            chunk_size: cmp::max(self.chunk_size, 1),
+        })
+    }
+}
+
+impl Service for StaticFiles {
+    fn start_request(&self, headers: Headers, _req: HttpPartStream) -> Response {
+        match headers.get_opt(":method") {
+            Some("GET") => self.serve(&headers, false),
+            Some("HEAD") => self.serve(&headers, true),
+            _ => {
+                let mut resp_headers = Headers::from_status(405);
+                resp_headers.add("allow", "GET, HEAD");
+                Response::headers(resp_headers)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(Some(PathBuf::from("a/b.txt")), relative_path("/a//./b.txt"));
+        assert_eq!(Some(PathBuf::from("a b")), relative_path("/a%20b"));
+        assert_eq!(Some(PathBuf::new()), relative_path("/"));
+        assert_eq!(None, relative_path("/a/../../etc/passwd"));
+        assert_eq!(None, relative_path("/%2e%2e/etc/passwd"));
+        assert_eq!(None, relative_path("/a%2f..%2f..%2fb"));
+        assert_eq!(None, relative_path("/a%00"));
+        assert_eq!(None, relative_path("/a%2"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(Ranges::Partial(vec![(0, 4)]), parse_range("bytes=0-4", 10));
+        assert_eq!(Ranges::Partial(vec![(5, 9)]), parse_range("bytes=5-", 10));
+        assert_eq!(Ranges::Partial(vec![(7, 9)]), parse_range("bytes=-3", 10));
+        assert_eq!(Ranges::Partial(vec![(0, 9)]), parse_range("bytes=-30", 10));
+        assert_eq!(Ranges::Partial(vec![(8, 9)]), parse_range("bytes=8-100", 10));
+        assert_eq!(Ranges::Partial(vec![(0, 0), (9, 9)]), parse_range("bytes=0-0, -1", 10));
+        assert_eq!(Ranges::Partial(vec![(1, 2)]), parse_range("bytes=1-2,20-30", 10));
+        assert_eq!(Ranges::Unsatisfiable, parse_range("bytes=10-", 10));
+        assert_eq!(Ranges::Unsatisfiable, parse_range("bytes=-0", 10));
+        assert_eq!(Ranges::Full, parse_range("bytes=5-4", 10));
+        assert_eq!(Ranges::Full, parse_range("bytes=a-", 10));
+        assert_eq!(Ranges::Full, parse_range("items=0-4", 10));
+        assert_eq!(Ranges::Full, parse_range("bytes=", 10));
+    }
+
+    #[test]
+    fn conditional() {
+        let modified = UNIX_EPOCH + Duration::new(784111777, 500);
+        let etag = etag(10, Some(modified));
+
+        let mut headers = Headers::new();
+        headers.add("if-none-match", &format!("\"x\", W/{}", etag));
+        assert!(not_modified(&headers, &etag, Some(modified)));
+
+        let mut headers = Headers::new();
+        headers.add("if-modified-since", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(not_modified(&headers, &etag, Some(modified)));
+        assert!(!not_modified(&headers, &etag, Some(modified + Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn mime_types() {
+        assert_eq!("text/html; charset=utf-8", mime_type(Path::new("a/index.HTML")));
+        assert_eq!(DEFAULT_MIME_TYPE, mime_type(Path::new("a/b")));
+    }
+}
@@ -1,6 +1,5 @@
 use std::io;
 use std::net::SocketAddr;
-use std::any::Any;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
@@ -13,6 +12,7 @@ use futures::stream::Stream;
 use futures::Future;
 
 use server_conf::ServerConf;
+use socket_memory::MemoryAddr;
 
 
 pub trait ToSocketListener {
@@ -21,10 +21,12 @@ pub trait ToSocketListener {
     fn cleanup(&self);
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AnySocketAddr {
     Inet(SocketAddr),
-    Unix(String)
+    Unix(String),
+    /// In-process server, see `socket_memory`
+    Memory(MemoryAddr),
 }
 
 impl Display for AnySocketAddr {
@@ -32,6 +34,7 @@ impl Display for AnySocketAddr {
           match *self {
             AnySocketAddr::Inet(ref inet_addr) => Display::fmt(inet_addr, f),
             AnySocketAddr::Unix(ref unix_addr) => Display::fmt(unix_addr, f),
+            AnySocketAddr::Memory(ref memory_addr) => Display::fmt(memory_addr, f),
         }
     }
 }
@@ -41,7 +44,9 @@ impl AnySocketAddr {
          match self {
             &AnySocketAddr::Inet(ref inet_addr) => Ok(inet_addr.port()),
             &AnySocketAddr::Unix(_) =>
-                Err(io::Error::new(io::ErrorKind::Other, "Cannot get port from unix domain socket"))
+                Err(io::Error::new(io::ErrorKind::Other, "Cannot get port from unix domain socket")),
+            &AnySocketAddr::Memory(_) =>
+                Err(io::Error::new(io::ErrorKind::Other, "Cannot get port from in-memory addr")),
         }
     }
 }
@@ -55,6 +60,7 @@ impl ToSocketListener for AnySocketAddr {
             #[cfg(not(unix))]
             // TODO: error instead of panic
             &AnySocketAddr::Unix(..) => panic!("cannot use unix sockets on non-unix"),
+            &AnySocketAddr::Memory(ref memory_addr) => memory_addr.to_listener(conf),
         }
     }
 
@@ -65,6 +71,7 @@ impl ToSocketListener for AnySocketAddr {
             &AnySocketAddr::Unix(ref unix_addr) => unix_addr.cleanup(),
             #[cfg(not(unix))]
             &AnySocketAddr::Unix(..) => {},
+            &AnySocketAddr::Memory(ref memory_addr) => memory_addr.cleanup(),
         }
     }
 }
@@ -79,6 +86,7 @@ impl ToClientStream for AnySocketAddr {
             &AnySocketAddr::Unix(ref unix_addr) => unix_addr.connect(handle),
             #[cfg(not(unix))]
             &AnySocketAddr::Unix(..) => panic!("cannot use unix sockets on non-unix"),
+            &AnySocketAddr::Memory(ref memory_addr) => memory_addr.connect(handle),
         }
     }
 }
@@ -90,8 +98,9 @@ pub trait ToTokioListener {
 }
 
 pub trait ToServerStream {
+    /// Stream of accepted connections and their peer addresses
     fn incoming(self: Box<Self>)
-        -> Box<Stream<Item=(Box<StreamItem>, Box<Any>), Error=io::Error>>;
+        -> Box<Stream<Item=(Box<StreamItem>, AnySocketAddr), Error=io::Error>>;
 }
 
 pub trait ToClientStream:
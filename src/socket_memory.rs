@@ -0,0 +1,281 @@
+//! In-process transport: client and server in the same process
+//! talk over in-memory pipes without sockets.
+//!
+//! Server listening on `MemoryAddr` registers the name in a process-wide table,
+//! client connecting to the same `MemoryAddr` gets one end of a new pipe,
+//! and server accepts the other end.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tokio_core::reactor;
+use tokio_io::AsyncRead;
+use tokio_io::AsyncWrite;
+
+use futures::Async;
+use futures::Poll;
+use futures::future;
+use futures::stream::Stream;
+use futures::Future;
+use futures::sync::mpsc::unbounded;
+use futures::sync::mpsc::UnboundedSender;
+use futures::sync::mpsc::UnboundedReceiver;
+use futures::task;
+use futures::task::Task;
+
+use server_conf::ServerConf;
+
+use socket::AnySocketAddr;
+use socket::ToSocketListener;
+use socket::ToTokioListener;
+use socket::ToServerStream;
+use socket::ToClientStream;
+use socket::StreamItem;
+
+
+/// Name of in-process server, any string
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryAddr(pub String);
+
+impl fmt::Display for MemoryAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "memory:{}", self.0)
+    }
+}
+
+
+/// One direction of the duplex pipe
+#[derive(Default)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    /// Writer called `shutdown` or was dropped
+    write_closed: bool,
+    /// Reader was dropped
+    read_closed: bool,
+    /// Task blocked reading from empty pipe
+    reader: Option<Task>,
+}
+
+impl Pipe {
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        if let Some(task) = self.reader.take() {
+            task.notify();
+        }
+    }
+}
+
+/// End of in-memory duplex pipe.
+///
+/// Writes never block, reads block until data is written by another end.
+pub struct MemoryStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Create a connected pair of streams
+pub fn pipe() -> (MemoryStream, MemoryStream) {
+    let a = Arc::new(Mutex::new(Pipe::default()));
+    let b = Arc::new(Mutex::new(Pipe::default()));
+    let first = MemoryStream { read: a.clone(), write: b.clone() };
+    let second = MemoryStream { read: b, write: a };
+    (first, second)
+}
+
+impl fmt::Debug for MemoryStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryStream").finish()
+    }
+}
+
+impl io::Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.read.lock().unwrap();
+        if pipe.buf.is_empty() {
+            if pipe.write_closed || buf.is_empty() {
+                return Ok(0);
+            }
+            pipe.reader = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let len = cmp::min(buf.len(), pipe.buf.len());
+        for b in &mut buf[..len] {
+            *b = pipe.buf.pop_front().unwrap();
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.write.lock().unwrap();
+        if pipe.read_closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end of memory pipe is closed"));
+        }
+        if pipe.write_closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "memory pipe is shut down"));
+        }
+        pipe.buf.extend(buf.iter().cloned());
+        if let Some(task) = pipe.reader.take() {
+            task.notify();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for MemoryStream {}
+
+impl AsyncWrite for MemoryStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.write.lock().unwrap().close_write();
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.write.lock().unwrap().close_write();
+
+        let mut read = self.read.lock().unwrap();
+        read.read_closed = true;
+        read.buf.clear();
+    }
+}
+
+impl StreamItem for MemoryStream {
+    fn is_tcp(&self) -> bool {
+        false
+    }
+
+    fn set_nodelay(&self, _no_delay: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+lazy_static! {
+    /// Servers listening in this process
+    static ref LISTENERS: Mutex<HashMap<String, UnboundedSender<MemoryStream>>> =
+        Mutex::new(HashMap::new());
+}
+
+struct MemoryListener {
+    addr: MemoryAddr,
+    incoming: UnboundedReceiver<MemoryStream>,
+}
+
+impl ToSocketListener for MemoryAddr {
+    fn to_listener(&self, _conf: &ServerConf) -> Box<ToTokioListener + Send> {
+        let (tx, rx) = unbounded();
+
+        let mut listeners = LISTENERS.lock().unwrap();
+        if listeners.contains_key(&self.0) {
+            panic!("{} is already in use", self);
+        }
+        listeners.insert(self.0.clone(), tx);
+
+        Box::new(MemoryListener {
+            addr: self.clone(),
+            incoming: rx,
+        })
+    }
+
+    fn cleanup(&self) {
+        LISTENERS.lock().unwrap().remove(&self.0);
+    }
+}
+
+impl ToTokioListener for MemoryListener {
+    fn to_tokio_listener(self: Box<Self>, _handle: &reactor::Handle) -> Box<ToServerStream> {
+        self
+    }
+
+    fn local_addr(&self) -> io::Result<AnySocketAddr> {
+        Ok(AnySocketAddr::Memory(self.addr.clone()))
+    }
+}
+
+impl ToServerStream for MemoryListener {
+    fn incoming(self: Box<Self>)
+        -> Box<Stream<Item=(Box<StreamItem>, AnySocketAddr), Error=io::Error>>
+    {
+        let MemoryListener { incoming, .. } = *self;
+        let stream = incoming
+            .map(|stream| {
+                // Client end is unnamed
+                (Box::new(stream) as Box<StreamItem>, AnySocketAddr::Memory(MemoryAddr(String::new())))
+            })
+            .map_err(|()| io::Error::new(io::ErrorKind::Other, "memory listener"));
+        Box::new(stream)
+    }
+}
+
+impl ToClientStream for MemoryAddr {
+    fn connect(&self, _handle: &reactor::Handle)
+        -> Box<Future<Item=Box<StreamItem>, Error=io::Error> + Send>
+    {
+        let (client, server) = pipe();
+
+        let sent = match LISTENERS.lock().unwrap().get(&self.0) {
+            Some(tx) => tx.unbounded_send(server).is_ok(),
+            None => false,
+        };
+
+        if sent {
+            Box::new(future::ok(Box::new(client) as Box<StreamItem>))
+        } else {
+            let error = io::Error::new(
+                io::ErrorKind::ConnectionRefused, format!("nobody listens on {}", self));
+            Box::new(future::err(error))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Read;
+    use std::io::Write;
+
+    use futures::future::lazy;
+
+    #[test]
+    fn pipe_read_write() {
+        let (mut a, mut b) = pipe();
+        a.write_all(b"abc").unwrap();
+
+        let mut buf = [0; 2];
+        assert_eq!(2, b.read(&mut buf).unwrap());
+        assert_eq!(b"ab", &buf);
+        assert_eq!(1, b.read(&mut buf).unwrap());
+        assert_eq!(b'c', buf[0]);
+
+        // Reading empty pipe registers current task
+        let r = lazy(|| Ok::<_, ()>(b.read(&mut buf).unwrap_err().kind())).wait().unwrap();
+        assert_eq!(io::ErrorKind::WouldBlock, r);
+
+        drop(a);
+        assert_eq!(0, b.read(&mut buf).unwrap());
+        assert_eq!(io::ErrorKind::BrokenPipe, b.write(b"x").unwrap_err().kind());
+    }
+
+    #[test]
+    fn connect_without_listener() {
+        let core = reactor::Core::new().unwrap();
+        let addr = MemoryAddr("socket_memory::test::connect_without_listener".to_owned());
+        let error = addr.connect(&core.handle()).wait().err().unwrap();
+        assert_eq!(io::ErrorKind::ConnectionRefused, error.kind());
+    }
+}
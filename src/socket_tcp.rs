@@ -1,6 +1,5 @@
 use std::net::SocketAddr;
 use std::io;
-use std::any::Any;
 
 use tokio_core::reactor;
 use tokio_core::net::TcpListener;
@@ -80,10 +79,10 @@ impl ToTokioListener for ::std::net::TcpListener {
 
 impl ToServerStream for TcpListener {
     fn incoming(self: Box<Self>)
-        -> Box<Stream<Item=(Box<StreamItem>, Box<Any>), Error=io::Error>>
+        -> Box<Stream<Item=(Box<StreamItem>, AnySocketAddr), Error=io::Error>>
     {
         let stream = (*self).incoming().map(|(stream, addr)|
-            (Box::new(stream) as Box<StreamItem>, Box::new(addr) as Box<Any>)
+            (Box::new(stream) as Box<StreamItem>, AnySocketAddr::Inet(addr))
         );
         Box::new(stream)
     }
@@ -1,5 +1,4 @@
 use std::io;
-use std::any::Any;
 use std::fs;
 use std::path::Path;
 
@@ -50,11 +49,16 @@ impl ToTokioListener for ::std::os::unix::net::UnixListener {
 
 impl ToServerStream for UnixListener {
     fn incoming(self: Box<Self>)
-        -> Box<Stream<Item=(Box<StreamItem>, Box<Any>), Error=io::Error>>
+        -> Box<Stream<Item=(Box<StreamItem>, AnySocketAddr), Error=io::Error>>
     {
-        let stream = (*self).incoming().map(|(stream, addr)|
-            (Box::new(stream) as Box<StreamItem>, Box::new(addr) as Box<Any>)
-        );
+        let stream = (*self).incoming().map(|(stream, addr)| {
+            // Client sockets are usually unnamed
+            let addr = addr.as_pathname()
+                .and_then(|path| path.to_str())
+                .unwrap_or("")
+                .to_owned();
+            (Box::new(stream) as Box<StreamItem>, AnySocketAddr::Unix(addr))
+        });
         Box::new(stream)
     }
 }
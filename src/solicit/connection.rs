@@ -24,7 +24,7 @@ use solicit::frame::*;
 use solicit::frame::settings::HttpSettings;
 use hpack;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum HttpFrameType {
     Data,
     Headers,
@@ -39,6 +39,41 @@ pub enum HttpFrameType {
     Unknown(u8),
 }
 
+impl HttpFrameType {
+    pub fn from_u8(frame_type: u8) -> HttpFrameType {
+        match frame_type {
+            frame::data::DATA_FRAME_TYPE => HttpFrameType::Data,
+            frame::headers::HEADERS_FRAME_TYPE => HttpFrameType::Headers,
+            frame::priority::PRIORITY_FRAME_TYPE => HttpFrameType::Priority,
+            frame::rst_stream::RST_STREAM_FRAME_TYPE => HttpFrameType::RstStream,
+            frame::settings::SETTINGS_FRAME_TYPE => HttpFrameType::Settings,
+            frame::push_promise::PUSH_PROMISE_FRAME_TYPE => HttpFrameType::PushPromise,
+            frame::ping::PING_FRAME_TYPE => HttpFrameType::Ping,
+            frame::goaway::GOAWAY_FRAME_TYPE => HttpFrameType::Goaway,
+            frame::window_update::WINDOW_UPDATE_FRAME_TYPE => HttpFrameType::WindowUpdate,
+            frame::continuation::CONTINUATION_FRAME_TYPE => HttpFrameType::Continuation,
+            t => HttpFrameType::Unknown(t),
+        }
+    }
+
+    /// Frame type name as in the spec, e. g. `WINDOW_UPDATE`
+    pub fn name(&self) -> &'static str {
+        match self {
+            &HttpFrameType::Data => "DATA",
+            &HttpFrameType::Headers => "HEADERS",
+            &HttpFrameType::Priority => "PRIORITY",
+            &HttpFrameType::RstStream => "RST_STREAM",
+            &HttpFrameType::Settings => "SETTINGS",
+            &HttpFrameType::PushPromise => "PUSH_PROMISE",
+            &HttpFrameType::Ping => "PING",
+            &HttpFrameType::Goaway => "GOAWAY",
+            &HttpFrameType::WindowUpdate => "WINDOW_UPDATE",
+            &HttpFrameType::Continuation => "CONTINUATION",
+            &HttpFrameType::Unknown(..) => "UNKNOWN",
+        }
+    }
+}
+
 /// An enum representing all frame variants that can be returned by an `HttpConnection` can handle.
 ///
 /// The variants wrap the appropriate `Frame` implementation, except for the `UnknownFrame`
@@ -141,6 +176,23 @@ impl HttpFrame{
             &HttpFrame::Unknown(ref f) => HttpFrameType::Unknown(f.frame_type()),
         }
     }
+
+    /// Length of the frame payload, i. e. without 9 bytes of frame header
+    pub fn payload_len(&self) -> u32 {
+        match self {
+            &HttpFrame::Data(ref f) => f.get_header().length,
+            &HttpFrame::Headers(ref f) => f.get_header().length,
+            &HttpFrame::Priority(ref f) => f.get_header().length,
+            &HttpFrame::RstStream(ref f) => f.get_header().length,
+            &HttpFrame::Settings(ref f) => f.get_header().length,
+            &HttpFrame::PushPromise(ref f) => f.get_header().length,
+            &HttpFrame::Ping(ref f) => f.get_header().length,
+            &HttpFrame::Goaway(ref f) => f.get_header().length,
+            &HttpFrame::WindowUpdate(ref f) => f.get_header().length,
+            &HttpFrame::Continuation(ref f) => f.get_header().length,
+            &HttpFrame::Unknown(ref f) => f.header().length,
+        }
+    }
 }
 
 impl FrameIR for HttpFrame {
@@ -260,6 +260,9 @@ impl Frame for HeadersFrame {
         // the appropriate flag is set.
         let priority = flags.is_set(HeadersFlag::Priority);
         let (data, stream_dep) = if priority {
+            if actual.len() < 5 {
+                return None;
+            }
             (actual.slice_from(5), Some(StreamDependency::parse(&actual[..5])))
         } else {
             (actual, None)
@@ -428,6 +431,19 @@ mod tests {
         assert_eq!(0, frame.padding_len);
     }
 
+    /// Tests that a HEADERS frame with the priority flag set, but without room
+    /// for the stream dependency is rejected.
+    #[test]
+    fn test_headers_frame_parse_priority_too_short() {
+        let payload = vec![0, 0, 1];
+        let header = FrameHeader::new(payload.len() as u32, 0x1, 0x20, 1);
+
+        let raw = raw_frame_from_parts(header, payload);
+        let frame: Option<HeadersFrame> = Frame::from_raw(&raw);
+
+        assert!(frame.is_none());
+    }
+
     /// Tests that a HEADERS frame with padding is correctly parsed.
     #[test]
     fn test_headers_frame_parse_with_padding() {
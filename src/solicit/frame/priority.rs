@@ -23,6 +23,18 @@ pub struct PriorityFrame {
 
 pub const PRIORITY_FRAME_TYPE: u8 = 0x2;
 
+impl PriorityFrame {
+    pub fn new(stream_id: StreamId, stream_dep: StreamId, exclusive: bool, weight: u8) -> PriorityFrame {
+        PriorityFrame {
+            flags: Flags::default(),
+            stream_id: stream_id,
+            exclusive: exclusive,
+            stream_dep: stream_dep,
+            weight: weight,
+        }
+    }
+}
+
 impl Frame for PriorityFrame {
     type FlagType = NoFlag;
 
@@ -76,7 +88,33 @@ impl Frame for PriorityFrame {
 }
 
 impl FrameIR for PriorityFrame {
-    fn serialize_into(self, _builder: &mut FrameBuilder) {
-        unimplemented!()
+    fn serialize_into(self, builder: &mut FrameBuilder) {
+        builder.write_header(self.get_header());
+        let exclusive_bit = if self.exclusive { 0x80000000 } else { 0 };
+        builder.write_u32(self.stream_dep | exclusive_bit);
+        builder.write_all(&[self.weight]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use solicit::tests::common::raw_frame_from_parts;
+
+    #[test]
+    fn serialize_parse() {
+        let frame = PriorityFrame::new(3, 1, true, 16);
+        let serialized = frame.clone().serialize_into_vec();
+        assert_eq!(&[0, 0, 5, PRIORITY_FRAME_TYPE, 0, 0, 0, 0, 3, 0x80, 0, 0, 1, 16][..], &serialized[..]);
+
+        let raw = RawFrame::parse(serialized).expect("parse");
+        assert_eq!(Some(frame), PriorityFrame::from_raw(&raw));
+    }
+
+    #[test]
+    fn parse_wrong_length() {
+        let raw = raw_frame_from_parts(FrameHeader::new(4, PRIORITY_FRAME_TYPE, 0, 3), vec![0, 0, 0, 1]);
+        assert_eq!(None, PriorityFrame::from_raw(&raw));
     }
 }
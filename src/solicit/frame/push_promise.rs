@@ -1,7 +1,4 @@
 use bytes::Bytes;
-use bytes::Buf;
-use bytes::IntoBuf;
-use bytes::BigEndian;
 
 use solicit::StreamId;
 use solicit::frame::Frame;
@@ -9,6 +6,7 @@ use solicit::frame::FrameIR;
 use solicit::frame::RawFrame;
 use solicit::frame::FrameHeader;
 use solicit::frame::parse_padded_payload;
+use solicit::frame::parse_stream_id;
 use solicit::frame::builder::FrameBuilder;
 
 use super::flags::Flag;
@@ -105,13 +103,13 @@ impl Frame for PushPromiseFrame {
             Some(t) => t,
         };
 
-        let mut buf = (&payload).into_buf();
+        if payload.len() < 4 {
+            return None;
+        }
 
-        let promised_stream_id = buf.get_u32::<BigEndian>();
+        let promised_stream_id = parse_stream_id(&payload);
 
-        let header_fragment = payload.slice(
-            (length as usize) - buf.remaining(),
-            payload.len());
+        let header_fragment = payload.slice_from(4);
 
         Some(PushPromiseFrame {
             header_fragment: header_fragment,
@@ -147,6 +145,7 @@ impl FrameIR for PushPromiseFrame {
         if padded {
             b.write_all(&[self.padding_len]);
         }
+        b.write_u32(self.promised_stream_id);
         // Now the actual headers fragment
         b.write_all(&self.header_fragment);
         // Finally, add the trailing padding, if required
@@ -156,3 +155,31 @@ impl FrameIR for PushPromiseFrame {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use solicit::tests::common::raw_frame_from_parts;
+
+    #[test]
+    fn serialize_parse() {
+        let mut frame = PushPromiseFrame {
+            flags: Flags::default(),
+            stream_id: 1,
+            promised_stream_id: 2,
+            header_fragment: Bytes::from(&b"abc"[..]),
+            padding_len: 0,
+        };
+        frame.flags.set(PushPromiseFlag::EndHeaders);
+
+        let raw = RawFrame::parse(frame.clone().serialize_into_vec()).expect("parse");
+        assert_eq!(Some(frame), PushPromiseFrame::from_raw(&raw));
+    }
+
+    #[test]
+    fn parse_too_short() {
+        let raw = raw_frame_from_parts(FrameHeader::new(3, PUSH_PROMISE_FRAME_TYPE, 0, 1), vec![0, 0, 2]);
+        assert_eq!(None, PushPromiseFrame::from_raw(&raw));
+    }
+}
@@ -0,0 +1,162 @@
+//! `std::future` API (enabled with `std-future` feature).
+//!
+//! Protocol implementation is based on futures 0.1, this module wraps
+//! futures and streams with futures 0.3 compat adapters, so they can be
+//! used with `.await`, and adapts `AsyncService` to `Service`.
+
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+
+use futures03::compat::Future01CompatExt;
+use futures03::compat::Stream01CompatExt;
+use futures03::future::TryFutureExt;
+use futures03::stream::Stream as StdStream;
+use futures03::stream::TryStreamExt;
+
+use bytes::Bytes;
+
+use client::Client;
+use message::SimpleHttpMessage;
+use result::Result;
+use resp::Response;
+use service::Service;
+use service::RequestContext;
+use solicit::header::Headers;
+use stream_part::HttpPartStream;
+use stream_part::HttpStreamPart;
+
+
+/// Boxed `std::future::Future` resolving to `Result`
+pub type BoxFuture<T> = Pin<Box<StdFuture<Output=Result<T>> + Send>>;
+/// Boxed futures 0.3 stream of `Result`
+pub type BoxStream<T> = Pin<Box<StdStream<Item=Result<T>> + Send>>;
+
+
+impl HttpPartStream {
+    /// Create from futures 0.3 stream
+    pub fn from_std_stream<S>(stream: S) -> HttpPartStream
+        where S : StdStream<Item=Result<HttpStreamPart>> + Send + 'static
+    {
+        HttpPartStream::new(Box::pin(stream).compat())
+    }
+
+    /// Convert to futures 0.3 stream
+    pub fn into_std_stream(self) -> BoxStream<HttpStreamPart> {
+        Box::pin(self.compat())
+    }
+
+    /// `DATA` frames as futures 0.3 stream, header frames are skipped
+    pub fn into_std_data_stream(self) -> BoxStream<Bytes> {
+        Box::pin(self.filter_data().compat())
+    }
+}
+
+impl Response {
+    /// Create from `std::future::Future` of headers and body
+    pub fn from_std_future<F>(future: F) -> Response
+        where F : StdFuture<Output=Result<(Headers, HttpPartStream)>> + Send + 'static
+    {
+        Response::new(Box::pin(future).compat())
+    }
+
+    /// Headers and body stream as `std::future::Future`
+    pub fn into_std_future(self) -> BoxFuture<(Headers, HttpPartStream)> {
+        Box::pin(self.0.compat())
+    }
+
+    /// Collect whole response as `std::future::Future`
+    pub fn collect_std(self) -> BoxFuture<SimpleHttpMessage> {
+        Box::pin(self.collect().compat())
+    }
+}
+
+impl Client {
+    /// Start request, resolve when response headers are received
+    pub fn request_async(&self, headers: Headers, body: HttpPartStream)
+        -> BoxFuture<(Headers, HttpPartStream)>
+    {
+        self.start_request(headers, body).into_std_future()
+    }
+
+    /// `GET` request, resolve with whole response
+    pub fn get_async(&self, path: &str, authority: &str) -> BoxFuture<SimpleHttpMessage> {
+        self.start_get(path, authority).collect_std()
+    }
+
+    /// `POST` request, resolve with whole response
+    pub fn post_async(&self, path: &str, authority: &str, body: Bytes) -> BoxFuture<SimpleHttpMessage> {
+        self.start_post(path, authority, body).collect_std()
+    }
+}
+
+
+/// Service returning `std::future::Future`.
+///
+/// Implemented for functions returning futures,
+/// use `AsyncServiceAdapter` to register it in a server.
+pub trait AsyncService : Send + Sync + 'static {
+    fn call(&self, headers: Headers, req: HttpPartStream) -> BoxFuture<(Headers, HttpPartStream)>;
+
+    /// Called by `Server` through `AsyncServiceAdapter`.
+    ///
+    /// Default implementation ignores the context.
+    fn call_with_context(&self, _context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> BoxFuture<(Headers, HttpPartStream)>
+    {
+        self.call(headers, req)
+    }
+}
+
+impl<F, R> AsyncService for F
+    where
+        F : Fn(Headers, HttpPartStream) -> R + Send + Sync + 'static,
+        R : StdFuture<Output=Result<(Headers, HttpPartStream)>> + Send + 'static,
+{
+    fn call(&self, headers: Headers, req: HttpPartStream) -> BoxFuture<(Headers, HttpPartStream)> {
+        Box::pin(self(headers, req))
+    }
+}
+
+/// `Service` implementation for `AsyncService`
+pub struct AsyncServiceAdapter<S : AsyncService>(pub S);
+
+impl<S : AsyncService> Service for AsyncServiceAdapter<S> {
+    fn start_request(&self, headers: Headers, req: HttpPartStream) -> Response {
+        Response::new(self.0.call(headers, req).compat())
+    }
+
+    fn start_request_with_context(&self, context: RequestContext, headers: Headers, req: HttpPartStream)
+        -> Response
+    {
+        Response::new(self.0.call_with_context(context, headers, req).compat())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures03::executor::block_on;
+    use futures03::stream;
+
+    #[test]
+    fn part_stream_round_trip() {
+        let parts = vec![
+            Ok(HttpStreamPart::intermediate_data(Bytes::from("ab"))),
+            Ok(HttpStreamPart::last_data(Bytes::from("cd"))),
+        ];
+        let stream = HttpPartStream::from_std_stream(stream::iter(parts));
+        let data: Vec<Bytes> = block_on(stream.into_std_data_stream().try_collect()).expect("ok");
+        assert_eq!(vec![Bytes::from("ab"), Bytes::from("cd")], data);
+    }
+
+    #[test]
+    fn response_round_trip() {
+        let response = Response::from_std_future(
+            Response::headers_and_bytes(Headers::ok_200(), "hello").into_std_future());
+        let message = block_on(response.collect_std()).expect("ok");
+        assert_eq!("200", message.headers.get(":status"));
+        assert_eq!(Bytes::from("hello"), message.body);
+    }
+}
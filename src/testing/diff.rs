@@ -0,0 +1,91 @@
+//! Readable differences between expected and actual values
+
+use std::cmp;
+use std::fmt;
+
+
+/// Line diff of two texts.
+///
+/// Lines are prefixed with ` ` if present in both texts,
+/// `-` if present only in `expected` and `+` if present only in `actual`.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // lcs[i][j] is the length of longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut r = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let (prefix, line) = if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+            (' ', a[i - 1])
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            i += 1;
+            ('-', a[i - 1])
+        } else {
+            j += 1;
+            ('+', b[j - 1])
+        };
+        r.push(prefix);
+        r.push_str(line);
+        r.push('\n');
+    }
+    r
+}
+
+/// Line diff of `{:#?}` representations, `None` if representations are equal
+pub fn debug_diff<T : fmt::Debug + ?Sized>(expected: &T, actual: &T) -> Option<String> {
+    let expected = format!("{:#?}", expected);
+    let actual = format!("{:#?}", actual);
+    if expected == actual {
+        None
+    } else {
+        Some(line_diff(&expected, &actual))
+    }
+}
+
+/// Like `assert_eq!`, but panic message contains diff of values
+pub fn assert_eq_diff<T : fmt::Debug + PartialEq + ?Sized>(expected: &T, actual: &T) {
+    if expected != actual {
+        let diff = debug_diff(expected, actual)
+            .unwrap_or_else(|| format!("{:?}\n(values differ, but have the same representation)", actual));
+        panic!("assertion failed: expected (-) != actual (+)\n{}", diff);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        assert_eq!(" a\n-b\n+x\n c\n+d\n", line_diff("a\nb\nc", "a\nx\nc\nd"));
+        assert_eq!(" a\n", line_diff("a", "a"));
+        assert_eq!("-a\n", line_diff("a", ""));
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!(None, debug_diff(&vec![1, 2], &vec![1, 2]));
+        assert_eq!(Some("-1\n+2\n".to_owned()), debug_diff(&1, &2));
+    }
+
+    #[test]
+    #[should_panic(expected = "+    3")]
+    fn assert_eq_panics_with_diff() {
+        assert_eq_diff(&vec![1, 2], &vec![1, 3]);
+    }
+}
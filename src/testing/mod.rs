@@ -0,0 +1,45 @@
+//! Frame-level test harness.
+//!
+//! `HttpConnectionTester` is a scripted HTTP/2 peer: test sends individual frames
+//! and checks frames it receives, e. g. to test how a service handles `RST_STREAM`
+//! or a small flow control window. Tester can act as a client
+//! (`HttpConnectionTester::connect`) to test `Server`, or as a server
+//! (`HttpServerTester::accept`) to test `Client`.
+//!
+//! `AsyncHttpConnectionTester` does the same over any `AsyncRead + AsyncWrite`
+//! stream inside an event loop.
+//!
+//! Expectations like `recv_frame_expect` panic with a line diff of expected (`-`)
+//! and actual (`+`) values.
+//!
+//! `Replay` sends frames of one peer of a connection recorded with `FrameCapture`
+//! to a `Server` or `Client` and compares its frames with the recorded ones,
+//! which turns a captured session into a regression test.
+//!
+//! ```no_run
+//! use httpbis::*;
+//! use httpbis::testing::*;
+//!
+//! let mut server = ServerBuilder::new_plain();
+//! server.set_addr((BIND_HOST, 0)).unwrap();
+//! let server = server.build().unwrap();
+//!
+//! let mut tester = HttpConnectionTester::connect_xchg(server.local_addr().port().unwrap());
+//! tester.send_get(1, "/nonexistent");
+//! let (_frame, headers, _) = tester.recv_frame_headers_decode();
+//! assert_eq!(404, headers.status());
+//! tester.send_rst(1, ErrorCode::Cancel);
+//! ```
+
+mod diff;
+mod replay;
+mod tester;
+mod tester_async;
+
+pub use self::diff::*;
+pub use self::replay::*;
+pub use self::tester::*;
+pub use self::tester_async::*;
+
+/// Host `HttpServerTester` listens on and `HttpConnectionTester` connects to
+pub const BIND_HOST: &str = "127.0.0.1";
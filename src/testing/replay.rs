@@ -0,0 +1,268 @@
+//! Replay of connections captured with `FrameCapture`.
+//!
+//! Frames sent by one peer of a recorded connection are sent again
+//! to a fresh `Server` or `Client`, and frames it sends back are compared
+//! with frames recorded from the original peer.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use frame_trace::CaptureReader;
+use frame_trace::CapturedFrame;
+use frame_trace::ConnFrameTracer;
+use frame_trace::FrameDirection;
+use frame_trace::FrameTracer;
+use frame_trace::TracedFrame;
+use frame_trace::format_frame_contents;
+
+use solicit::StreamId;
+use solicit::frame::RawFrame;
+use solicit::frame::headers::HEADERS_FRAME_TYPE;
+use solicit::frame::settings::SettingsFrame;
+use solicit::connection::HttpFrame;
+use solicit::connection::HttpFrameType;
+
+use super::diff::line_diff;
+use super::tester::HttpConnectionTester;
+
+
+/// Side of HTTP/2 connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    Client,
+    Server,
+}
+
+impl Peer {
+    pub fn other(&self) -> Peer {
+        match *self {
+            Peer::Client => Peer::Server,
+            Peer::Server => Peer::Client,
+        }
+    }
+}
+
+/// Frames of one captured connection
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Side which captured the connection
+    pub recorded_by: Peer,
+    pub frames: Vec<CapturedFrame>,
+}
+
+impl Recording {
+    /// Create a recording guessing `recorded_by` from direction
+    /// of the first HEADERS frame on client-initiated stream.
+    pub fn new(frames: Vec<CapturedFrame>) -> Recording {
+        let recorded_by = frames.iter()
+            .find(|f| f.frame.frame_type() == HEADERS_FRAME_TYPE && f.frame.get_stream_id() % 2 == 1)
+            .map(|f| match f.direction {
+                FrameDirection::Sent => Peer::Client,
+                FrameDirection::Received => Peer::Server,
+            })
+            .unwrap_or(Peer::Server);
+
+        Recording {
+            recorded_by: recorded_by,
+            frames: frames,
+        }
+    }
+
+    /// Read a capture and split it into connections,
+    /// ordered by the first frame of connection
+    pub fn read<R : Read>(read: R) -> io::Result<Vec<Recording>> {
+        let mut conns: Vec<(u64, Vec<CapturedFrame>)> = Vec::new();
+        for frame in CaptureReader::new(read)? {
+            let frame = frame?;
+            match conns.iter().position(|&(conn_id, _)| conn_id == frame.conn_id) {
+                Some(i) => conns[i].1.push(frame),
+                None => conns.push((frame.conn_id, vec![frame])),
+            }
+        }
+        Ok(conns.into_iter().map(|(_, frames)| Recording::new(frames)).collect())
+    }
+
+    /// Read a capture file written by `FrameCapture::create`
+    pub fn open<P : AsRef<Path>>(path: P) -> io::Result<Vec<Recording>> {
+        Recording::read(io::BufReader::new(File::open(path)?))
+    }
+
+    /// Which peer sent the frame
+    pub fn sent_by(&self, frame: &CapturedFrame) -> Peer {
+        match frame.direction {
+            FrameDirection::Sent => self.recorded_by,
+            FrameDirection::Received => self.recorded_by.other(),
+        }
+    }
+}
+
+
+/// Initial SETTINGS are sent during handshake, and they are not captured
+fn is_handshake_settings(frame: &RawFrame) -> bool {
+    match HttpFrame::from_raw(frame) {
+        Ok(HttpFrame::Settings(ref f)) => !f.is_ack(),
+        _ => false,
+    }
+}
+
+/// Collects transcript of frames grouped by stream
+struct Transcript {
+    ignore_headers: Vec<String>,
+    streams: Mutex<BTreeMap<StreamId, String>>,
+}
+
+impl FrameTracer for Transcript {
+    fn frame(&self, frame: &TracedFrame) {
+        let mut text = String::new();
+        for line in format_frame_contents(frame).lines() {
+            let ignored = self.ignore_headers.iter()
+                .find(|h| line.starts_with(&format!("    {}: ", h)[..]));
+            match ignored {
+                Some(h) => text.push_str(&format!("    {}: <ignored>", h)),
+                None => text.push_str(line),
+            }
+            text.push('\n');
+        }
+        if let Some(&HttpFrame::Data(ref f)) = frame.frame {
+            text.push_str(&format!("    data: {:?}\n", f.data));
+        }
+
+        let mut streams = self.streams.lock().unwrap();
+        streams.entry(frame.stream_id).or_insert_with(String::new).push_str(&text);
+    }
+}
+
+/// Result of `Replay::run`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayResult {
+    /// Frames recorded from the peer under test
+    pub expected: String,
+    /// Frames sent by the peer under test during replay
+    pub actual: String,
+}
+
+impl ReplayResult {
+    /// Line diff of transcripts, `None` if peer behaved as recorded
+    pub fn diff(&self) -> Option<String> {
+        if self.expected == self.actual {
+            None
+        } else {
+            Some(line_diff(&self.expected, &self.actual))
+        }
+    }
+
+    /// Panic with diff if peer did not behave as recorded
+    pub fn assert_same(&self) {
+        if let Some(diff) = self.diff() {
+            panic!("replay differs: recorded (-) != replayed (+)\n{}", diff);
+        }
+    }
+}
+
+/// Replay frames of one peer of `Recording` against the other peer.
+///
+/// Frames are sent and received in recorded order. Frames of the peer
+/// under test are compared per stream, because relative order of frames
+/// of different streams depends on timing. Client under test must start
+/// requests in the same order as the recorded client.
+pub struct Replay {
+    recording: Recording,
+    play: Peer,
+    /// How long to wait for each frame of the peer under test
+    pub timeout: Duration,
+    /// Headers which values are not compared, e. g. `date`
+    pub ignore_headers: Vec<String>,
+    /// Frames which are not compared, by default `WINDOW_UPDATE`,
+    /// because window updates depend on timing
+    pub ignore_frame_types: Vec<HttpFrameType>,
+}
+
+impl Replay {
+    /// Replay frames sent by `play`, e. g. `Peer::Client` to test a `Server`
+    pub fn new(recording: Recording, play: Peer) -> Replay {
+        Replay {
+            recording: recording,
+            play: play,
+            timeout: Duration::from_millis(500),
+            ignore_headers: Vec::new(),
+            ignore_frame_types: vec![HttpFrameType::WindowUpdate],
+        }
+    }
+
+    /// Replay over `tester` connection.
+    ///
+    /// Preface must already be sent (`HttpConnectionTester::connect` and `send_preface`)
+    /// or received (`HttpServerTester::accept` and `recv_preface`).
+    pub fn run(&self, tester: &mut HttpConnectionTester) -> ReplayResult {
+        let (ours, theirs): (Vec<&CapturedFrame>, Vec<&CapturedFrame>) = self.recording.frames.iter()
+            .partition(|f| self.recording.sent_by(f) == self.play);
+
+        if !ours.first().map_or(false, |f| is_handshake_settings(&f.frame)) {
+            tester.send_frame(SettingsFrame::new());
+        }
+        let mut skip_handshake_settings =
+            !theirs.first().map_or(false, |f| is_handshake_settings(&f.frame));
+
+        let mut expected = Vec::new();
+        let mut actual = Vec::new();
+
+        for frame in &self.recording.frames {
+            if self.recording.sent_by(frame) == self.play {
+                if let Err(e) = tester.try_send_raw(&frame.frame.serialize()[..]) {
+                    debug!("peer closed connection: {:?}", e);
+                }
+                continue;
+            }
+
+            expected.push(frame.frame.clone());
+            while let Some(f) = tester.recv_raw_frame_timeout(self.timeout) {
+                if mem::replace(&mut skip_handshake_settings, false) && is_handshake_settings(&f) {
+                    continue;
+                }
+                actual.push(f);
+                break;
+            }
+        }
+
+        // Frames sent after the end of recording
+        while let Some(f) = tester.recv_raw_frame_timeout(self.timeout) {
+            if mem::replace(&mut skip_handshake_settings, false) && is_handshake_settings(&f) {
+                continue;
+            }
+            actual.push(f);
+        }
+
+        ReplayResult {
+            expected: self.transcript(&expected),
+            actual: self.transcript(&actual),
+        }
+    }
+
+    fn transcript(&self, frames: &[RawFrame]) -> String {
+        let transcript = Arc::new(Transcript {
+            ignore_headers: self.ignore_headers.clone(),
+            streams: Mutex::new(BTreeMap::new()),
+        });
+
+        let mut tracer = ConnFrameTracer::new(transcript.clone());
+        for frame in frames {
+            let frame_type = HttpFrameType::from_u8(frame.frame_type());
+            if self.ignore_frame_types.contains(&frame_type) {
+                continue;
+            }
+            tracer.frames_sent(&frame.serialize()[..]);
+        }
+
+        let streams = transcript.streams.lock().unwrap();
+        streams.iter()
+            .map(|(stream_id, text)| format!("stream {}:\n{}", stream_id, text))
+            .collect()
+    }
+}
@@ -1,5 +1,3 @@
-#![allow(dead_code)]
-
 use std::io;
 use std::io::Write;
 use std::io::Read;
@@ -7,32 +5,39 @@ use std::io::Read;
 use std::str;
 use std::net;
 use std::net::ToSocketAddrs;
+use std::time::Duration;
 
 use bytes::Bytes;
 
-use httpbis;
-use httpbis::message::SimpleHttpMessage;
-use httpbis::solicit::StreamId;
-use httpbis::error::ErrorCode;
-use httpbis::solicit::header::*;
-use httpbis::solicit::frame::FrameIR;
-use httpbis::solicit::frame::settings::SettingsFrame;
-use httpbis::solicit::frame::headers::HeadersFrame;
-use httpbis::solicit::frame::headers::HeadersFlag;
-use httpbis::solicit::frame::continuation::ContinuationFrame;
-use httpbis::solicit::frame::continuation::ContinuationFlag;
-use httpbis::solicit::frame::data::DataFrame;
-use httpbis::solicit::frame::data::DataFlag;
-use httpbis::solicit::frame::goaway::GoawayFrame;
-use httpbis::solicit::frame::window_update::WindowUpdateFrame;
-use httpbis::solicit::frame::RawFrame;
-use httpbis::solicit::frame::rst_stream::RstStreamFrame;
-use httpbis::solicit::connection::HttpFrame;
-use httpbis::solicit::connection::HttpConnection;
+use message::SimpleHttpMessage;
+use solicit::StreamId;
+use error::ErrorCode;
+use solicit::header::*;
+use solicit::frame::FrameIR;
+use solicit::frame::settings::SettingsFrame;
+use solicit::frame::headers::HeadersFrame;
+use solicit::frame::headers::HeadersFlag;
+use solicit::frame::continuation::ContinuationFrame;
+use solicit::frame::continuation::ContinuationFlag;
+use solicit::frame::data::DataFrame;
+use solicit::frame::data::DataFlag;
+use solicit::frame::goaway::GoawayFrame;
+use solicit::frame::window_update::WindowUpdateFrame;
+use solicit::frame::RawFrame;
+use solicit::frame::FRAME_HEADER_LEN;
+use solicit::frame::unpack_header;
+use solicit::frame::rst_stream::RstStreamFrame;
+use solicit::connection::HttpFrame;
+use solicit::connection::HttpConnection;
+use solicit_async;
 
 use super::BIND_HOST;
+use super::diff::assert_eq_diff;
 
 
+/// Listener which accepts connections from the client under test.
+///
+/// Accepted connection is a scripted server-side peer.
 pub struct HttpServerTester(net::TcpListener);
 
 impl HttpServerTester {
@@ -62,10 +67,34 @@ impl HttpServerTester {
         debug!("accept connection.");
         r
     }
+
+    /// Accept connection, receive preface and exchange settings
+    pub fn accept_xchg(&self) -> HttpConnectionTester {
+        let mut conn = self.accept();
+        conn.recv_preface();
+        conn.settings_xchg();
+        conn
+    }
 }
 
-static PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+pub static PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// How the peer reacted to an error
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PeerError {
+    /// Peer sent `RST_STREAM`
+    Stream(StreamId, ErrorCode),
+    /// Peer sent `GOAWAY`
+    Goaway(ErrorCode),
+    /// Peer closed the connection without `GOAWAY`
+    Closed,
+}
 
+/// Scripted HTTP/2 peer: test sends and receives individual frames.
+///
+/// `conn` tracks HPACK state, settings and connection windows of the peer.
+/// Frames are received with blocking reads, so tester must not run
+/// on event loop thread of the client or server under test.
 pub struct HttpConnectionTester {
     tcp: net::TcpStream,
     pub conn: HttpConnection,
@@ -80,6 +109,19 @@ impl HttpConnectionTester {
         }
     }
 
+    /// Connect, send preface and exchange settings
+    pub fn connect_xchg(port: u16) -> HttpConnectionTester {
+        let mut conn = HttpConnectionTester::connect(port);
+        conn.send_preface();
+        conn.settings_xchg();
+        conn
+    }
+
+    /// Client address of the connection
+    pub fn local_addr(&self) -> net::SocketAddr {
+        self.tcp.local_addr().expect("local_addr")
+    }
+
     pub fn recv_preface(&mut self) {
         let mut preface = Vec::new();
         preface.resize(PREFACE.len(), 0);
@@ -102,11 +144,21 @@ impl HttpConnectionTester {
     }
 
     pub fn send_preface(&mut self) {
-        self.tcp.write(PREFACE).expect("send");
+        self.tcp.write_all(PREFACE).expect("send");
+    }
+
+    /// Send bytes as is, e. g. malformed frames
+    pub fn send_raw(&mut self, bytes: &[u8]) {
+        self.tcp.write_all(bytes).expect("send_raw");
+    }
+
+    /// Send bytes as is, error if peer closed the connection
+    pub fn try_send_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.tcp.write_all(bytes)
     }
 
     pub fn send_frame<F : FrameIR>(&mut self, frame: F) {
-        self.tcp.write(&frame.serialize_into_vec()).expect("send_frame");
+        self.tcp.write_all(&frame.serialize_into_vec()).expect("send_frame");
     }
 
     pub fn send_window_update_conn(&mut self, increment: u32) {
@@ -114,6 +166,10 @@ impl HttpConnectionTester {
         self.send_frame(WindowUpdateFrame::for_connection(increment));
     }
 
+    pub fn send_window_update_stream(&mut self, stream_id: StreamId, increment: u32) {
+        self.send_frame(WindowUpdateFrame::for_stream(stream_id, increment));
+    }
+
     pub fn send_goaway(&mut self, last_stream_id: StreamId) {
         self.send_frame(GoawayFrame::new(last_stream_id, ErrorCode::InadequateSecurity));
     }
@@ -150,12 +206,42 @@ impl HttpConnectionTester {
     }
 
     pub fn recv_raw_frame(&mut self) -> RawFrame {
-        httpbis::solicit_async::recv_raw_frame_sync(
+        solicit_async::recv_raw_frame_sync(
             &mut self.tcp,
             self.conn.our_settings_ack.max_frame_size)
                 .expect("recv_raw_frame")
     }
 
+    /// Receive frame of any size, `None` on EOF or if no frame arrives within `timeout`
+    pub fn recv_raw_frame_timeout(&mut self, timeout: Duration) -> Option<RawFrame> {
+        // read with blocking socket: `SO_RCVTIMEO` expiration is reported as `WouldBlock`,
+        // which async reader would treat as "not ready" and wait forever
+        self.tcp.set_read_timeout(Some(timeout)).expect("set_read_timeout");
+        let r = self.read_raw_frame();
+        self.tcp.set_read_timeout(None).expect("set_read_timeout");
+        match r {
+            Ok(raw_frame) => Some(raw_frame),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                || e.kind() == io::ErrorKind::TimedOut
+                || e.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                debug!("no frame: {:?}", e);
+                None
+            }
+            Err(e) => panic!("recv_raw_frame_timeout: {:?}", e),
+        }
+    }
+
+    fn read_raw_frame(&mut self) -> io::Result<RawFrame> {
+        let mut header = [0; FRAME_HEADER_LEN];
+        self.tcp.read_exact(&mut header)?;
+        let length = unpack_header(&header).length;
+        let mut frame = header.to_vec();
+        frame.resize(FRAME_HEADER_LEN + length as usize, 0);
+        self.tcp.read_exact(&mut frame[FRAME_HEADER_LEN..])?;
+        Ok(RawFrame::from(frame))
+    }
+
     pub fn fn_recv_frame_no_check_ack(&mut self) -> HttpFrame {
         let raw_frame = self.recv_raw_frame();
         let frame = HttpFrame::from_raw(&raw_frame).expect("parse frame");
@@ -163,6 +249,34 @@ impl HttpConnectionTester {
         frame
     }
 
+    /// Receive frame, `None` if connection is closed by peer
+    pub fn recv_frame_or_eof(&mut self) -> Option<HttpFrame> {
+        let max_frame_size = self.conn.our_settings_ack.max_frame_size;
+        match solicit_async::recv_raw_frame_sync(&mut self.tcp, max_frame_size) {
+            Ok(raw_frame) => {
+                let frame = HttpFrame::from_raw(&raw_frame).expect("parse frame");
+                debug!("received frame: {:?}", frame);
+                Some(frame)
+            }
+            Err(e) => {
+                debug!("connection closed: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Skip frames until peer resets a stream, sends `GOAWAY` or closes the connection
+    pub fn recv_error(&mut self) -> PeerError {
+        loop {
+            match self.recv_frame_or_eof() {
+                Some(HttpFrame::RstStream(f)) => return PeerError::Stream(f.stream_id, f.error_code()),
+                Some(HttpFrame::Goaway(f)) => return PeerError::Goaway(f.error_code()),
+                Some(_) => {}
+                None => return PeerError::Closed,
+            }
+        }
+    }
+
     pub fn recv_special_frame_process_special(&mut self) -> Option<HttpFrame> {
         let frame = self.fn_recv_frame_no_check_ack();
         if let HttpFrame::Settings(ref f) = frame {
@@ -190,6 +304,12 @@ impl HttpConnectionTester {
         }
     }
 
+    /// Receive frame and panic with diff if it is not equal to expected
+    pub fn recv_frame_expect<F : Into<HttpFrame>>(&mut self, expected: F) {
+        let frame = self.recv_frame();
+        assert_eq_diff(&expected.into(), &frame);
+    }
+
     pub fn recv_frame_settings(&mut self) -> SettingsFrame {
         match self.fn_recv_frame_no_check_ack() {
             HttpFrame::Settings(settings) => settings,
@@ -309,6 +429,12 @@ impl HttpConnectionTester {
         headers
     }
 
+    /// Receive headers and panic with diff if they are not equal to expected
+    pub fn recv_frame_headers_expect(&mut self, stream_id: StreamId, expected: &Headers, end: bool) {
+        let headers = self.recv_frame_headers_check(stream_id, end);
+        assert_eq_diff(expected, &headers);
+    }
+
     pub fn recv_frame_data(&mut self) -> DataFrame {
         match self.recv_frame() {
             HttpFrame::Data(data) => data,
@@ -371,3 +497,25 @@ impl HttpConnectionTester {
         }
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recv_raw_frame_timeout() {
+        let server = HttpServerTester::new();
+        let mut client = HttpConnectionTester::connect(server.port());
+        let mut conn = server.accept();
+
+        // silent peer
+        assert!(conn.recv_raw_frame_timeout(Duration::from_millis(100)).is_none());
+
+        client.send_window_update_conn(1);
+        assert!(conn.recv_raw_frame_timeout(Duration::from_millis(1000)).is_some());
+
+        drop(client);
+        assert!(conn.recv_raw_frame_timeout(Duration::from_millis(1000)).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Frame-level tester returning futures, for use inside an event loop
+
+use bytes::Bytes;
+
+use futures::future;
+use futures::future::Future;
+
+use tokio_io::AsyncRead;
+use tokio_io::AsyncWrite;
+use tokio_io::io::read_exact;
+use tokio_io::io::write_all;
+
+use error;
+use error::ErrorCode;
+use solicit::StreamId;
+use solicit::header::*;
+use solicit::frame::FrameIR;
+use solicit::frame::settings::SettingsFrame;
+use solicit::frame::headers::HeadersFrame;
+use solicit::frame::headers::HeadersFlag;
+use solicit::frame::data::DataFrame;
+use solicit::frame::data::DataFlag;
+use solicit::frame::rst_stream::RstStreamFrame;
+use solicit::connection::HttpFrame;
+use solicit::connection::HttpConnection;
+use solicit_async::*;
+
+use super::tester::PREFACE;
+use super::diff::assert_eq_diff;
+
+
+/// Scripted HTTP/2 peer over any async stream, e. g. inside `Core::run`.
+///
+/// Same as `HttpConnectionTester`, but operations consume the tester and return a future.
+pub struct AsyncHttpConnectionTester<I> {
+    io: I,
+    pub conn: HttpConnection,
+}
+
+impl<I : AsyncRead + AsyncWrite + 'static> AsyncHttpConnectionTester<I> {
+    pub fn new(io: I) -> AsyncHttpConnectionTester<I> {
+        AsyncHttpConnectionTester {
+            io: io,
+            conn: HttpConnection::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> I {
+        self.io
+    }
+
+    fn write(self, bytes: Vec<u8>) -> HttpFuture<Self> {
+        let AsyncHttpConnectionTester { io, conn } = self;
+        Box::new(write_all(io, bytes)
+            .map(move |(io, _)| AsyncHttpConnectionTester { io: io, conn: conn })
+            .map_err(error::Error::from))
+    }
+
+    pub fn send_preface(self) -> HttpFuture<Self> {
+        self.write(PREFACE.to_vec())
+    }
+
+    pub fn recv_preface(self) -> HttpFuture<Self> {
+        let AsyncHttpConnectionTester { io, conn } = self;
+        Box::new(read_exact(io, vec![0; PREFACE.len()])
+            .map_err(error::Error::from)
+            .map(move |(io, preface)| {
+                assert_eq!(PREFACE, &preface[..]);
+                AsyncHttpConnectionTester { io: io, conn: conn }
+            }))
+    }
+
+    pub fn send_frame<F : FrameIR>(self, frame: F) -> HttpFuture<Self> {
+        self.write(frame.serialize_into_vec())
+    }
+
+    pub fn send_settings(mut self, settings: SettingsFrame) -> HttpFuture<Self> {
+        assert!(self.conn.our_settings_sent.is_none());
+        let mut new_settings = self.conn.our_settings_ack;
+        new_settings.apply_from_frame(&settings);
+        self.conn.our_settings_sent = Some(new_settings);
+        self.send_frame(settings)
+    }
+
+    pub fn send_headers(mut self, stream_id: StreamId, headers: Headers, end: bool) -> HttpFuture<Self> {
+        let fragment = self.conn.encoder.encode(headers.0.iter().map(|h| (h.name(), h.value())));
+        let mut headers_frame = HeadersFrame::new(fragment, stream_id);
+        headers_frame.set_flag(HeadersFlag::EndHeaders);
+        if end {
+            headers_frame.set_flag(HeadersFlag::EndStream);
+        }
+        self.send_frame(headers_frame)
+    }
+
+    pub fn send_data(mut self, stream_id: StreamId, data: &[u8], end: bool) -> HttpFuture<Self> {
+        let mut data_frame = DataFrame::new(stream_id);
+        data_frame.data = Bytes::from(data);
+        if end {
+            data_frame.set_flag(DataFlag::EndStream);
+        }
+        self.conn.out_window_size.try_decrease_to_positive(data.len() as i32).expect("decrease");
+        self.send_frame(data_frame)
+    }
+
+    pub fn send_rst(self, stream_id: StreamId, error_code: ErrorCode) -> HttpFuture<Self> {
+        self.send_frame(RstStreamFrame::new(stream_id, error_code))
+    }
+
+    /// Receive any frame
+    pub fn recv_frame_no_check_ack(self) -> HttpFuture<(Self, HttpFrame)> {
+        let AsyncHttpConnectionTester { io, conn } = self;
+        let max_frame_size = conn.our_settings_ack.max_frame_size;
+        Box::new(recv_http_frame(io, max_frame_size)
+            .map(move |(io, frame)| {
+                debug!("received frame: {:?}", frame);
+                (AsyncHttpConnectionTester { io: io, conn: conn }, frame)
+            }))
+    }
+
+    /// Receive frame, processing SETTINGS ack and connection WINDOW_UPDATE
+    pub fn recv_frame(self) -> HttpFuture<(Self, HttpFrame)> {
+        Box::new(future::loop_fn(self, |tester| {
+            tester.recv_frame_no_check_ack().map(|(mut tester, frame)| {
+                if let HttpFrame::Settings(ref f) = frame {
+                    if tester.conn.our_settings_sent.is_some() && f.is_ack() {
+                        tester.conn.our_settings_ack = tester.conn.our_settings_sent.take().unwrap();
+                        return future::Loop::Continue(tester);
+                    }
+                }
+                if let HttpFrame::WindowUpdate(ref f) = frame {
+                    if f.stream_id == 0 {
+                        tester.conn.out_window_size.try_increase(f.increment).expect("increment");
+                    }
+                    return future::Loop::Continue(tester);
+                }
+                future::Loop::Break((tester, frame))
+            })
+        }))
+    }
+
+    /// Receive frame and panic with diff if it is not equal to expected
+    pub fn recv_frame_expect<F : Into<HttpFrame>>(self, expected: F) -> HttpFuture<Self> {
+        let expected = expected.into();
+        Box::new(self.recv_frame().map(move |(tester, frame)| {
+            assert_eq_diff(&expected, &frame);
+            tester
+        }))
+    }
+
+    /// Receive HEADERS frame (without CONTINUATION) and decode headers
+    pub fn recv_frame_headers_decode(self) -> HttpFuture<(Self, HeadersFrame, Headers)> {
+        Box::new(self.recv_frame().map(|(mut tester, frame)| {
+            let frame = match frame {
+                HttpFrame::Headers(headers) => headers,
+                f => panic!("expecting HEADERS, got: {:?}", f),
+            };
+            let headers = tester.conn.decoder.decode(frame.header_fragment()).expect("decode");
+            let headers = Headers(headers.into_iter().map(|(n, v)| Header::new(n, v)).collect());
+            (tester, frame, headers)
+        }))
+    }
+
+    /// Exchange settings with the peer and wait for ACK of our settings
+    pub fn settings_xchg(self) -> HttpFuture<Self> {
+        Box::new(self.send_settings(SettingsFrame::new())
+            .and_then(|tester| tester.recv_frame_no_check_ack())
+            .and_then(|(mut tester, frame)| {
+                match frame {
+                    HttpFrame::Settings(ref settings) if !settings.is_ack() => {
+                        tester.conn.peer_settings.apply_from_frame(settings);
+                    }
+                    f => panic!("expecting SETTINGS, got: {:?}", f),
+                }
+                tester.send_frame(SettingsFrame::new_ack())
+            })
+            .and_then(|tester| tester.recv_frame_no_check_ack())
+            .map(|(mut tester, frame)| {
+                match frame {
+                    HttpFrame::Settings(ref settings) if settings.is_ack() => {}
+                    f => panic!("expecting SETTINGS ack, got: {:?}", f),
+                }
+                tester.conn.our_settings_ack = tester.conn.our_settings_sent.take().unwrap();
+                tester
+            }))
+    }
+}
@@ -17,6 +17,7 @@ use bytes::Bytes;
 
 use futures::future::Future;
 use futures::stream::Stream;
+use futures::sync::mpsc as futures_mpsc;
 use futures::sync::oneshot;
 
 use tokio_core::reactor;
@@ -124,6 +125,9 @@ fn client_call_dropped() {
 
         drop(req);
 
+        server_tester.recv_rst_frame_check(1, ErrorCode::Cancel);
+
+        // must be ignored by client
         server_tester.send_headers(1, Headers::ok_200(), true);
     }
 
@@ -139,6 +143,80 @@ fn client_call_dropped() {
     assert_eq!(0, state.streams.len(), "{:?}", state);
 }
 
+#[test]
+fn client_call_dropped_after_end_stream() {
+    init_logger();
+
+    let server = HttpServerTester::new();
+
+    let client: Client =
+        Client::new_plain(BIND_HOST, server.port(), Default::default()).expect("connect");
+
+    let mut server_tester = server.accept();
+    server_tester.recv_preface();
+    server_tester.settings_xchg();
+
+    let (body_tx, body_rx) = futures_mpsc::unbounded();
+    let body = HttpPartStream::bytes(body_rx.map_err(|()| Error::Other("body")));
+    let req = client.start_request(Headers::new_post("/upload"), body);
+
+    server_tester.recv_frame_headers_check(1, false);
+    server_tester.send_headers(1, Headers::ok_200(), true);
+
+    let (headers, resp_body) = req.0.wait().expect("headers");
+    assert_eq!(200, headers.status());
+
+    // wait until client processed END_STREAM
+    for _ in 0..1000 {
+        let state: ConnectionStateSnapshot = client.dump_state().wait().expect("state");
+        if state.streams[&1].state.is_closed_remote() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // stream is half-closed (remote), so request body is still sent
+    drop(resp_body);
+
+    body_tx.unbounded_send(Bytes::from("abcd")).expect("send");
+    assert_eq!(b"abcd", &server_tester.recv_frame_data_check(1, false)[..]);
+
+    drop(body_tx);
+    server_tester.recv_frame_data_check(1, true);
+}
+
+#[test]
+fn request_deadline() {
+    init_logger();
+
+    let server = HttpServerTester::new();
+
+    let client: Client =
+        Client::new_plain(BIND_HOST, server.port(), Default::default()).expect("connect");
+
+    let mut server_tester = server.accept();
+    server_tester.recv_preface();
+    server_tester.settings_xchg();
+
+    let req = client.start_request_with_options(
+        Headers::new_get("/slow"),
+        HttpPartStream::empty(),
+        ClientRequestOptions::with_timeout(Duration::from_millis(100)));
+
+    server_tester.recv_message(1);
+
+    server_tester.recv_rst_frame_check(1, ErrorCode::Cancel);
+
+    match req.collect().wait() {
+        Ok(..) => panic!("expected error"),
+        Err(Error::RequestTimeout) => {},
+        Err(e) => panic!("wrong error: {:?}", e),
+    }
+
+    let state: ConnectionStateSnapshot = client.dump_state().wait().expect("state");
+    assert_eq!(0, state.streams.len(), "{:?}", state);
+}
+
 #[test]
 fn reconnect_on_disconnect() {
     init_logger();
@@ -225,6 +303,36 @@ fn reconnect_on_goaway() {
     }
 }
 
+#[test]
+fn fail_fast_while_waiting_to_reconnect() {
+    init_logger();
+
+    // Take a free port and close it, so connection is refused
+    let port = HttpServerTester::new().port();
+
+    let mut conf = ClientConf::new();
+    conf.reconnect = Some(ClientReconnectConf {
+        initial_delay: Duration::from_secs(3600),
+        fail_fast: true,
+        ..Default::default()
+    });
+
+    let client: Client = Client::new_plain(BIND_HOST, port, conf).expect("client");
+
+    while client.connection_state() != ClientConnectionState::TransientFailure {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    match client.start_get("/fail", "localhost").collect().wait() {
+        Ok(..) => panic!("expected error"),
+        Err(Error::Other(..)) => {},
+        Err(e) => panic!("wrong error: {:?}", e),
+    }
+
+    assert!(client.wait_for_connect().wait().is_err());
+    assert_eq!(ClientConnectionState::TransientFailure, client.connection_state());
+}
+
 #[test]
 pub fn issue_89() {
     init_logger();
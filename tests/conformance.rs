@@ -0,0 +1,855 @@
+//! Protocol conformance tests, modelled on h2spec.
+//!
+//! Each test checks one requirement of RFC 7540 (HTTP/2) or RFC 7541 (HPACK),
+//! tests are grouped in modules by spec section.
+//! Requirements the implementation does not satisfy yet are `#[ignore]`d
+//! with a comment, run them with `cargo test --test conformance -- --ignored`.
+//!
+//! When a stream error is required, connection error is accepted as well,
+//! because an endpoint MAY treat a stream error as a connection error (5.4.2).
+//! Server closes the connection without `GOAWAY` on connection errors,
+//! that is also accepted.
+
+extern crate bytes;
+extern crate regex;
+extern crate futures;
+extern crate httpbis;
+extern crate tokio_core;
+extern crate env_logger;
+
+mod test_misc;
+
+use httpbis::ErrorCode;
+use httpbis::Headers;
+use httpbis::solicit::StreamId;
+use httpbis::solicit::frame::FrameHeader;
+use httpbis::solicit::frame::pack_header;
+use httpbis::solicit::frame::headers::HeadersFrame;
+use httpbis::solicit::frame::headers::HeadersFlag;
+use httpbis::solicit::frame::continuation::ContinuationFrame;
+use httpbis::solicit::frame::continuation::ContinuationFlag;
+
+use test_misc::*;
+
+
+fn init_logger() {
+    env_logger::try_init().ok();
+}
+
+/// Connect to server and exchange settings
+fn connect(server: &ServerTest) -> HttpConnectionTester {
+    init_logger();
+    HttpConnectionTester::connect_xchg(server.port)
+}
+
+/// Serialize a frame without validation
+fn raw_frame(frame_type: u8, flags: u8, stream_id: StreamId, payload: &[u8]) -> Vec<u8> {
+    let header = FrameHeader::new(payload.len() as u32, frame_type, flags, stream_id);
+    let mut r = pack_header(&header).to_vec();
+    r.extend_from_slice(payload);
+    r
+}
+
+fn request_headers(method: &str, path: &str) -> Headers {
+    let mut headers = Headers::new();
+    headers.add(":method", method);
+    headers.add(":path", path);
+    headers.add(":scheme", "http");
+    headers.add(":authority", "localhost");
+    headers
+}
+
+fn encode(tester: &mut HttpConnectionTester, headers: &Headers) -> Vec<u8> {
+    tester.conn.encoder.encode(headers.0.iter().map(|h| (h.name(), h.value())))
+}
+
+fn assert_connection_error(error: PeerError, error_code: ErrorCode) {
+    match error {
+        PeerError::Goaway(code) => assert_eq!(error_code, code),
+        PeerError::Closed => {}
+        e => panic!("expecting connection error {:?}, got {:?}", error_code, e),
+    }
+}
+
+fn assert_stream_error(error: PeerError, stream_id: StreamId, error_code: ErrorCode) {
+    match error {
+        PeerError::Stream(id, code) => {
+            assert_eq!(stream_id, id);
+            assert_eq!(error_code, code);
+        }
+        e => assert_connection_error(e, error_code),
+    }
+}
+
+/// Server is still usable on the same connection
+fn assert_alive(tester: &mut HttpConnectionTester, stream_id: StreamId) {
+    assert_eq!(200, tester.get(stream_id, "/echo").headers.status());
+}
+
+// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_HEADERS: u8 = 0x4;
+const ACK: u8 = 0x1;
+
+
+/// 3.5. HTTP/2 Connection Preface
+mod s3_5_preface {
+    use super::*;
+
+    /// Server connection preface consists of a potentially empty SETTINGS frame
+    /// that MUST be the first frame the server sends
+    #[test]
+    fn server_sends_settings_first() {
+        init_logger();
+        let server = ServerTest::new();
+        let mut tester = HttpConnectionTester::connect(server.port);
+        tester.send_preface();
+        let settings = tester.recv_frame_settings_set();
+        assert!(!settings.is_ack());
+    }
+
+    /// Invalid connection preface MUST be treated as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn invalid_preface() {
+        init_logger();
+        let server = ServerTest::new();
+        let mut tester = HttpConnectionTester::connect(server.port);
+        tester.send_raw(b"PRI * HTTP/2.0\r\n\r\nXX\r\n\r\n");
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 4.1. Frame Format
+mod s4_1_frame_format {
+    use super::*;
+
+    /// Implementations MUST ignore and discard any frame that has a type that is unknown
+    #[test]
+    fn unknown_frame_type() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(0xff, 0, 0, b"abcd"));
+        assert_alive(&mut tester, 1);
+    }
+
+    /// Unknown flags MUST be ignored
+    #[test]
+    fn unknown_flags() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PING, 0xf0, 0, &[1, 2, 3, 4, 5, 6, 7, 8]));
+        match tester.recv_frame() {
+            httpbis::solicit::connection::HttpFrame::Ping(ping) => {
+                assert!(ping.is_ack());
+                assert_eq!(0x0102030405060708, ping.opaque_data());
+            }
+            f => panic!("expecting PING, got: {:?}", f),
+        }
+    }
+}
+
+/// 4.2. Frame Size
+mod s4_2_frame_size {
+    use super::*;
+
+    /// An endpoint MUST send an error code of FRAME_SIZE_ERROR
+    /// if a frame exceeds the size defined in SETTINGS_MAX_FRAME_SIZE
+    #[test]
+    fn data_exceeds_max_frame_size() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_raw(&raw_frame(DATA, 0, 1, &[0; 16385]));
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::FrameSizeError);
+    }
+
+    /// A frame size error in a frame that could alter the state of the entire connection
+    /// MUST be treated as a connection error
+    #[test]
+    fn headers_exceeds_max_frame_size() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 1, &[0; 16385]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+}
+
+/// 4.3. Header Compression and Decompression
+mod s4_3_header_compression {
+    use super::*;
+
+    /// A decoding error in a header block MUST be treated as a connection error
+    /// of type COMPRESSION_ERROR
+    #[test]
+    fn invalid_header_block() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        // Indexed header field with index 70 while dynamic table is empty
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 1, &[0x80 | 70]));
+        assert_connection_error(tester.recv_error(), ErrorCode::CompressionError);
+    }
+}
+
+/// 5.1. Stream States
+mod s5_1_stream_states {
+    use super::*;
+
+    /// idle: Receiving any frame other than HEADERS or PRIORITY on a stream in this state
+    /// MUST be treated as a connection error of type PROTOCOL_ERROR
+    #[test]
+    #[ignore] // server responds with RST_STREAM(STREAM_CLOSED)
+    fn data_on_idle_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_data(1, b"abc", false);
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// idle: RST_STREAM on idle stream MUST be treated as a connection error of type PROTOCOL_ERROR
+    #[test]
+    #[ignore] // server ignores the frame
+    fn rst_stream_on_idle_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_rst(1, ErrorCode::Cancel);
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// half-closed (remote): If an endpoint receives additional frames, other than
+    /// WINDOW_UPDATE, PRIORITY, or RST_STREAM, for a stream that is in this state,
+    /// it MUST respond with a stream error of type STREAM_CLOSED
+    #[test]
+    #[ignore] // server accepts DATA after END_STREAM while response is not finished
+    fn data_on_half_closed_remote_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("GET", "/blocks/100000/1"), true);
+        tester.send_data(1, b"abc", false);
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::StreamClosed);
+    }
+
+    /// closed: An endpoint that receives any frame other than PRIORITY after receiving
+    /// a RST_STREAM or END_STREAM MUST treat that as a stream error of type STREAM_CLOSED
+    #[test]
+    fn data_on_closed_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        assert_alive(&mut tester, 1);
+        tester.send_data(1, b"abc", false);
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::StreamClosed);
+    }
+}
+
+/// 5.1.1. Stream Identifiers
+mod s5_1_1_stream_identifiers {
+    use super::*;
+
+    /// Streams initiated by a client MUST use odd-numbered stream identifiers
+    #[test]
+    fn even_stream_id() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(2, request_headers("GET", "/echo"), true);
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// An endpoint that receives an unexpected stream identifier MUST respond
+    /// with a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn decreasing_stream_id() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        assert_alive(&mut tester, 5);
+        tester.send_headers(3, request_headers("GET", "/echo"), true);
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 5.5. Extending HTTP/2
+mod s5_5_extending {
+    use super::*;
+
+    /// Extension frames that appear in the middle of a header block are not permitted;
+    /// these MUST be treated as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn unknown_frame_in_header_block() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        let fragment = encode(&mut tester, &request_headers("GET", "/echo"));
+        tester.send_raw(&raw_frame(HEADERS, 0, 1, &fragment));
+        tester.send_raw(&raw_frame(0xff, 0, 0, b"abcd"));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 6.1. DATA
+mod s6_1_data {
+    use super::*;
+
+    /// If a DATA frame is received whose stream identifier field is 0x0,
+    /// the recipient MUST respond with a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn data_on_stream_0() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(DATA, 0, 0, b"abc"));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 6.2. HEADERS
+mod s6_2_headers {
+    use super::*;
+
+    /// If a HEADERS frame is received whose stream identifier field is 0x0,
+    /// the recipient MUST respond with a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn headers_on_stream_0() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        let fragment = encode(&mut tester, &request_headers("GET", "/echo"));
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 0, &fragment));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 6.3. PRIORITY
+mod s6_3_priority {
+    use super::*;
+
+    /// The PRIORITY frame can be sent on a stream in any state, including idle streams
+    #[test]
+    fn priority_on_idle_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PRIORITY, 0, 1, &[0, 0, 0, 0, 16]));
+        assert_alive(&mut tester, 1);
+    }
+
+    /// A PRIORITY frame with a length other than 5 octets MUST be treated
+    /// as a stream error of type FRAME_SIZE_ERROR
+    #[test]
+    fn priority_wrong_length() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PRIORITY, 0, 1, &[0, 0, 0, 0]));
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::FrameSizeError);
+    }
+}
+
+/// 6.4. RST_STREAM
+mod s6_4_rst_stream {
+    use super::*;
+
+    use httpbis::solicit::connection::HttpFrame;
+    use httpbis::solicit::frame::ping::PingFrame;
+
+    /// If a RST_STREAM frame is received with a stream identifier of 0x0,
+    /// the recipient MUST treat this as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn rst_stream_on_stream_0() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(RST_STREAM, 0, 0, &[0, 0, 0, 8]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// A RST_STREAM frame with a length other than 4 octets MUST be treated
+    /// as a connection error of type FRAME_SIZE_ERROR
+    #[test]
+    fn rst_stream_wrong_length() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_raw(&raw_frame(RST_STREAM, 0, 1, &[0, 0, 0, 0, 8]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+
+    /// After receiving a RST_STREAM on a stream, the receiver MUST NOT send
+    /// additional frames for that stream
+    #[test]
+    fn no_frames_after_rst_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_rst(1, ErrorCode::Cancel);
+
+        // frames of stream 1 may be sent before RST_STREAM is processed,
+        // PING is answered after it
+        tester.send_frame(PingFrame::with_data(17));
+        loop {
+            match tester.recv_frame() {
+                HttpFrame::Ping(ref ping) if ping.is_ack() => {
+                    assert_eq!(17, ping.opaque_data());
+                    break;
+                }
+                f => assert_eq!(1, f.get_stream_id(), "unexpected frame: {:?}", f),
+            }
+        }
+
+        tester.send_get(3, "/echo");
+        let message = tester.recv_message(3);
+        assert_eq!(200, message.headers.status());
+    }
+}
+
+/// 6.5. SETTINGS
+mod s6_5_settings {
+    use super::*;
+
+    use httpbis::solicit::frame::settings::SettingsFrame;
+
+    /// Upon receiving the SETTINGS frame, the recipient MUST immediately emit
+    /// a SETTINGS frame with the ACK flag set
+    #[test]
+    fn settings_acknowledged() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_recv_settings(SettingsFrame::new());
+    }
+
+    /// Receipt of a SETTINGS frame with the ACK flag set and a length field value other than 0
+    /// MUST be treated as a connection error of type FRAME_SIZE_ERROR
+    #[test]
+    fn ack_with_payload() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, ACK, 0, &[0, 3, 0, 0, 0, 100]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+
+    /// If an endpoint receives a SETTINGS frame whose stream identifier field is anything
+    /// other than 0x0, the endpoint MUST respond with a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn settings_on_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 1, &[0, 3, 0, 0, 0, 100]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// A SETTINGS frame with a length other than a multiple of 6 octets MUST be treated
+    /// as a connection error of type FRAME_SIZE_ERROR
+    #[test]
+    fn length_not_multiple_of_6() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+
+    /// An endpoint that receives a SETTINGS frame with any unknown or unsupported identifier
+    /// MUST ignore that setting
+    #[test]
+    fn unknown_setting() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 0, &[0, 0xff, 0, 0, 0, 1]));
+        assert!(tester.recv_frame_settings().is_ack());
+        assert_alive(&mut tester, 1);
+    }
+
+    /// SETTINGS_ENABLE_PUSH: Any value other than 0 or 1 MUST be treated
+    /// as a connection error of type PROTOCOL_ERROR
+    #[test]
+    #[ignore] // value is not validated
+    fn invalid_enable_push() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 0, &[0, 2, 0, 0, 0, 2]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// SETTINGS_INITIAL_WINDOW_SIZE: Values above the maximum flow-control window size
+    /// of 2^31-1 MUST be treated as a connection error of type FLOW_CONTROL_ERROR
+    #[test]
+    #[ignore] // value is not validated
+    fn initial_window_size_too_large() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 0, &[0, 4, 0x80, 0, 0, 0]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FlowControlError);
+    }
+
+    /// SETTINGS_MAX_FRAME_SIZE: Values outside of range 2^14..2^24-1 MUST be treated
+    /// as a connection error of type PROTOCOL_ERROR
+    #[test]
+    #[ignore] // value is not validated
+    fn max_frame_size_too_small() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(SETTINGS, 0, 0, &[0, 5, 0, 0, 0x3f, 0xff]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 6.7. PING
+mod s6_7_ping {
+    use super::*;
+
+    use httpbis::solicit::frame::ping::PingFrame;
+
+    /// Receivers of a PING frame that does not include an ACK flag MUST send a PING frame
+    /// with the ACK flag set in response, with an identical payload
+    #[test]
+    fn ping_acknowledged() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PING, 0, 0, &[1, 2, 3, 4, 5, 6, 7, 8]));
+        tester.recv_frame_expect(PingFrame::new_ack(0x0102030405060708));
+    }
+
+    /// A PING frame with ACK flag MUST NOT be responded to
+    #[test]
+    #[ignore] // server closes connection on unexpected PING ACK
+    fn ping_ack_not_responded() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PING, ACK, 0, &[1, 1, 1, 1, 1, 1, 1, 1]));
+        tester.send_raw(&raw_frame(PING, 0, 0, &[2, 2, 2, 2, 2, 2, 2, 2]));
+        tester.recv_frame_expect(PingFrame::new_ack(0x0202020202020202));
+    }
+
+    /// If a PING frame is received with a stream identifier field value other than 0x0,
+    /// the recipient MUST respond with a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn ping_on_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PING, 0, 1, &[0; 8]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// Receipt of a PING frame with a length field value other than 8
+    /// MUST be treated as a connection error of type FRAME_SIZE_ERROR
+    #[test]
+    fn ping_wrong_length() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(PING, 0, 0, &[0; 6]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+}
+
+/// 6.8. GOAWAY
+mod s6_8_goaway {
+    use super::*;
+
+    /// An endpoint MUST treat a GOAWAY frame with a stream identifier other than 0x0
+    /// as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn goaway_on_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(GOAWAY, 0, 1, &[0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 6.9. WINDOW_UPDATE
+mod s6_9_window_update {
+    use super::*;
+
+    use httpbis::solicit::frame::data::DataFrame;
+    use httpbis::solicit::frame::settings::SettingsFrame;
+    use httpbis::solicit::frame::settings::HttpSetting;
+
+    /// A receiver MUST treat the receipt of a WINDOW_UPDATE frame with a flow-control
+    /// window increment of 0 as a connection error of type PROTOCOL_ERROR
+    /// if it is on the connection
+    #[test]
+    fn zero_increment_on_connection() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(WINDOW_UPDATE, 0, 0, &[0, 0, 0, 0]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// A receiver MUST treat the receipt of a WINDOW_UPDATE frame with a flow-control
+    /// window increment of 0 as a stream error of type PROTOCOL_ERROR
+    #[test]
+    fn zero_increment_on_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_raw(&raw_frame(WINDOW_UPDATE, 0, 1, &[0, 0, 0, 0]));
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::ProtocolError);
+    }
+
+    /// A WINDOW_UPDATE frame with a length other than 4 octets MUST be treated
+    /// as a connection error of type FRAME_SIZE_ERROR
+    #[test]
+    fn wrong_length() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(WINDOW_UPDATE, 0, 0, &[0, 0, 0, 0, 1]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FrameSizeError);
+    }
+
+    /// If a sender receives a WINDOW_UPDATE that causes a flow-control window to exceed
+    /// this maximum, it MUST terminate the connection with FLOW_CONTROL_ERROR
+    #[test]
+    fn connection_window_overflow() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(WINDOW_UPDATE, 0, 0, &[0x7f, 0xff, 0xff, 0xff]));
+        assert_connection_error(tester.recv_error(), ErrorCode::FlowControlError);
+    }
+
+    /// If a sender receives a WINDOW_UPDATE that causes a flow-control window to exceed
+    /// this maximum, it MUST send a RST_STREAM with FLOW_CONTROL_ERROR
+    #[test]
+    fn stream_window_overflow() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_raw(&raw_frame(WINDOW_UPDATE, 0, 1, &[0x7f, 0xff, 0xff, 0xff]));
+        assert_stream_error(tester.recv_error(), 1, ErrorCode::FlowControlError);
+    }
+
+    /// 6.9.1. A sender MUST NOT send a flow-controlled frame with a length
+    /// that exceeds the space available in either of the flow-control windows
+    #[test]
+    fn initial_window_size_respected() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_recv_settings(SettingsFrame::from_settings(vec![HttpSetting::InitialWindowSize(3)]));
+
+        tester.send_get(1, "/blocks/5/1");
+        assert_eq!(200, tester.recv_frame_headers_check(1, false).status());
+        tester.recv_frame_expect(DataFrame::with_data(1, vec![0u8; 3]));
+
+        tester.send_window_update_stream(1, 10);
+        assert_eq!(vec![0u8; 2], tester.recv_frame_data_tail(1));
+    }
+}
+
+/// 6.10. CONTINUATION
+mod s6_10_continuation {
+    use super::*;
+
+    /// Any number of CONTINUATION frames can be sent
+    #[test]
+    fn multiple_continuation_frames() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        let fragment = encode(&mut tester, &request_headers("GET", "/echo"));
+        assert!(fragment.len() >= 3);
+
+        let mut headers = HeadersFrame::new(&fragment[..1], 1);
+        headers.set_flag(HeadersFlag::EndStream);
+        tester.send_frame(headers);
+        tester.send_frame(ContinuationFrame::new(&fragment[1..2], 1));
+        let mut continuation = ContinuationFrame::new(&fragment[2..], 1);
+        continuation.set_flag(ContinuationFlag::EndHeaders);
+        tester.send_frame(continuation);
+
+        assert_eq!(200, tester.recv_message(1).headers.status());
+    }
+
+    /// A receiver MUST treat the receipt of any other type of frame or a frame on
+    /// a different stream as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn continuation_on_different_stream() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        let fragment = encode(&mut tester, &request_headers("GET", "/echo"));
+        tester.send_raw(&raw_frame(HEADERS, 0, 1, &fragment[..1]));
+        tester.send_raw(&raw_frame(CONTINUATION, END_HEADERS, 3, &fragment[1..]));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// A receiver MUST treat the receipt of any other type of frame
+    /// as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn data_between_headers_and_continuation() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        let fragment = encode(&mut tester, &request_headers("POST", "/echo"));
+        tester.send_raw(&raw_frame(HEADERS, 0, 1, &fragment[..1]));
+        tester.send_raw(&raw_frame(DATA, 0, 1, b"abc"));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+
+    /// A CONTINUATION frame MUST be preceded by a HEADERS, PUSH_PROMISE or CONTINUATION frame
+    /// without the END_HEADERS flag set
+    #[test]
+    fn continuation_after_end_headers() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        let fragment = encode(&mut tester, &request_headers("POST", "/echo"));
+        tester.send_raw(&raw_frame(CONTINUATION, END_HEADERS, 1, &fragment));
+        assert_connection_error(tester.recv_error(), ErrorCode::ProtocolError);
+    }
+}
+
+/// 8.2. Server Push
+mod s8_2_server_push {
+    use super::*;
+
+    /// A client cannot push. Thus, servers MUST treat the receipt of a PUSH_PROMISE frame
+    /// as a connection error of type PROTOCOL_ERROR
+    #[test]
+    fn push_promise_from_client() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_headers(1, request_headers("POST", "/echo"), false);
+        tester.send_raw(&raw_frame(PUSH_PROMISE, END_HEADERS, 1, &[0, 0, 0, 2]));
+        match tester.recv_error() {
+            PeerError::Goaway(code) => assert_eq!(ErrorCode::ProtocolError, code),
+            e => panic!("expecting GOAWAY, got {:?}", e),
+        }
+        tester.recv_eof();
+    }
+}
+
+/// RFC 7541 HPACK
+mod hpack {
+    use super::*;
+
+    /// 2.3.3. Indices strictly greater than the sum of the lengths of both tables
+    /// MUST be treated as a decoding error
+    #[test]
+    fn s2_3_3_index_out_of_bounds() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 1, &[0x80 | 0x7f, 0x40]));
+        assert_connection_error(tester.recv_error(), ErrorCode::CompressionError);
+    }
+
+    /// 6.1. The index value of 0 is not used. It MUST be treated as a decoding error
+    /// if found in an indexed header field representation
+    #[test]
+    fn s6_1_index_zero() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 1, &[0x80]));
+        assert_connection_error(tester.recv_error(), ErrorCode::CompressionError);
+    }
+
+    /// 6.3. The new maximum size MUST be lower than or equal to the limit
+    /// determined by the protocol using HPACK
+    #[test]
+    #[ignore] // size update is not validated
+    fn s6_3_table_size_update_above_limit() {
+        let server = ServerTest::new();
+        let mut tester = connect(&server);
+        // Dynamic Table Size Update to 4097, limit is 4096
+        tester.send_raw(&raw_frame(HEADERS, END_HEADERS, 1, &[0x3f, 0xe2, 0x1f]));
+        assert_connection_error(tester.recv_error(), ErrorCode::CompressionError);
+    }
+}
+
+/// Requirements checked on `Client`
+mod client {
+    use super::*;
+
+    use bytes::Bytes;
+
+    use futures::future::Future;
+
+    use httpbis::Client;
+    use httpbis::solicit::frame::data::DataFrame;
+    use httpbis::solicit::frame::ping::PingFrame;
+    use httpbis::solicit::frame::settings::SettingsFrame;
+    use httpbis::solicit::frame::settings::HttpSetting;
+
+    fn connect() -> (Client, HttpConnectionTester) {
+        init_logger();
+        let server = HttpServerTester::new();
+        let client = Client::new_plain(BIND_HOST, server.port(), Default::default()).expect("client");
+        let mut tester = server.accept();
+        tester.recv_preface();
+        (client, tester)
+    }
+
+    /// 3.5. The client connection preface starts with a sequence of 24 octets,
+    /// this sequence MUST be followed by a SETTINGS frame
+    #[test]
+    fn s3_5_preface_followed_by_settings() {
+        let (_client, mut tester) = connect();
+        let settings = tester.recv_frame_settings_set();
+        assert!(!settings.is_ack());
+    }
+
+    /// 6.5.3. Upon receiving the SETTINGS frame, the recipient MUST immediately emit
+    /// a SETTINGS frame with the ACK flag set
+    #[test]
+    fn s6_5_3_settings_acknowledged() {
+        let (_client, mut tester) = connect();
+        tester.settings_xchg();
+    }
+
+    /// 6.7. Receivers of a PING frame that does not include an ACK flag MUST send
+    /// a PING frame with the ACK flag set in response, with an identical payload
+    #[test]
+    fn s6_7_ping_acknowledged() {
+        let (_client, mut tester) = connect();
+        tester.settings_xchg();
+        tester.send_raw(&raw_frame(PING, 0, 0, &[1, 2, 3, 4, 5, 6, 7, 8]));
+        tester.recv_frame_expect(PingFrame::new_ack(0x0102030405060708));
+    }
+
+    /// 5.1.1. Streams initiated by a client MUST use odd-numbered stream identifiers,
+    /// the identifier of a newly established stream MUST be numerically greater
+    /// than all streams that the initiating endpoint has opened
+    #[test]
+    fn s5_1_1_stream_identifiers() {
+        let (client, mut tester) = connect();
+        tester.settings_xchg();
+
+        // client sends END_STREAM in a separate empty DATA frame
+        let _r1 = client.start_get("/a", "localhost");
+        tester.recv_frame_headers_check(1, false);
+        tester.recv_frame_data_check_empty_end(1);
+
+        let _r2 = client.start_get("/b", "localhost");
+        tester.recv_frame_headers_check(3, false);
+        tester.recv_frame_data_check_empty_end(3);
+    }
+
+    /// 8.1.2.3. All HTTP/2 requests MUST include exactly one valid value for the :method,
+    /// :scheme, and :path pseudo-header fields
+    #[test]
+    fn s8_1_2_3_request_pseudo_headers() {
+        let (client, mut tester) = connect();
+        tester.settings_xchg();
+
+        let _r = client.start_get("/a", "localhost");
+        let headers = tester.recv_frame_headers_check(1, false);
+        for name in &[":method", ":scheme", ":path"] {
+            assert_eq!(1, headers.0.iter().filter(|h| h.name() == name.as_bytes()).count(), "{}", name);
+        }
+    }
+
+    /// 6.9.1. A sender MUST NOT send a flow-controlled frame with a length
+    /// that exceeds the space available in either of the flow-control windows
+    #[test]
+    fn s6_9_1_initial_window_size_respected() {
+        let (client, mut tester) = connect();
+        tester.settings_xchg();
+        tester.send_recv_settings(SettingsFrame::from_settings(vec![HttpSetting::InitialWindowSize(3)]));
+
+        let r = client.start_post("/a", "localhost", Bytes::from(&b"abcde"[..])).collect();
+
+        tester.recv_frame_headers_check(1, false);
+        tester.recv_frame_expect(DataFrame::with_data(1, b"abc".to_vec()));
+
+        tester.send_window_update_stream(1, 10);
+        assert_eq!(b"de".to_vec(), tester.recv_frame_data_tail(1));
+
+        tester.send_headers(1, Headers::ok_200(), true);
+        assert_eq!(200, r.wait().expect("response").headers.status());
+    }
+}
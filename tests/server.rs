@@ -9,8 +9,13 @@ extern crate httpbis;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+#[cfg(feature = "std-future")]
+extern crate futures03;
+#[cfg(feature = "http")]
+extern crate http;
 
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
@@ -35,7 +40,10 @@ use httpbis::*;
 use httpbis::stream_part::HttpStreamPart;
 use httpbis::solicit::frame::settings::*;
 use httpbis::solicit::frame::headers::*;
+use httpbis::solicit::frame::data::DataFrame;
 use httpbis::solicit::DEFAULT_SETTINGS;
+use httpbis::solicit::connection::HttpFrameType;
+use httpbis::testing::PeerError;
 
 use std::iter::FromIterator;
 use std::net::TcpStream;
@@ -248,6 +256,32 @@ fn increase_frame_size() {
     assert_eq!(10000, tester.recv_frame_data_tail(1).len());
 }
 
+#[test]
+fn truncated_header_block() {
+    init_logger();
+
+    let server = ServerTest::new();
+
+    let mut tester = HttpConnectionTester::connect(server.port);
+    tester.send_preface();
+    tester.settings_xchg();
+
+    // HEADERS with dynamic table size update cut short after the prefix
+    tester.send_raw(&[0x00, 0x00, 0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 0x01, 0x3f]);
+
+    match tester.recv_error() {
+        PeerError::Goaway(code) => assert_eq!(ErrorCode::CompressionError, code),
+        e => panic!("expecting GOAWAY, got {:?}", e),
+    }
+    tester.recv_eof();
+
+    let mut tester = HttpConnectionTester::connect(server.port);
+    tester.send_preface();
+    tester.settings_xchg();
+
+    assert_eq!(200, tester.get(1, "/echo").headers.status());
+}
+
 #[test]
 fn exceed_window_size() {
     init_logger();
@@ -459,3 +493,676 @@ fn external_event_loop() {
 
     t.join().expect("thread join");
 }
+
+#[test]
+fn request_context() {
+    init_logger();
+
+    struct ServiceImpl {}
+
+    impl Service for ServiceImpl {
+        fn start_request(&self, _headers: Headers, _req: HttpPartStream) -> Response {
+            unreachable!()
+        }
+
+        fn start_request_with_context(&self, context: RequestContext, _headers: Headers, _req: HttpPartStream)
+            -> Response
+        {
+            let mut headers = Headers::ok_200();
+            headers.add("x-peer-addr", &format!("{}", context.peer_addr()));
+            headers.add("x-local-addr", &format!("{}", context.conn.local_addr));
+            headers.add("x-stream-id", &format!("{}", context.stream_id));
+            headers.add("x-tls", &format!("{}", context.conn.tls));
+            Response::headers_and_bytes(headers, "")
+        }
+    }
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service("/", Arc::new(ServiceImpl {}));
+    let server = server.build().expect("server");
+
+    let mut tester = HttpConnectionTester::connect(server.local_addr().port().unwrap());
+    tester.send_preface();
+    tester.settings_xchg();
+
+    tester.send_get(3, "/");
+    let headers = tester.recv_frame_headers_check(3, false);
+    assert_eq!("3", headers.get("x-stream-id"));
+    assert_eq!("false", headers.get("x-tls"));
+    assert_eq!(format!("{}", server.local_addr()), headers.get("x-local-addr"));
+    assert_eq!(tester.local_addr().to_string(), headers.get("x-peer-addr"));
+}
+
+#[test]
+fn layers() {
+    init_logger();
+
+    struct Deny {}
+
+    impl Layer for Deny {
+        fn start_request(&self, headers: Headers, req: HttpPartStream, next: service_layer::Next) -> Response {
+            if headers.path() == "/denied" {
+                return Response::headers(Headers::from_status(403));
+            }
+            next.start_request(headers, req)
+        }
+    }
+
+    let (timed_tx, timed_rx) = mpsc::channel();
+    let timed_tx = Mutex::new(timed_tx);
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/", |headers, _req| {
+        let mut resp_headers = Headers::ok_200();
+        resp_headers.add("x-echo", headers.get_opt("x-injected").unwrap_or(""));
+        Response::headers_and_bytes(resp_headers, "hello")
+    });
+    let mut headers_layer = service_layer::HeadersLayer::new();
+    headers_layer.add_request_header("x-injected", "req");
+    headers_layer.add_response_header("x-server", "test");
+    server.add_layer(service_layer::LogLayer);
+    server.add_layer(service_layer::TimingLayer::new(move |headers, _duration| {
+        timed_tx.lock().unwrap().send(headers.path().to_owned()).unwrap();
+    }));
+    server.add_layer(headers_layer);
+    server.add_layer(Deny {});
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+
+    let resp = client.start_get("/", "localhost").collect().wait().expect("ok");
+    assert_eq!(200, resp.headers.status());
+    assert_eq!("req", resp.headers.get("x-echo"));
+    assert_eq!("test", resp.headers.get("x-server"));
+    assert_eq!(b"hello", &resp.body[..]);
+
+    let resp = client.start_get("/denied", "localhost").collect().wait().expect("ok");
+    assert_eq!(403, resp.headers.status());
+    assert_eq!("test", resp.headers.get("x-server"));
+
+    // Callback is called when response stream is dropped by the server
+    let mut timed: Vec<String> = (0..2)
+        .map(|_| timed_rx.recv_timeout(::std::time::Duration::from_secs(10)).expect("timing callback"))
+        .collect();
+    timed.sort();
+    assert_eq!(vec!["/".to_owned(), "/denied".to_owned()], timed);
+}
+
+#[test]
+fn access_log() {
+    init_logger();
+
+    let (tx, rx) = mpsc::channel();
+    let tx = ::std::sync::Mutex::new(tx);
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/", |_headers, _req| {
+        Response::headers_and_bytes(Headers::ok_200(), "hello")
+    });
+    server.set_access_log(move |record| {
+        tx.lock().unwrap().send(record.clone()).unwrap();
+    });
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+    let resp = client.start_post("/foo?bar", "localhost", Bytes::from("abc")).collect().wait().expect("ok");
+    assert_eq!(200, resp.headers.status());
+
+    let record: AccessLogRecord = rx.recv().expect("recv");
+    assert_eq!(1, record.conn_id);
+    assert_eq!(1, record.stream_id);
+    assert_eq!("POST", record.method);
+    assert_eq!("/foo?bar", record.path);
+    assert_eq!("localhost", record.authority);
+    assert_eq!(Some(200), record.status);
+    assert_eq!(3, record.request_bytes);
+    assert_eq!(5, record.response_bytes);
+    assert_eq!(None, record.rst_received);
+    assert_eq!(None, record.rst_sent);
+}
+
+#[test]
+fn metrics() {
+    init_logger();
+
+    let server_metrics = Arc::new(MetricsRegistry::new());
+    let client_metrics = Arc::new(MetricsRegistry::new());
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/", |_headers, _req| {
+        Response::headers_and_bytes(Headers::ok_200(), "hello")
+    });
+    server.set_metrics(server_metrics.clone());
+    let server = server.build().expect("server");
+
+    let mut client = ClientBuilder::new_plain();
+    client.set_addr((BIND_HOST, server.local_addr().port().unwrap())).expect("set_addr");
+    client.set_metrics(client_metrics.clone());
+    let client = client.build().expect("client");
+
+    let resp = client.start_post("/foo", "localhost", Bytes::from("abc")).collect().wait().expect("ok");
+    assert_eq!(200, resp.headers.status());
+
+    let server_snapshot = server_metrics.snapshot();
+    assert_eq!(1, server_snapshot.conns_opened);
+    assert_eq!(1, server_snapshot.streams_opened);
+    assert_eq!(1, server_snapshot.handler_latency_count);
+    assert!(server_snapshot.frames_received[&HttpFrameType::Headers] >= 1);
+
+    let client_snapshot = client_metrics.snapshot();
+    assert_eq!(1, client_snapshot.conns_opened);
+    assert_eq!(1, client_snapshot.streams_opened);
+    assert!(client_snapshot.bytes_sent > 0);
+    assert!(client_snapshot.bytes_received > 0);
+
+    let text = server_metrics.to_prometheus("httpbis_server");
+    assert!(text.contains("httpbis_server_connections_opened_total 1\n"), "{}", text);
+    assert!(text.contains("httpbis_server_handler_latency_seconds_count 1\n"), "{}", text);
+}
+
+#[test]
+fn frame_tracer() {
+    init_logger();
+
+    struct Collect(Mutex<Vec<String>>);
+
+    impl FrameTracer for Collect {
+        fn frame(&self, frame: &TracedFrame) {
+            self.0.lock().unwrap().push(format_frame(frame));
+        }
+    }
+
+    let collect = Arc::new(Collect(Mutex::new(Vec::new())));
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/", |_headers, req| {
+        // read the request body first so its DATA frame is traced before the client gets a response
+        Response::new(req.filter_data().collect().map(|_| {
+            let mut headers = Headers::ok_200();
+            headers.add("set-cookie", "secret");
+            (headers, HttpPartStream::once_bytes("hello"))
+        }))
+    });
+    server.set_frame_tracer(collect.clone());
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), Default::default())
+        .expect("client");
+
+    let resp = client.start_post("/foo", "localhost", Bytes::from("abc")).collect().wait().expect("ok");
+    assert_eq!(200, resp.headers.status());
+
+    let frames = collect.0.lock().unwrap().join("");
+    assert!(frames.contains(" recv HEADERS stream=1 "), "{}", frames);
+    assert!(frames.contains("    :path: /foo\n"), "{}", frames);
+    assert!(frames.contains(" recv DATA stream=1 "), "{}", frames);
+    assert!(frames.contains(" send HEADERS stream=1 "), "{}", frames);
+    assert!(frames.contains("    set-cookie: <redacted>\n"), "{}", frames);
+    assert!(!frames.contains("secret"), "{}", frames);
+}
+
+#[test]
+fn replay_recorded_session() {
+    init_logger();
+
+    #[derive(Clone)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl ::std::io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn start_server(body: &'static str, tracer: Option<Arc<FrameTracer>>) -> Server {
+        let mut server = ServerBuilder::new_plain();
+        server.set_addr((BIND_HOST, 0)).expect("set_addr");
+        server.service.set_service_fn("/", move |_headers, _req| {
+            Response::headers_and_bytes(Headers::ok_200(), body)
+        });
+        if let Some(tracer) = tracer {
+            server.set_frame_tracer(tracer);
+        }
+        server.build().expect("server")
+    }
+
+    let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+    let capture = FrameCapture::new(buf.clone()).expect("capture");
+    let recorded_server = start_server("hello", Some(Arc::new(capture)));
+
+    let client = Client::new_plain(BIND_HOST, recorded_server.local_addr().port().unwrap(), Default::default())
+        .expect("client");
+    let resp = client.start_post("/foo", "localhost", Bytes::from("abc")).collect().wait().expect("ok");
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+
+    let recordings = Recording::read(&buf.0.lock().unwrap()[..]).expect("read");
+    assert_eq!(1, recordings.len());
+    assert_eq!(Peer::Server, recordings[0].recorded_by);
+
+    let same_server = start_server("hello", None);
+    let mut tester = HttpConnectionTester::connect(same_server.local_addr().port().unwrap());
+    tester.send_preface();
+    Replay::new(recordings[0].clone(), Peer::Client).run(&mut tester).assert_same();
+
+    let changed_server = start_server("world", None);
+    let mut tester = HttpConnectionTester::connect(changed_server.local_addr().port().unwrap());
+    tester.send_preface();
+    let diff = Replay::new(recordings[0].clone(), Peer::Client).run(&mut tester).diff().expect("diff");
+    assert!(diff.contains("-    data: b\"hello\"\n"), "{}", diff);
+    assert!(diff.contains("+    data: b\"world\"\n"), "{}", diff);
+}
+
+#[test]
+fn rst_stream_blocked_by_window() {
+    init_logger();
+
+    let server = ServerTest::new();
+
+    let mut tester = HttpConnectionTester::connect_xchg(server.port);
+    tester.send_recv_settings(SettingsFrame::from_settings(vec![HttpSetting::InitialWindowSize(3)]));
+
+    tester.send_get(1, "/blocks/5/1");
+    tester.recv_frame_headers_expect(1, &Headers::ok_200(), false);
+    tester.recv_frame_expect(DataFrame::with_data(1, vec![0u8; 3]));
+
+    tester.send_rst(1, ErrorCode::Cancel);
+
+    assert_eq!(200, tester.get(3, "/echo").headers.status());
+}
+
+#[test]
+fn proxy() {
+    init_logger();
+
+    let mut upstream = ServerBuilder::new_plain();
+    upstream.set_addr((BIND_HOST, 0)).expect("set_addr");
+    upstream.service.set_service_fn("/", |headers, _req| {
+        let mut resp_headers = Headers::ok_200();
+        resp_headers.add("connection", "x-hop");
+        resp_headers.add("x-hop", "1");
+        for name in &[":authority", "x-forwarded-for", "forwarded", "x-forwarded-host", "x-secret"] {
+            resp_headers.add(&format!("x-echo-{}", name.trim_left_matches(':')), headers.get_opt(name).unwrap_or("-"));
+        }
+        Response::headers_and_bytes(resp_headers, "hello")
+    });
+    upstream.service.set_service_fn("/reset", |_headers, _req| {
+        Response::err(Error::CodeError(ErrorCode::RefusedStream))
+    });
+    upstream.service.set_service_fn("/hang", |_headers, _req| {
+        Response::new(::futures::future::empty::<(Headers, HttpPartStream), Error>())
+    });
+    let upstream = upstream.build().expect("upstream");
+
+    let upstream_client = Client::new_plain(BIND_HOST, upstream.local_addr().port().unwrap(), ClientConf::new())
+        .expect("upstream client");
+
+    let mut proxy_service = ProxyService::new();
+    proxy_service.add_upstream(Arc::new(upstream_client), "upstream");
+    proxy_service.timeout = Some(::std::time::Duration::from_millis(200));
+
+    let mut proxy = ServerBuilder::new_plain();
+    proxy.set_addr((BIND_HOST, 0)).expect("set_addr");
+    proxy.service.set_service("/", Arc::new(proxy_service));
+    let proxy = proxy.build().expect("proxy");
+
+    let mut tester = HttpConnectionTester::connect_xchg(proxy.local_addr().port().unwrap());
+
+    let mut headers = Headers::new_get("/");
+    headers.add(":authority", "localhost");
+    headers.add("connection", "x-secret");
+    headers.add("x-secret", "password");
+    headers.add("x-forwarded-for", "10.0.0.1");
+    tester.send_headers(1, headers, true);
+    let resp = tester.recv_frame_headers_check(1, false);
+    assert_eq!(200, resp.status());
+    assert_eq!("upstream", resp.get("x-echo-authority"));
+    assert_eq!("-", resp.get("x-echo-x-secret"));
+    let peer_ip = tester.local_addr().ip();
+    assert_eq!(format!("10.0.0.1, {}", peer_ip), resp.get("x-echo-x-forwarded-for"));
+    assert_eq!(format!("for={};proto=http;host=\"localhost\"", peer_ip), resp.get("x-echo-forwarded"));
+    assert_eq!("localhost", resp.get("x-echo-x-forwarded-host"));
+    assert_eq!(None, resp.get_opt("connection"));
+    assert_eq!(None, resp.get_opt("x-hop"));
+    assert_eq!(&b"hello"[..], &tester.recv_frame_data_tail(1)[..]);
+
+    tester.send_get(3, "/reset");
+    tester.recv_rst_frame_check(3, ErrorCode::RefusedStream);
+
+    assert_eq!(504, tester.get(5, "/hang").headers.status());
+}
+
+fn proxy_to(upstream_client: Client) -> Server {
+    let mut proxy_service = ProxyService::new();
+    proxy_service.add_upstream(Arc::new(upstream_client), "upstream");
+
+    let mut proxy = ServerBuilder::new_plain();
+    proxy.set_addr((BIND_HOST, 0)).expect("set_addr");
+    proxy.service.set_service("/", Arc::new(proxy_service));
+    proxy.build().expect("proxy")
+}
+
+#[test]
+fn proxy_rst_stream_from_upstream() {
+    init_logger();
+
+    let mut upstream = ServerBuilder::new_plain();
+    upstream.set_addr((BIND_HOST, 0)).expect("set_addr");
+    upstream.service.set_service_fn("/", |_headers, _req| {
+        let parts = vec![
+            Ok(HttpStreamPart::intermediate_data(Bytes::from("a"))),
+            Err(Error::CodeError(ErrorCode::EnhanceYourCalm)),
+        ];
+        Response::headers_and_stream(Headers::ok_200(), HttpPartStream::new(stream::iter_result(parts)))
+    });
+    let upstream = upstream.build().expect("upstream");
+
+    let upstream_client = Client::new_plain(BIND_HOST, upstream.local_addr().port().unwrap(), ClientConf::new())
+        .expect("upstream client");
+    let proxy = proxy_to(upstream_client);
+
+    let mut tester = HttpConnectionTester::connect_xchg(proxy.local_addr().port().unwrap());
+
+    // upstream resets stream after response body is started
+    tester.send_get(1, "/");
+    assert_eq!(200, tester.recv_frame_headers_check(1, false).status());
+    assert_eq!(b"a".to_vec(), tester.recv_frame_data_check(1, false));
+    tester.recv_rst_frame_check(1, ErrorCode::EnhanceYourCalm);
+}
+
+#[test]
+fn proxy_rst_stream_from_downstream() {
+    init_logger();
+
+    let upstream_metrics = Arc::new(MetricsRegistry::new());
+
+    let mut upstream = ServerBuilder::new_plain();
+    upstream.set_addr((BIND_HOST, 0)).expect("set_addr");
+    // respond only after the whole request body is received
+    upstream.service.set_service_fn("/", |_headers, req| {
+        Response::new(req.filter_data().collect().map(|_| (Headers::ok_200(), HttpPartStream::empty())))
+    });
+    upstream.set_metrics(upstream_metrics.clone());
+    let upstream = upstream.build().expect("upstream");
+
+    let upstream_client = Client::new_plain(BIND_HOST, upstream.local_addr().port().unwrap(), ClientConf::new())
+        .expect("upstream client");
+    let proxy = proxy_to(upstream_client);
+
+    let mut tester = HttpConnectionTester::connect_xchg(proxy.local_addr().port().unwrap());
+
+    // reset stream while request body is forwarded
+    let mut headers = Headers::new_post("/");
+    headers.add(":authority", "localhost");
+    tester.send_headers(1, headers, false);
+    tester.send_data(1, b"a", false);
+    tester.send_rst(1, ErrorCode::EnhanceYourCalm);
+
+    let mut attempts = 0;
+    while upstream_metrics.snapshot().rst_stream_received.get(&ErrorCode::EnhanceYourCalm) != Some(&1) {
+        attempts += 1;
+        assert!(attempts < 1000, "upstream stream is not reset: {:?}", upstream_metrics.snapshot());
+        thread::sleep(::std::time::Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn proxy_upstream_unavailable() {
+    init_logger();
+
+    // Take a free port and close it, so connection is refused
+    let port = HttpServerTester::new().port();
+
+    let mut conf = ClientConf::new();
+    conf.reconnect = Some(ClientReconnectConf {
+        initial_delay: ::std::time::Duration::from_secs(3600),
+        fail_fast: true,
+        ..Default::default()
+    });
+    let upstream_client = Client::new_plain(BIND_HOST, port, conf).expect("upstream client");
+    while upstream_client.connection_state() != ClientConnectionState::TransientFailure {
+        thread::sleep(::std::time::Duration::from_millis(1));
+    }
+
+    let proxy = proxy_to(upstream_client);
+
+    let mut tester = HttpConnectionTester::connect_xchg(proxy.local_addr().port().unwrap());
+    assert_eq!(502, tester.get(1, "/").headers.status());
+}
+
+#[cfg(unix)]
+#[test]
+fn static_files() {
+    init_logger();
+
+    let tempdir = tempdir::TempDir::new("rust_http2_test").unwrap();
+    ::std::fs::create_dir(tempdir.path().join("dir")).unwrap();
+    ::std::fs::File::create(tempdir.path().join("dir/a.txt")).unwrap().write_all(b"0123456789").unwrap();
+
+    let mut static_files = StaticFiles::new(tempdir.path());
+    static_files.prefix = "/static".to_owned();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service("/static", Arc::new(static_files));
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+
+    let get = |path: &str, extra: &[(&str, &str)]| {
+        let mut headers = Headers::new_get(path);
+        headers.add(":authority", "localhost");
+        headers.add(":scheme", "http");
+        for &(name, value) in extra {
+            headers.add(name, value);
+        }
+        client.start_request_simple(headers, Bytes::new()).collect().wait().expect("ok")
+    };
+
+    let resp = get("/static/dir/a.txt", &[]);
+    assert_eq!(200, resp.headers.status());
+    assert_eq!("text/plain; charset=utf-8", resp.headers.get("content-type"));
+    assert_eq!("10", resp.headers.get("content-length"));
+    assert_eq!(&b"0123456789"[..], &resp.body[..]);
+    let etag = resp.headers.get("etag").to_owned();
+
+    let resp = get("/static/dir/a.txt", &[("range", "bytes=2-4")]);
+    assert_eq!(206, resp.headers.status());
+    assert_eq!("bytes 2-4/10", resp.headers.get("content-range"));
+    assert_eq!(&b"234"[..], &resp.body[..]);
+
+    let resp = get("/static/dir/a.txt", &[("range", "bytes=0-0,-2")]);
+    assert_eq!(206, resp.headers.status());
+    assert!(resp.headers.get("content-type").starts_with("multipart/byteranges; boundary="));
+    let body = String::from_utf8(resp.body.to_vec()).unwrap();
+    assert!(body.contains("content-range: bytes 0-0/10\r\n\r\n0\r\n"));
+    assert!(body.contains("content-range: bytes 8-9/10\r\n\r\n89\r\n"));
+    assert_eq!(format!("{}", body.len()), resp.headers.get("content-length"));
+
+    assert_eq!(416, get("/static/dir/a.txt", &[("range", "bytes=20-")]).headers.status());
+    assert_eq!(304, get("/static/dir/a.txt", &[("if-none-match", &etag)]).headers.status());
+    assert_eq!(302, get("/static/dir", &[]).headers.status());
+    assert_eq!(404, get("/static/dir/", &[]).headers.status());
+    assert_eq!(404, get("/static/dir/b.txt", &[]).headers.status());
+    assert_eq!(400, get("/static/dir/%2e%2e/%2e%2e/etc/passwd", &[]).headers.status());
+}
+
+#[test]
+fn compression() {
+    init_logger();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/", |_headers, _req| {
+        let mut headers = Headers::ok_200();
+        headers.add("content-type", "text/plain");
+        let chunks = vec![Bytes::from(vec![b'a'; 10000]), Bytes::from(vec![b'b'; 10000])];
+        Response::headers_and_bytes_stream(headers, stream::iter_ok(chunks))
+    });
+    server.service.set_service_fn("/png", |_headers, _req| {
+        let mut headers = Headers::ok_200();
+        headers.add("content-type", "image/png");
+        Response::headers_and_bytes(headers, vec![b'a'; 10000])
+    });
+    server.add_layer(service_layer::CompressionLayer::new());
+    let server = server.build().expect("server");
+    let port = server.local_addr().port().unwrap();
+
+    let plain = Client::new_plain(BIND_HOST, port, ClientConf::new()).expect("client");
+
+    let mut headers = Headers::new_get("/");
+    headers.add(":authority", "localhost");
+    headers.add(":scheme", "http");
+    headers.add("accept-encoding", "gzip");
+    let resp = plain.start_request_simple(headers, Bytes::new()).collect().wait().expect("ok");
+    assert_eq!("gzip", resp.headers.get("content-encoding"));
+    assert_eq!("accept-encoding", resp.headers.get("vary"));
+    assert!(resp.body.len() < 1000);
+
+    let resp = plain.start_get("/", "localhost").collect().wait().expect("ok");
+    assert_eq!(None, resp.headers.get_opt("content-encoding"));
+    assert_eq!(20000, resp.body.len());
+
+    let mut conf = ClientConf::new();
+    conf.decompress = true;
+    let decompressing = Client::new_plain(BIND_HOST, port, conf).expect("client");
+
+    let resp = decompressing.start_get("/", "localhost").collect().wait().expect("ok");
+    assert_eq!(None, resp.headers.get_opt("content-encoding"));
+    let mut expected = vec![b'a'; 10000];
+    expected.extend_from_slice(&[b'b'; 10000]);
+    assert_eq!(&expected[..], &resp.body[..]);
+
+    let resp = decompressing.start_get("/png", "localhost").collect().wait().expect("ok");
+    assert_eq!(None, resp.headers.get_opt("vary"));
+    assert_eq!(10000, resp.body.len());
+}
+
+#[test]
+fn grpc_echo() {
+    init_logger();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/test.Echo/Echo", |_headers, req| {
+        grpc::response(grpc::GrpcMessageStream::new(req))
+    });
+    server.service.set_service_fn("/test.Echo/Missing", |_headers, _req| {
+        grpc::response_status(grpc::GrpcStatus::new(grpc::GrpcCode::NotFound, "missing"))
+    });
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+
+    let messages = vec![Bytes::from("first"), Bytes::new(), Bytes::from(vec![b'x'; 100000])];
+    let headers = grpc::request_headers(HttpScheme::Http, "localhost", "/test.Echo/Echo");
+    let body = grpc::encode_messages(stream::iter_ok(messages.clone()));
+    let resp = client.start_request(headers, body);
+    let echoed: Vec<Bytes> = grpc::response_messages(resp).collect().wait().expect("ok");
+    assert_eq!(messages, echoed);
+
+    let headers = grpc::request_headers(HttpScheme::Http, "localhost", "/test.Echo/Missing");
+    let resp = client.start_request(headers, HttpPartStream::empty());
+    match grpc::response_messages(resp).collect().wait() {
+        Err(Error::GrpcError(ref status)) if status.code == grpc::GrpcCode::NotFound => {}
+        r => panic!("expecting NOT_FOUND: {:?}", r),
+    }
+}
+
+#[cfg(feature = "std-future")]
+#[test]
+fn std_future() {
+    use futures03::executor::block_on;
+    use futures03::future;
+    use httpbis::std_future::AsyncServiceAdapter;
+
+    init_logger();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service("/echo", Arc::new(AsyncServiceAdapter(|_headers: Headers, req: HttpPartStream| {
+        future::ready(Ok::<_, Error>((Headers::ok_200(), req)))
+    })));
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+
+    let message = block_on(client.post_async("/echo", "localhost", Bytes::from("hello"))).expect("post");
+    assert_eq!("200", message.headers.get(":status"));
+    assert_eq!(Bytes::from("hello"), message.body);
+
+    let message = block_on(client.get_async("/missing", "localhost")).expect("get");
+    assert_eq!("404", message.headers.get(":status"));
+}
+
+#[test]
+fn blocking_client() {
+    init_logger();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service_fn("/echo", |_headers, req| {
+        Response::headers_and_stream(Headers::ok_200(), req)
+    });
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+    let client = BlockingClient::new(client);
+
+    // larger than initial window, so body is sent and received in several chunks
+    let body: Vec<u8> = (0..200000).map(|i| i as u8).collect();
+    let mut resp = client.post("/echo", "localhost", std::io::Cursor::new(body.clone())).expect("post");
+    assert_eq!(200, resp.status);
+    let mut read = Vec::new();
+    resp.body.read_to_end(&mut read).expect("read");
+    assert_eq!(body, read);
+
+    let mut resp = client.get("/missing", "localhost").expect("get");
+    assert_eq!(404, resp.status);
+    resp.body.read_all().expect("read");
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http_service() {
+    init_logger();
+
+    let mut server = ServerBuilder::new_plain();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.service.set_service("/", Arc::new(HttpService::new(|req: http::Request<HttpPartStream>| {
+        let stream_id = req.extensions().get::<RequestContext>().map(|c| c.stream_id).unwrap_or(0);
+        let response = http::Response::builder()
+            .status(201)
+            .header("x-method", req.method().as_str())
+            .header("x-uri", req.uri().to_string())
+            .header("x-stream-id", format!("{}", stream_id))
+            .body(req.into_body())
+            .unwrap();
+        Response::from_http(response)
+    })));
+    let server = server.build().expect("server");
+
+    let client = Client::new_plain(BIND_HOST, server.local_addr().port().unwrap(), ClientConf::new())
+        .expect("client");
+
+    let resp = client.start_post("/a?b=c", "localhost", Bytes::from("hello")).collect().wait().expect("ok");
+    assert_eq!(201, resp.headers.status());
+    assert_eq!("POST", resp.headers.get("x-method"));
+    assert_eq!("http://localhost/a?b=c", resp.headers.get("x-uri"));
+    assert_eq!("1", resp.headers.get("x-stream-id"));
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+}
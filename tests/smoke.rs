@@ -82,6 +82,28 @@ fn smoke_unix_domain_sockets() {
     }
 }
 
+#[test]
+fn smoke_memory() {
+    init_logger();
+
+    let _server = ServerTest::new_memory("smoke_memory");
+
+    let client: Client = Client::new_plain_memory("smoke_memory", Default::default()).expect("client");
+
+    let mut futures = Vec::new();
+    for _ in 0..10 {
+        futures.push(client.start_get("/blocks/200000/5", "localhost").collect());
+    }
+
+    let r = future::join_all(futures).wait().expect("wait");
+    for rr in r {
+        assert_eq!(200000 * 5, rr.body.len());
+    }
+
+    let r = client.start_post("/echo", "localhost", Bytes::from(&b"abcd"[..])).collect().wait().expect("wait");
+    assert_eq!(&b"abcd"[..], &r.body[..]);
+}
+
 #[test]
 fn parallel_large() {
     init_logger();
@@ -1,10 +1,9 @@
 mod server_one_conn;
 mod server_test;
-mod tester;
 
 pub use self::server_one_conn::*;
 pub use self::server_test::*;
-pub use self::tester::*;
+pub use httpbis::testing::*;
 
 // Bind on IPv4 because IPv6 is broken on travis
 pub const BIND_HOST: &str = "127.0.0.1";
@@ -77,4 +77,17 @@ impl ServerTest {
             port: 0,
         }
     }
+
+    pub fn new_memory(name: &str) -> ServerTest {
+        let mut server = ServerBuilder::new_plain();
+        server.set_memory_addr(name).unwrap();
+
+        server.service.set_service("/blocks", Arc::new(Blocks {}));
+        server.service.set_service("/echo", Arc::new(Echo {}));
+        let server = server.build().expect("server");
+        ServerTest {
+            server: server,
+            port: 0,
+        }
+    }
 }
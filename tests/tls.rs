@@ -11,10 +11,16 @@ extern crate httpbis;
 extern crate log;
 extern crate env_logger;
 extern crate regex;
+#[cfg(unix)]
+extern crate tempdir;
 
 use bytes::Bytes;
 
+use std::fs;
+use std::io::Write;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use futures::future::Future;
 
@@ -23,6 +29,11 @@ use httpbis::*;
 use httpbis::message::SimpleHttpMessage;
 
 use httpbis::socket::AnySocketAddr;
+use httpbis::solicit::StreamId;
+use httpbis::client_conn::ClientConnection;
+use httpbis::client_conn::ClientConnectionCallbacks;
+
+use tokio_core::reactor;
 
 use tls_api::Certificate;
 use tls_api_native_tls::TlsAcceptor;
@@ -85,3 +96,223 @@ fn tls() {
     assert_eq!(200, resp.headers.status());
     assert_eq!(&b"hello"[..], &resp.body[..]);
 }
+
+#[test]
+fn sni() {
+    struct ServiceImpl {
+    }
+
+    impl Service for ServiceImpl {
+        fn start_request(&self, _headers: Headers, _req: HttpPartStream) -> Response {
+            unreachable!()
+        }
+
+        fn start_request_with_context(&self, context: RequestContext, _headers: Headers, _req: HttpPartStream)
+            -> Response
+        {
+            let sni = context.sni_host_name().unwrap_or("").to_owned();
+            Response::headers_and_bytes(Headers::ok_200(), Bytes::from(sni))
+        }
+    }
+
+    let mut server = ServerBuilder::new();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    // No default acceptor
+    server.add_tls_for_host("*.com", test_tls_acceptor());
+    server.service.set_service("/", Arc::new(ServiceImpl {}));
+    let server = server.build().expect("server");
+
+    let socket_addr = match server.local_addr() {
+        &AnySocketAddr::Inet(ref sock_addr) => sock_addr,
+        _ => panic!("Assumed server was an inet server")
+    };
+
+    let client: Client = Client::new_expl(
+        socket_addr,
+        ClientTlsOption::Tls("foobar.com".to_owned(), Arc::new(test_tls_connector())),
+        Default::default())
+            .expect("http client");
+
+    let resp: SimpleHttpMessage = client.start_get("/hi", "localhost").collect().wait().unwrap();
+    assert_eq!(200, resp.headers.status());
+    assert_eq!(&b"foobar.com"[..], &resp.body[..]);
+}
+
+#[test]
+fn tls_handle() {
+    struct ServiceImpl {
+    }
+
+    impl Service for ServiceImpl {
+        fn start_request(&self, _headers: Headers, _req: HttpPartStream) -> Response {
+            Response::headers_and_bytes(Headers::ok_200(), Bytes::from("hello"))
+        }
+    }
+
+    let mut server = ServerBuilder::new();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    let tls_handle = server.tls_handle();
+    server.set_tls(test_tls_acceptor());
+    server.service.set_service("/", Arc::new(ServiceImpl {}));
+    let server = server.build().expect("server");
+
+    let socket_addr = match server.local_addr() {
+        &AnySocketAddr::Inet(ref sock_addr) => sock_addr.clone(),
+        _ => panic!("Assumed server was an inet server")
+    };
+
+    let tls_client: Client = Client::new_expl(
+        &socket_addr,
+        ClientTlsOption::Tls("foobar.com".to_owned(), Arc::new(test_tls_connector())),
+        Default::default())
+            .expect("http client");
+
+    let resp: SimpleHttpMessage = tls_client.start_get("/hi", "localhost").collect().wait().unwrap();
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+
+    // New connections are plain
+    tls_handle.set(ServerTlsOption::Plain);
+
+    let plain_client = Client::new_plain(
+        &socket_addr.ip().to_string(), socket_addr.port(), Default::default())
+            .expect("http client");
+
+    let resp: SimpleHttpMessage = plain_client.start_get("/hi", "localhost").collect().wait().unwrap();
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+
+    // Existing connection is not affected
+    let resp: SimpleHttpMessage = tls_client.start_get("/hi", "localhost").collect().wait().unwrap();
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+}
+
+#[cfg(unix)]
+#[test]
+fn tls_file_watcher() {
+    struct ServiceImpl {
+    }
+
+    impl Service for ServiceImpl {
+        fn start_request(&self, _headers: Headers, _req: HttpPartStream) -> Response {
+            Response::headers_and_bytes(Headers::ok_200(), Bytes::from("hello"))
+        }
+    }
+
+    let tempdir = tempdir::TempDir::new("rust_http2_test").unwrap();
+    let pkcs12_path = tempdir.path().join("identity.p12");
+    fs::File::create(&pkcs12_path).unwrap().write_all(b"not yet").unwrap();
+
+    let mut server = ServerBuilder::new();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    let tls_handle = server.tls_handle();
+    // No acceptor for "foobar.com" until the file is loaded
+    server.add_tls_for_host("*.org", test_tls_acceptor());
+    server.service.set_service("/", Arc::new(ServiceImpl {}));
+    let server = server.build().expect("server");
+
+    let _watcher = ServerTlsFileWatcher::new(
+        tls_handle.clone(), pkcs12_path.clone(), Duration::from_millis(10), |pkcs12| {
+            Ok(TlsAcceptorBuilder::from_pkcs12(pkcs12, "mypass")?.build()?)
+        }).expect("watcher");
+
+    fs::File::create(&pkcs12_path).unwrap().write_all(include_bytes!("identity.p12")).unwrap();
+
+    let mut reloaded = false;
+    for _ in 0..500 {
+        if let ServerTlsOption::Sni(ref acceptors) = tls_handle.current() {
+            if acceptors.select(Some("foobar.com")).is_some() {
+                reloaded = true;
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(reloaded, "acceptor was not reloaded");
+
+    let socket_addr = match server.local_addr() {
+        &AnySocketAddr::Inet(ref sock_addr) => sock_addr,
+        _ => panic!("Assumed server was an inet server")
+    };
+
+    let client: Client = Client::new_expl(
+        socket_addr,
+        ClientTlsOption::Tls("foobar.com".to_owned(), Arc::new(test_tls_connector())),
+        Default::default())
+            .expect("http client");
+
+    let resp: SimpleHttpMessage = client.start_get("/hi", "localhost").collect().wait().unwrap();
+    assert_eq!(200, resp.headers.status());
+    assert_eq!(&b"hello"[..], &resp.body[..]);
+}
+
+
+/// Server which does not negotiate ALPN protocol:
+/// `test_tls_acceptor` has no ALPN protocols configured
+fn tls_server_without_alpn() -> Server {
+    struct ServiceImpl {
+    }
+
+    impl Service for ServiceImpl {
+        fn start_request(&self, _headers: Headers, _req: HttpPartStream) -> Response {
+            Response::headers_and_bytes(Headers::ok_200(), Bytes::from("hello"))
+        }
+    }
+
+    let mut server = ServerBuilder::new();
+    server.set_addr((BIND_HOST, 0)).expect("set_addr");
+    server.set_tls(test_tls_acceptor());
+    server.service.set_service("/", Arc::new(ServiceImpl {}));
+    server.build().expect("server")
+}
+
+struct NoCallbacks;
+
+impl ClientConnectionCallbacks for NoCallbacks {
+    fn goaway(&self, _stream_id: StreamId, _raw_error_code: u32) {}
+}
+
+fn connect_with_alpn(lp: &reactor::Core, server: &Server, alpn: ClientAlpn)
+    -> (ClientConnection, Box<Future<Item=(), Error=Error>>)
+{
+    let socket_addr = match server.local_addr() {
+        &AnySocketAddr::Inet(ref sock_addr) => sock_addr.clone(),
+        _ => panic!("Assumed server was an inet server")
+    };
+
+    let mut conf = ClientConf::new();
+    conf.alpn = Some(alpn);
+
+    ClientConnection::new_tls(
+        lp.handle(), "foobar.com", Arc::new(test_tls_connector()), Box::new(socket_addr), conf, NoCallbacks)
+}
+
+#[test]
+fn alpn_required() {
+    let server = tls_server_without_alpn();
+
+    let mut lp = reactor::Core::new().expect("core");
+    let (_conn, future) = connect_with_alpn(&lp, &server, ClientAlpn::Require);
+
+    match lp.run(future) {
+        Err(Error::AlpnMismatch(None)) => {}
+        r => panic!("expecting ALPN mismatch: {:?}", r),
+    }
+}
+
+#[test]
+fn alpn_permissive() {
+    let server = tls_server_without_alpn();
+
+    for alpn in vec![ClientAlpn::RejectOther, ClientAlpn::Ignore] {
+        let mut lp = reactor::Core::new().expect("core");
+        let (conn, future) = connect_with_alpn(&lp, &server, alpn.clone());
+        lp.handle().spawn(future.map_err(|e| panic!("connection failed: {:?}", e)));
+
+        let mut headers = Headers::new_get("/hi");
+        headers.add(":scheme", "https");
+        headers.add(":authority", "localhost");
+        let resp = lp.run(conn.start_request(headers, HttpPartStream::empty()).collect())
+            .expect("response");
+        assert_eq!(200, resp.headers.status(), "{:?}", alpn);
+        assert_eq!(&b"hello"[..], &resp.body[..]);
+    }
+}